@@ -0,0 +1,3 @@
+ALTER TABLE songs
+  ADD COLUMN artist   TEXT,
+  ADD COLUMN duration INTEGER; -- in seconds, NULL if the platform doesn't report it
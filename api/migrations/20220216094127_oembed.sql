@@ -0,0 +1,2 @@
+ALTER TABLE songs
+  ADD COLUMN thumbnail_url TEXT;
@@ -0,0 +1,5 @@
+CREATE TABLE local_files (
+  song_id     INTEGER PRIMARY KEY REFERENCES songs(song_id) ON DELETE CASCADE,
+  size        BIGINT NOT NULL,
+  modified_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE song_matches (
+  song_id         INTEGER NOT NULL REFERENCES songs(song_id) ON DELETE CASCADE,
+  target_platform TEXT NOT NULL,
+  matched_song_id INTEGER REFERENCES songs(song_id) ON DELETE CASCADE, -- NULL if nothing matched
+  matched_at      TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (song_id, target_platform)
+);
@@ -0,0 +1,16 @@
+CREATE TABLE import_jobs (
+  job_id               UUID PRIMARY KEY,
+  platform             TEXT NOT NULL,
+  platform_playlist_id TEXT NOT NULL,
+  status               TEXT NOT NULL, -- running/done/failed
+  pages_fetched        INTEGER NOT NULL DEFAULT 0,
+  songs_stored         INTEGER NOT NULL DEFAULT 0,
+  errors               TEXT[] NOT NULL DEFAULT '{}',
+  created_at           TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at           TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- at most one running import per playlist
+CREATE UNIQUE INDEX index__import_jobs__running
+  ON import_jobs (platform, platform_playlist_id)
+  WHERE status = 'running';
@@ -0,0 +1,9 @@
+-- the pages of playlist items as they were last fetched, for conditional requests using their etags
+CREATE TABLE playlist_pages (
+  playlist_id     INTEGER NOT NULL REFERENCES playlists(playlist_id) ON DELETE CASCADE,
+  page            INTEGER NOT NULL,
+  etag            TEXT NOT NULL,
+  next_page_token TEXT,
+  video_ids       TEXT[] NOT NULL,
+  PRIMARY KEY (playlist_id, page)
+);
@@ -0,0 +1,6 @@
+ALTER TABLE songs
+  ADD COLUMN availability    TEXT NOT NULL DEFAULT 'available', -- available/unavailable
+  ADD COLUMN last_checked_at TIMESTAMPTZ; -- NULL if it was never re-checked since it was added
+
+-- songs are re-checked least recently checked first
+CREATE INDEX index__songs__last_checked_at ON songs (platform, last_checked_at NULLS FIRST);
@@ -0,0 +1,8 @@
+-- songs.availability may now also be 'unplayable'
+CREATE TABLE playback_failures (
+  song_id     INTEGER NOT NULL REFERENCES songs(song_id) ON DELETE CASCADE,
+  error_code  INTEGER NOT NULL, -- e.g. YouTube player error codes, 101/150 mean embedding is disabled
+  reported_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX index__playback_failures__song_id ON playback_failures (song_id);
@@ -0,0 +1,9 @@
+-- songs added to or removed from playlists by refreshes, the first import of a playlist is not recorded
+CREATE TABLE playlist_changes (
+  playlist_id INTEGER NOT NULL REFERENCES playlists(playlist_id) ON DELETE CASCADE,
+  song_id     INTEGER NOT NULL REFERENCES songs(song_id) ON DELETE CASCADE,
+  change      TEXT NOT NULL, -- added/removed
+  changed_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX index__playlist_changes__playlist_id__changed_at ON playlist_changes (playlist_id, changed_at);
@@ -0,0 +1,7 @@
+-- as reported by the platform, NULL for playlists which were not refreshed since, or whose platform doesn't report it
+ALTER TABLE playlists
+  ADD COLUMN title         TEXT,
+  ADD COLUMN owner         TEXT,
+  ADD COLUMN owner_id      TEXT,
+  ADD COLUMN thumbnail_url TEXT,
+  ADD COLUMN item_count    INTEGER;
@@ -0,0 +1,14 @@
+-- the position of every song in its playlist, for ordering and keyset pagination
+ALTER TABLE playlists_songs ADD COLUMN position INTEGER;
+
+-- the original order is unknown, existing playlists keep the order they were returned in so far
+UPDATE playlists_songs SET position = positions.position
+FROM (
+  SELECT playlist_id, song_id, ROW_NUMBER() OVER (PARTITION BY playlist_id ORDER BY song_id) - 1 AS position
+  FROM playlists_songs
+) AS positions
+WHERE (playlists_songs.playlist_id, playlists_songs.song_id) = (positions.playlist_id, positions.song_id);
+
+ALTER TABLE playlists_songs ALTER COLUMN position SET NOT NULL;
+
+CREATE INDEX index__playlists_songs__playlist_id__position ON playlists_songs (playlist_id, position);
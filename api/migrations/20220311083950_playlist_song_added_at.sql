@@ -0,0 +1,11 @@
+-- when a song was added to its playlist, kept across refreshes
+ALTER TABLE playlists_songs ADD COLUMN added_at TIMESTAMPTZ;
+
+-- the first time the song was seen is the best guess for existing playlists
+UPDATE playlists_songs SET added_at = songs.added_at
+FROM songs
+WHERE playlists_songs.song_id = songs.song_id;
+
+ALTER TABLE playlists_songs
+  ALTER COLUMN added_at SET NOT NULL,
+  ALTER COLUMN added_at SET DEFAULT CURRENT_TIMESTAMP;
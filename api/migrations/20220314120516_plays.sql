@@ -0,0 +1,13 @@
+-- songs played on a channel, as reported by its player
+CREATE TABLE plays (
+  play_id    BIGSERIAL PRIMARY KEY,
+  channel    TEXT NOT NULL,
+  song_id    INTEGER NOT NULL REFERENCES songs(song_id) ON DELETE CASCADE,
+  requester  TEXT, -- NULL if nobody requested the song, e.g. when it came from a playlist
+  started_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  ended_at   TIMESTAMPTZ,
+  outcome    TEXT -- finished/skipped/error, NULL while the song is playing
+);
+
+CREATE INDEX index__plays__channel__play_id ON plays (channel, play_id);
+CREATE INDEX index__plays__song_id ON plays (song_id);
@@ -0,0 +1,7 @@
+-- per-channel configuration, channels without a row use the defaults
+CREATE TABLE channel_settings (
+  channel          TEXT PRIMARY KEY,
+  -- songs played within this many minutes, or within this many songs, can't be requested again, NULL to disable
+  cooldown_minutes INTEGER,
+  cooldown_songs   INTEGER
+);
@@ -0,0 +1,20 @@
+-- play history aggregated per channel, hour, song and requester, for the `/stats` endpoints.
+-- refreshed periodically by the API, so it lags behind `plays` by up to `SR_API_STATS_REFRESH_INTERVAL`
+CREATE MATERIALIZED VIEW channel_play_stats AS
+SELECT
+  channel,
+  date_trunc('hour', started_at) AS hour,
+  song_id,
+  COALESCE(requester, '') AS requester, -- '' if nobody requested the song, NULLs can't be compared by the refresh
+  COUNT(*) AS plays,
+  COUNT(*) FILTER (WHERE outcome = 'skipped') AS skips,
+  COUNT(*) FILTER (WHERE first_play) AS first_plays, -- plays of songs never played on the channel before
+  COALESCE(SUM(EXTRACT(EPOCH FROM ended_at - started_at)), 0)::FLOAT8 AS play_seconds
+FROM (
+  SELECT *, ROW_NUMBER() OVER (PARTITION BY channel, song_id ORDER BY play_id) = 1 AS first_play
+  FROM plays
+) AS numbered_plays
+GROUP BY channel, hour, song_id, COALESCE(requester, '');
+
+-- required for `REFRESH MATERIALIZED VIEW CONCURRENTLY`
+CREATE UNIQUE INDEX index__channel_play_stats__key ON channel_play_stats (channel, hour, song_id, requester);
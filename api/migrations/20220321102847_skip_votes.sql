@@ -0,0 +1,13 @@
+-- a vote passes once it has this many votes, or this percentage of the active chatters voted, NULL to disable.
+-- vote-skip is disabled unless at least one of them is set
+ALTER TABLE channel_settings
+  ADD COLUMN skip_votes   INTEGER,
+  ADD COLUMN skip_percent INTEGER;
+
+-- votes to skip a play, they start over with every play
+CREATE TABLE skip_votes (
+  play_id  BIGINT NOT NULL REFERENCES plays(play_id) ON DELETE CASCADE,
+  voter    TEXT NOT NULL,
+  voted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (play_id, voter)
+);
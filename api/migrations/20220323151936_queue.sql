@@ -0,0 +1,27 @@
+ALTER TABLE channel_settings
+  ADD COLUMN queue_mode  TEXT NOT NULL DEFAULT 'fifo', -- fifo/votes, how pending requests are ordered
+  ADD COLUMN max_upvotes INTEGER; -- how many pending requests a viewer can upvote at once, NULL for no limit
+
+-- songs requested on a channel
+CREATE TABLE queue_entries (
+  entry_id     BIGSERIAL PRIMARY KEY,
+  channel      TEXT NOT NULL,
+  song_id      INTEGER NOT NULL REFERENCES songs(song_id) ON DELETE CASCADE,
+  requester    TEXT NOT NULL,
+  requested_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  pinned_at    TIMESTAMPTZ, -- pinned to the top by a moderator, NULL if it isn't
+  dequeued_at  TIMESTAMPTZ -- taken from the queue by a player, NULL while the request is pending
+);
+
+-- a song can only be pending once per channel
+CREATE UNIQUE INDEX index__queue_entries__channel__song_id ON queue_entries (channel, song_id) WHERE dequeued_at IS NULL;
+
+-- viewers' upvotes of pending requests
+CREATE TABLE queue_votes (
+  entry_id BIGINT NOT NULL REFERENCES queue_entries(entry_id) ON DELETE CASCADE,
+  voter    TEXT NOT NULL,
+  voted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (entry_id, voter)
+);
+
+CREATE INDEX index__queue_votes__voter ON queue_votes (voter);
@@ -0,0 +1,18 @@
+ALTER TABLE channel_settings
+  ADD COLUMN priority_by_role BOOLEAN NOT NULL DEFAULT false, -- requests of more privileged roles are played first
+  ADD COLUMN subscriber_lane  INTEGER, -- play this many requests of subscribers and above per regular one, NULL to disable
+  ADD COLUMN vip_tokens       INTEGER; -- how many requests a VIP can move to the front per stream, NULL to disable
+
+-- user/subscriber/vip/moderator/broadcaster, the role of the requester when they requested the song
+ALTER TABLE queue_entries
+  ADD COLUMN role     TEXT NOT NULL DEFAULT 'user',
+  ADD COLUMN front_at TIMESTAMPTZ; -- moved to the front with a VIP's token, NULL if it wasn't
+
+-- the streams of a channel, VIP tokens are handed out again with every stream
+CREATE TABLE streams (
+  stream_id  BIGSERIAL PRIMARY KEY,
+  channel    TEXT NOT NULL,
+  started_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX index__streams__channel__started_at ON streams (channel, started_at);
@@ -0,0 +1,4 @@
+-- background refreshes of playlists which keep failing are retried with an exponential backoff
+ALTER TABLE playlists
+  ADD COLUMN refresh_failures INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN retry_at         TIMESTAMPTZ; -- NULL unless the last background refresh failed
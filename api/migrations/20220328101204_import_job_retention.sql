@@ -0,0 +1,10 @@
+-- songs are only stored once the whole playlist was fetched, so running and failed jobs have no count
+ALTER TABLE import_jobs
+  ALTER COLUMN songs_stored DROP NOT NULL,
+  ALTER COLUMN songs_stored DROP DEFAULT;
+UPDATE import_jobs SET songs_stored = NULL WHERE status <> 'done';
+
+-- finished jobs are deleted once they are older than the retention period
+CREATE INDEX index__import_jobs__finished
+  ON import_jobs (updated_at)
+  WHERE status <> 'running';
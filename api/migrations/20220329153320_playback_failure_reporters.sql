@@ -0,0 +1,5 @@
+-- the channel whose player reported the failure, NULL for reports from before it was recorded
+ALTER TABLE playback_failures
+  ADD COLUMN channel TEXT;
+
+CREATE INDEX index__playback_failures__song_id__channel ON playback_failures (song_id, channel, reported_at);
@@ -0,0 +1,9 @@
+-- songs which were played or requested can't be deleted, so that the history, stats and skip votes of channels are kept.
+-- songs which disappear from their platform become unavailable instead
+ALTER TABLE plays
+  DROP CONSTRAINT plays_song_id_fkey,
+  ADD CONSTRAINT plays_song_id_fkey FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE RESTRICT;
+
+ALTER TABLE queue_entries
+  DROP CONSTRAINT queue_entries_song_id_fkey,
+  ADD CONSTRAINT queue_entries_song_id_fkey FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE RESTRICT;
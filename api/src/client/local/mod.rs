@@ -0,0 +1,270 @@
+use crate::{
+  common::platform::Platform,
+  db::{local_files::LocalFileData, songs::SongData},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use lofty::prelude::*;
+use std::path::{Component, Path, PathBuf};
+
+/// File extensions which are picked up when scanning, and their content types
+const SUPPORTED_FORMATS: &[(&str, &str)] = &[
+  ("mp3", "audio/mpeg"),
+  ("flac", "audio/flac"),
+  ("ogg", "audio/ogg"),
+  ("opus", "audio/opus"),
+  ("m4a", "audio/mp4"),
+  ("wav", "audio/wav"),
+];
+
+fn content_type_of(path: &Path) -> Option<&'static str> {
+  let extension = path.extension()?.to_str()?.to_lowercase();
+  SUPPORTED_FORMATS
+    .iter()
+    .find(|(ext, _)| *ext == extension)
+    .map(|(_, content_type)| *content_type)
+}
+
+/// A directory of audio files
+#[derive(Clone)]
+pub struct LocalLibrary {
+  root: PathBuf,
+}
+
+impl LocalLibrary {
+  pub fn new(root: impl Into<PathBuf>) -> LocalLibrary {
+    Self { root: root.into() }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalFile {
+  /// Path relative to the library root, always separated by `/`
+  pub id: String,
+  pub title: String,
+  pub artist: Option<String>,
+  pub duration: Option<chrono::Duration>,
+  pub size: u64,
+  pub modified_at: DateTime<Utc>,
+}
+
+/// The result of a library scan
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scan {
+  /// Sorted by id
+  pub files: Vec<LocalFile>,
+  /// Ids of directories and files which couldn't be read, with the same format as [`LocalFile::id`].
+  ///
+  /// Files at or under them may still exist, so they must not be treated as removed.
+  pub failed: Vec<String>,
+}
+
+impl From<LocalFile> for LocalFileData {
+  fn from(v: LocalFile) -> Self {
+    let mut song = SongData::new(v.modified_at, v.id, Platform::Local, v.title);
+    if let Some(artist) = v.artist {
+      song = song.with_artist(artist);
+    }
+    if let Some(duration) = v.duration {
+      song = song.with_duration(duration);
+    }
+    LocalFileData::new(song, v.size as i64, v.modified_at)
+  }
+}
+
+impl LocalLibrary {
+  /// Resolve a song id to a path inside the library.
+  ///
+  /// Returns `None` for ids which would point outside of it.
+  pub fn path(&self, id: &str) -> Option<PathBuf> {
+    let relative = Path::new(id);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+      return None;
+    }
+    Some(self.root.join(relative))
+  }
+
+  /// Content type of the song with `id`, if it is a supported format
+  pub fn content_type(&self, id: &str) -> Option<&'static str> {
+    content_type_of(Path::new(id))
+  }
+
+  /// Recursively scan the library, reading the tags of every supported file.
+  ///
+  /// Only fails if the library root can't be read. Entries which can't be read are logged,
+  /// and reported in [`Scan::failed`] so that their files aren't mistaken for removed ones.
+  /// This does blocking IO, so it should be run on a blocking thread.
+  pub fn scan(&self) -> std::io::Result<Scan> {
+    let mut result = Scan::default();
+    let mut pending = vec![];
+    for entry in std::fs::read_dir(&self.root)? {
+      self.scan_entry(&self.root, entry, &mut pending, &mut result);
+    }
+    while let Some(dir) = pending.pop() {
+      let entries = match std::fs::read_dir(&dir) {
+        Ok(v) => v,
+        Err(e) => {
+          log::warn!("Skipping directory {}: {e}", dir.display());
+          result.failed.push(self.id_of(&dir));
+          continue;
+        }
+      };
+      for entry in entries {
+        self.scan_entry(&dir, entry, &mut pending, &mut result);
+      }
+    }
+    result.files.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(result)
+  }
+
+  fn scan_entry(
+    &self,
+    dir: &Path,
+    entry: std::io::Result<std::fs::DirEntry>,
+    pending: &mut Vec<PathBuf>,
+    result: &mut Scan,
+  ) {
+    let entry = match entry {
+      Ok(v) => v,
+      Err(e) => {
+        // the entry is unknown, so any file of its directory may be affected
+        log::warn!("Skipping entry of {}: {e}", dir.display());
+        result.failed.push(self.id_of(dir));
+        return;
+      }
+    };
+    let path = entry.path();
+    let file_type = match entry.file_type() {
+      Ok(v) => v,
+      Err(e) => {
+        log::warn!("Skipping {}: {e}", path.display());
+        result.failed.push(self.id_of(&path));
+        return;
+      }
+    };
+    if file_type.is_dir() {
+      pending.push(path);
+    } else if file_type.is_file() && content_type_of(&path).is_some() {
+      match self.read(&path) {
+        Ok(file) => result.files.push(file),
+        Err(e) => {
+          log::warn!("Skipping {}: {e}", path.display());
+          result.failed.push(self.id_of(&path));
+        }
+      }
+    }
+  }
+
+  /// The id of a path inside the library, the empty string for the root
+  fn id_of(&self, path: &Path) -> String {
+    path
+      .strip_prefix(&self.root)
+      .unwrap_or(path)
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/")
+  }
+
+  fn read(&self, path: &Path) -> std::io::Result<LocalFile> {
+    let metadata = std::fs::metadata(path)?;
+    let id = self.id_of(path);
+    // postgres stores timestamps with a lower precision, truncate so that unchanged files compare equal
+    let modified_at = Utc.timestamp(DateTime::<Utc>::from(metadata.modified()?).timestamp(), 0);
+    let fallback_title = path
+      .file_stem()
+      .map(|v| v.to_string_lossy().into_owned())
+      .unwrap_or_else(|| id.clone());
+
+    let (title, artist, duration) = match lofty::read_from_path(path) {
+      Ok(tagged) => {
+        let duration = tagged.properties().duration();
+        let tag = tagged.primary_tag().or_else(|| tagged.first_tag());
+        (
+          tag.and_then(|t| t.title().map(|v| v.into_owned())),
+          tag.and_then(|t| t.artist().map(|v| v.into_owned())),
+          Some(duration)
+            .filter(|d| !d.is_zero())
+            .and_then(|d| chrono::Duration::from_std(d).ok()),
+        )
+      }
+      Err(e) => {
+        log::warn!("Failed to read tags of {}: {e}", path.display());
+        (None, None, None)
+      }
+    };
+
+    Ok(LocalFile {
+      id,
+      title: title.unwrap_or(fallback_title),
+      artist,
+      duration,
+      size: metadata.len(),
+      modified_at,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// One second of 8-bit mono silence at 8kHz
+  fn wav() -> Vec<u8> {
+    let samples = 8000u32;
+    let mut data = vec![];
+    data.extend_from_slice(b"RIFF");
+    data.extend_from_slice(&(36 + samples).to_le_bytes());
+    data.extend_from_slice(b"WAVEfmt ");
+    data.extend_from_slice(&16u32.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    data.extend_from_slice(&1u16.to_le_bytes()); // channels
+    data.extend_from_slice(&8000u32.to_le_bytes()); // sample rate
+    data.extend_from_slice(&8000u32.to_le_bytes()); // byte rate
+    data.extend_from_slice(&1u16.to_le_bytes()); // block align
+    data.extend_from_slice(&8u16.to_le_bytes()); // bits per sample
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&samples.to_le_bytes());
+    data.resize(data.len() + samples as usize, 128);
+    data
+  }
+
+  #[test]
+  fn path_stays_inside_library() {
+    let library = LocalLibrary::new("/music");
+    assert_eq!(library.path("a/b.mp3"), Some(PathBuf::from("/music/a/b.mp3")));
+    assert_eq!(library.path("../b.mp3"), None);
+    assert_eq!(library.path("a/../../b.mp3"), None);
+    assert_eq!(library.path("/etc/passwd"), None);
+    assert_eq!(library.path("./b.mp3"), None);
+  }
+
+  #[test]
+  fn scan_directory() -> anyhow::Result<()> {
+    let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(root.join("album"))?;
+    std::fs::write(root.join("album/song.wav"), wav())?;
+    std::fs::write(root.join("cover.jpg"), b"not audio")?;
+    std::fs::write(root.join("broken.mp3"), b"not audio")?;
+
+    let files = LocalLibrary::new(&root).scan();
+    std::fs::remove_dir_all(&root)?;
+    let Scan { files, failed } = files?;
+
+    assert!(failed.is_empty());
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].id, "album/song.wav");
+    assert_eq!(files[0].title, "song");
+    assert_eq!(files[0].duration, Some(chrono::Duration::seconds(1)));
+    assert_eq!(files[1].id, "broken.mp3");
+    assert_eq!(files[1].title, "broken");
+    assert_eq!(files[1].duration, None);
+
+    Ok(())
+  }
+
+  #[test]
+  fn scan_missing_library() {
+    let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    assert!(LocalLibrary::new(&root).scan().is_err());
+  }
+}
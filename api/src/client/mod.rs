@@ -1,3 +1,11 @@
+pub mod local;
+pub mod oembed;
+pub mod soundcloud;
+pub mod spotify;
 pub mod ytv3;
 
+pub use local::LocalLibrary;
+pub use oembed::OembedApi as Oembed;
+pub use soundcloud::SoundcloudApi as Soundcloud;
+pub use spotify::SpotifyApi as Spotify;
 pub use ytv3::YoutubeApiV3 as Youtube;
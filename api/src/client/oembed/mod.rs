@@ -0,0 +1,217 @@
+mod schema;
+
+use crate::{common::platform::Platform, db::songs::SongData};
+use chrono::Utc;
+use reqwest::Url;
+
+/// An allowed oEmbed provider, parsed from `HOST=ENDPOINT`
+///
+/// URLs on `HOST` or any of its subdomains are resolved through `ENDPOINT`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provider {
+  pub host: String,
+  pub endpoint: String,
+}
+
+impl std::str::FromStr for Provider {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (host, endpoint) = s
+      .split_once('=')
+      .ok_or_else(|| format!("invalid oEmbed provider `{s}`, expected `HOST=ENDPOINT`"))?;
+    let host = host.trim().trim_start_matches("www.").to_lowercase();
+    let endpoint = endpoint.trim();
+    if host.is_empty() {
+      return Err(format!("invalid oEmbed provider `{s}`, missing host"));
+    }
+    Url::parse(endpoint).map_err(|e| format!("invalid oEmbed provider endpoint `{endpoint}`: {e}"))?;
+    Ok(Provider {
+      host,
+      endpoint: endpoint.into(),
+    })
+  }
+}
+
+impl Provider {
+  fn matches(&self, url: &Url) -> bool {
+    match url.host_str() {
+      Some(host) => {
+        let host = host.to_lowercase();
+        host == self.host || host.ends_with(&format!(".{}", self.host))
+      }
+      None => false,
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct OembedApi {
+  inner: reqwest::Client,
+  providers: Vec<Provider>,
+}
+
+impl OembedApi {
+  pub fn new(providers: Vec<Provider>) -> OembedApi {
+    Self {
+      inner: reqwest::Client::new(),
+      providers,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embed {
+  /// The canonical URL of the embedded resource
+  pub url: String,
+  pub title: String,
+  pub author: Option<String>,
+  pub thumbnail_url: Option<String>,
+  pub duration: Option<chrono::Duration>,
+}
+
+impl From<Embed> for SongData {
+  fn from(v: Embed) -> Self {
+    // oEmbed has no notion of a publish date
+    let mut data = Self::new(Utc::now(), v.url, Platform::Oembed, v.title);
+    if let Some(author) = v.author {
+      data = data.with_artist(author);
+    }
+    if let Some(thumbnail_url) = v.thumbnail_url {
+      data = data.with_thumbnail_url(thumbnail_url);
+    }
+    if let Some(duration) = v.duration {
+      data = data.with_duration(duration);
+    }
+    data
+  }
+}
+
+impl OembedApi {
+  /// Normalize `url` and find the provider it belongs to.
+  ///
+  /// Returns `None` if `url` is not a valid URL, or if its provider is not allowed.
+  pub fn provider(&self, url: &str) -> Option<(Url, &Provider)> {
+    let mut url = Url::parse(url.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+      return None;
+    }
+    url.set_fragment(None);
+    let provider = self.providers.iter().find(|p| p.matches(&url))?;
+    Some((url, provider))
+  }
+
+  /// Resolve `url` through its provider's oEmbed endpoint.
+  ///
+  /// Returns `None` if the provider is not allowed.
+  pub async fn resolve(&self, url: &str) -> reqwest::Result<Option<Embed>> {
+    let (url, provider) = match self.provider(url) {
+      Some(v) => v,
+      None => return Ok(None),
+    };
+    let embed = self
+      .inner
+      .get(&provider.endpoint)
+      .query(&[("url", url.as_str()), ("format", "json")])
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<schema::Embed>()
+      .await?;
+    Ok(Some(Embed {
+      title: embed.title.unwrap_or_else(|| url.to_string()),
+      url: url.into(),
+      author: embed.author_name,
+      thumbnail_url: embed.thumbnail_url,
+      duration: embed.duration.map(chrono::Duration::seconds),
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+  };
+
+  #[test]
+  fn parse_provider() {
+    assert_eq!(
+      "www.Vimeo.com=https://vimeo.com/api/oembed.json".parse::<Provider>(),
+      Ok(Provider {
+        host: "vimeo.com".into(),
+        endpoint: "https://vimeo.com/api/oembed.json".into()
+      })
+    );
+    assert!("vimeo.com".parse::<Provider>().is_err());
+    assert!("=https://vimeo.com/api/oembed.json".parse::<Provider>().is_err());
+    assert!("vimeo.com=not a url".parse::<Provider>().is_err());
+  }
+
+  #[test]
+  fn match_provider() {
+    let client = OembedApi::new(vec![
+      "vimeo.com=https://vimeo.com/api/oembed.json".parse().unwrap(),
+      "bandcamp.com=https://bandcamp.com/oembed".parse().unwrap(),
+    ]);
+
+    let (url, provider) = client.provider("https://vimeo.com/76979871#t=10").unwrap();
+    assert_eq!(url.as_str(), "https://vimeo.com/76979871");
+    assert_eq!(provider.host, "vimeo.com");
+    let (_, provider) = client.provider("https://artist.bandcamp.com/track/song").unwrap();
+    assert_eq!(provider.host, "bandcamp.com");
+
+    assert!(client.provider("https://notvimeo.com/76979871").is_none());
+    assert!(client.provider("https://example.com/?u=vimeo.com").is_none());
+    assert!(client.provider("ftp://vimeo.com/76979871").is_none());
+    assert!(client.provider("76979871").is_none());
+  }
+
+  #[actix_rt::test]
+  async fn resolve_allowed_url() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/oembed"))
+      .and(method("GET"))
+      .and(query_param("url", "http://localhost/video/1"))
+      .respond_with(ResponseTemplate::new(200).set_body_json(schema::Embed {
+        title: Some("Test Video".into()),
+        author_name: Some("Test Author".into()),
+        thumbnail_url: None,
+        duration: Some(62),
+      }))
+      .expect(1)
+      .named("oembed")
+      .mount(&mock)
+      .await;
+
+    let client = OembedApi::new(vec![format!("localhost={}/oembed", mock.uri()).parse().unwrap()]);
+    let embed = client.resolve("http://localhost/video/1").await?.unwrap();
+    assert_eq!(embed.url, "http://localhost/video/1");
+    assert_eq!(embed.title, "Test Video");
+    assert_eq!(embed.author.as_deref(), Some("Test Author"));
+    assert_eq!(embed.duration, Some(chrono::Duration::seconds(62)));
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn resolve_disallowed_url() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/oembed"))
+      .respond_with(ResponseTemplate::new(500))
+      .expect(0)
+      .named("oembed")
+      .mount(&mock)
+      .await;
+
+    let client = OembedApi::new(vec![format!("localhost={}/oembed", mock.uri()).parse().unwrap()]);
+    assert_eq!(client.resolve("https://example.com/video/1").await?, None);
+
+    Ok(())
+  }
+}
@@ -0,0 +1,69 @@
+/// An oEmbed response, see https://oembed.com/#section2.3
+///
+/// Only the fields we use are listed, all of them are optional in the spec.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Embed {
+  pub title: Option<String>,
+  pub author_name: Option<String>,
+  pub thumbnail_url: Option<String>,
+  /// Not part of the spec, but reported in seconds by some providers (e.g. Vimeo)
+  pub duration: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn deserialize_vimeo_embed() {
+    let data = r#"
+        {
+          "type": "video",
+          "version": "1.0",
+          "provider_name": "Vimeo",
+          "provider_url": "https://vimeo.com/",
+          "title": "Test Video",
+          "author_name": "Test Author",
+          "author_url": "https://vimeo.com/testauthor",
+          "is_plus": "0",
+          "account_type": "basic",
+          "html": "<iframe src=\"https://player.vimeo.com/video/76979871\"></iframe>",
+          "width": 640,
+          "height": 360,
+          "duration": 62,
+          "description": "",
+          "thumbnail_url": "https://i.vimeocdn.com/video/452001751-640",
+          "thumbnail_width": 640,
+          "thumbnail_height": 360,
+          "upload_date": "2013-10-15 14:08:29",
+          "video_id": 76979871,
+          "uri": "/videos/76979871"
+        }
+      "#;
+
+    assert_eq!(
+      serde_json::from_str::<Embed>(data).unwrap(),
+      Embed {
+        title: Some("Test Video".into()),
+        author_name: Some("Test Author".into()),
+        thumbnail_url: Some("https://i.vimeocdn.com/video/452001751-640".into()),
+        duration: Some(62),
+      }
+    )
+  }
+
+  #[test]
+  fn deserialize_minimal_embed() {
+    let data = r#"{ "type": "rich", "version": "1.0" }"#;
+
+    assert_eq!(
+      serde_json::from_str::<Embed>(data).unwrap(),
+      Embed {
+        title: None,
+        author_name: None,
+        thumbnail_url: None,
+        duration: None,
+      }
+    )
+  }
+}
@@ -0,0 +1,364 @@
+mod schema;
+
+use crate::{common::platform::Platform, db::songs::SongData};
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use std::collections::HashMap;
+
+/// The `/tracks` endpoint accepts at most this many ids per request
+const MAX_TRACKS_PER_REQUEST: usize = 50;
+
+#[derive(Clone)]
+pub struct SoundcloudApi {
+  inner: reqwest::Client,
+  base_url: String,
+  client_id: Secret<String>,
+}
+
+impl SoundcloudApi {
+  pub fn new(base_url: impl Into<String>, client_id: Secret<String>) -> SoundcloudApi {
+    Self {
+      inner: reqwest::Client::new(),
+      base_url: base_url.into(),
+      client_id,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+  pub id: String,
+  pub title: String,
+  pub uploader: String,
+  pub duration: chrono::Duration,
+  pub published_at: DateTime<Utc>,
+}
+
+impl From<Track> for SongData {
+  fn from(v: Track) -> Self {
+    Self::new(v.published_at, v.id, Platform::Soundcloud, v.title)
+      .with_artist(v.uploader)
+      .with_duration(v.duration)
+  }
+}
+
+impl From<schema::Track> for Track {
+  fn from(v: schema::Track) -> Self {
+    Self {
+      id: v.id.to_string(),
+      title: v.title,
+      uploader: v.user.username,
+      duration: chrono::Duration::milliseconds(v.duration),
+      published_at: v.created_at,
+    }
+  }
+}
+
+/// A resource pointed to by a SoundCloud URL
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolved {
+  Track(Track),
+  /// A set, identified by its stable id
+  Playlist(String),
+}
+
+/// Stable SoundCloud ids are numeric, anything else is treated as a URL.
+pub fn is_id(v: &str) -> bool {
+  !v.is_empty() && v.bytes().all(|c| c.is_ascii_digit())
+}
+
+impl SoundcloudApi {
+  /// Resolve a track or set URL, returns `None` for any other kind of resource
+  pub async fn resolve(&self, url: &str) -> reqwest::Result<Option<Resolved>> {
+    let resource = self
+      .inner
+      .get(format!("{}/resolve", self.base_url))
+      .query(&[("client_id", self.client_id.expose_secret().as_str()), ("url", url)])
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<schema::Resource>()
+      .await?;
+    Ok(match resource {
+      schema::Resource::Track(track) => Some(Resolved::Track(track.into())),
+      schema::Resource::Playlist(playlist) => Some(Resolved::Playlist(playlist.id.to_string())),
+      schema::Resource::Other => None,
+    })
+  }
+
+  /// Fetch a single track by its id or URL
+  pub async fn track(&self, id_or_url: &str) -> reqwest::Result<Option<Track>> {
+    if is_id(id_or_url) {
+      Ok(self.tracks([id_or_url]).await?.into_iter().next())
+    } else {
+      Ok(match self.resolve(id_or_url).await? {
+        Some(Resolved::Track(track)) => Some(track),
+        _ => None,
+      })
+    }
+  }
+
+  /// Get the stable id of a set from its id or URL
+  pub async fn playlist_id(&self, id_or_url: &str) -> reqwest::Result<Option<String>> {
+    if is_id(id_or_url) {
+      Ok(Some(id_or_url.into()))
+    } else {
+      Ok(match self.resolve(id_or_url).await? {
+        Some(Resolved::Playlist(id)) => Some(id),
+        _ => None,
+      })
+    }
+  }
+
+  /// Fetch tracks by id. Unknown ids are omitted from the result.
+  pub async fn tracks(&self, ids: impl IntoIterator<Item = &str>) -> reqwest::Result<Vec<Track>> {
+    let ids = ids.into_iter().collect::<Vec<_>>();
+    let mut result = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(MAX_TRACKS_PER_REQUEST) {
+      let tracks = self
+        .inner
+        .get(format!("{}/tracks", self.base_url))
+        .query(&[
+          ("client_id", self.client_id.expose_secret().as_str()),
+          ("ids", chunk.join(",").as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Vec<schema::Track>>()
+        .await?;
+      result.extend(tracks.into_iter().map(Track::from));
+    }
+    Ok(result)
+  }
+
+  /// Search for tracks matching `query`, in order of relevance
+  pub async fn search(&self, query: &str, limit: u32) -> reqwest::Result<Vec<Track>> {
+    Ok(
+      self
+        .inner
+        .get(format!("{}/search/tracks", self.base_url))
+        .query(&[("client_id", self.client_id.expose_secret().as_str()), ("q", query)])
+        .query(&[("limit", limit)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<schema::TrackSearch>()
+        .await?
+        .collection
+        .into_iter()
+        .map(Track::from)
+        .collect(),
+    )
+  }
+
+  /// Fetch every track in a set, in set order
+  pub async fn playlist_tracks(&self, playlist_id: &str) -> reqwest::Result<Vec<Track>> {
+    let playlist = self
+      .inner
+      .get(format!("{}/playlists/{}", self.base_url, playlist_id))
+      .query(&[("client_id", self.client_id.expose_secret().as_str())])
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<schema::Playlist>()
+      .await?;
+
+    // fill in the tracks which were not returned in full
+    let missing = playlist
+      .tracks
+      .iter()
+      .filter_map(|track| match track {
+        schema::PlaylistTrack::Stub(stub) => Some(stub.id.to_string()),
+        schema::PlaylistTrack::Full(_) => None,
+      })
+      .collect::<Vec<_>>();
+    let mut fetched = self
+      .tracks(missing.iter().map(|id| id.as_str()))
+      .await?
+      .into_iter()
+      .map(|track| (track.id.clone(), track))
+      .collect::<HashMap<_, _>>();
+
+    Ok(
+      playlist
+        .tracks
+        .into_iter()
+        .filter_map(|track| match track {
+          schema::PlaylistTrack::Full(track) => Some(Track::from(track)),
+          schema::PlaylistTrack::Stub(stub) => fetched.remove(&stub.id.to_string()),
+        })
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, MockServer, Request, ResponseTemplate,
+  };
+
+  fn track(id: u64) -> schema::Track {
+    schema::Track {
+      id,
+      title: format!("{id} title"),
+      duration: 180_000,
+      created_at: Utc::now(),
+      user: schema::User {
+        username: "test".into(),
+      },
+    }
+  }
+
+  fn tracks_response(r: &Request) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(
+      r.url
+        .query_pairs()
+        .filter_map(|(k, v)| if k == "ids" { Some(v.into_owned()) } else { None })
+        .flat_map(|ids| ids.split(',').map(|id| id.parse::<u64>().unwrap()).collect::<Vec<_>>())
+        .map(track)
+        .collect::<Vec<_>>(),
+    )
+  }
+
+  #[actix_rt::test]
+  async fn resolve_track_url() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/resolve"))
+      .and(method("GET"))
+      .and(query_param("url", "https://soundcloud.com/test/track"))
+      .respond_with(ResponseTemplate::new(200).set_body_json(schema::Resource::Track(track(10))))
+      .expect(1)
+      .named("resolve")
+      .mount(&mock)
+      .await;
+
+    let client = SoundcloudApi::new(mock.uri(), Secret::new("test".into()));
+    let track = client.track("https://soundcloud.com/test/track").await?.unwrap();
+    assert_eq!(track.id, "10");
+    assert_eq!(track.uploader, "test");
+    assert_eq!(track.duration, chrono::Duration::seconds(180));
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn track_by_id_skips_resolve() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/resolve"))
+      .respond_with(ResponseTemplate::new(500))
+      .expect(0)
+      .named("resolve")
+      .mount(&mock)
+      .await;
+    Mock::given(path("/tracks"))
+      .and(method("GET"))
+      .respond_with(tracks_response)
+      .expect(1)
+      .named("tracks")
+      .mount(&mock)
+      .await;
+
+    let client = SoundcloudApi::new(mock.uri(), Secret::new("test".into()));
+    assert_eq!(client.track("10").await?.unwrap().id, "10");
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn resolve_playlist_url() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/resolve"))
+      .and(method("GET"))
+      .respond_with(
+        ResponseTemplate::new(200).set_body_json(schema::Resource::Playlist(schema::Playlist {
+          id: 5,
+          title: "test".into(),
+          tracks: vec![],
+        })),
+      )
+      .expect(2)
+      .named("resolve")
+      .mount(&mock)
+      .await;
+
+    let client = SoundcloudApi::new(mock.uri(), Secret::new("test".into()));
+    assert_eq!(
+      client.playlist_id("https://soundcloud.com/test/sets/test").await?,
+      Some("5".into())
+    );
+    assert_eq!(client.track("https://soundcloud.com/test/sets/test").await?, None);
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn playlist_fills_in_stubs_in_order() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/playlists/5"))
+      .and(method("GET"))
+      .respond_with(
+        ResponseTemplate::new(200).set_body_json(schema::Playlist {
+          id: 5,
+          title: "test".into(),
+          tracks: (0..120)
+            .map(|i| {
+              if i < 5 {
+                schema::PlaylistTrack::Full(track(i))
+              } else {
+                schema::PlaylistTrack::Stub(schema::TrackStub { id: i })
+              }
+            })
+            .collect(),
+        }),
+      )
+      .expect(1)
+      .named("playlist")
+      .mount(&mock)
+      .await;
+    Mock::given(path("/tracks"))
+      .and(method("GET"))
+      .respond_with(tracks_response)
+      .expect(3)
+      .named("tracks")
+      .mount(&mock)
+      .await;
+
+    let client = SoundcloudApi::new(mock.uri(), Secret::new("test".into()));
+    let tracks = client.playlist_tracks("5").await?;
+    assert_eq!(tracks.len(), 120);
+    assert!(tracks.iter().enumerate().all(|(i, t)| t.id == i.to_string()));
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn search_tracks() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/search/tracks"))
+      .and(method("GET"))
+      .and(query_param("q", "test query"))
+      .respond_with(ResponseTemplate::new(200).set_body_json(schema::TrackSearch {
+        collection: vec![track(1), track(2)],
+      }))
+      .expect(1)
+      .named("search")
+      .mount(&mock)
+      .await;
+
+    let client = SoundcloudApi::new(mock.uri(), Secret::new("test".into()));
+    let tracks = client.search("test query", 10).await?;
+    assert_eq!(tracks.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec!["1", "2"]);
+
+    Ok(())
+  }
+}
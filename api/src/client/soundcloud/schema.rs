@@ -0,0 +1,158 @@
+use chrono::{DateTime, Utc};
+
+/// Response of `/resolve`, which may be any kind of resource
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Resource {
+  Track(Track),
+  Playlist(Playlist),
+  #[serde(other)]
+  Other,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Track {
+  pub id: u64,
+  pub title: String,
+  /// Duration in milliseconds
+  pub duration: i64,
+  pub created_at: DateTime<Utc>,
+  pub user: User,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct User {
+  pub username: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Playlist {
+  pub id: u64,
+  pub title: String,
+  pub tracks: Vec<PlaylistTrack>,
+}
+
+/// Only the first few tracks of a playlist are returned in full,
+/// the rest only contain their `id` and have to be fetched separately.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum PlaylistTrack {
+  Full(Track),
+  Stub(TrackStub),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct TrackStub {
+  pub id: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct TrackSearch {
+  pub collection: Vec<Track>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn deserialize_resolved_track() {
+    let data = r#"
+        {
+          "artwork_url": "https://i1.sndcdn.com/artworks-000123456789-abcdef-large.jpg",
+          "comment_count": 42,
+          "created_at": "2019-03-08T17:04:21Z",
+          "duration": 215373,
+          "full_duration": 215373,
+          "genre": "Electronic",
+          "id": 587465124,
+          "kind": "track",
+          "permalink": "test-track",
+          "permalink_url": "https://soundcloud.com/test-user/test-track",
+          "public": true,
+          "title": "Test Track",
+          "user": {
+            "avatar_url": "https://i1.sndcdn.com/avatars-000123456789-abcdef-large.jpg",
+            "id": 123456,
+            "kind": "user",
+            "permalink": "test-user",
+            "username": "Test User"
+          }
+        }
+      "#;
+
+    assert_eq!(
+      serde_json::from_str::<Resource>(data).unwrap(),
+      Resource::Track(Track {
+        id: 587465124,
+        title: "Test Track".into(),
+        duration: 215373,
+        created_at: DateTime::<Utc>::from(DateTime::parse_from_rfc3339("2019-03-08T17:04:21Z").unwrap()),
+        user: User {
+          username: "Test User".into()
+        }
+      })
+    )
+  }
+
+  #[test]
+  fn deserialize_resolved_playlist() {
+    let data = r#"
+        {
+          "created_at": "2020-05-14T10:21:41Z",
+          "duration": 430746,
+          "id": 1045221943,
+          "kind": "playlist",
+          "permalink_url": "https://soundcloud.com/test-user/sets/test-set",
+          "title": "Test Set",
+          "track_count": 2,
+          "tracks": [
+            {
+              "created_at": "2019-03-08T17:04:21Z",
+              "duration": 215373,
+              "id": 587465124,
+              "kind": "track",
+              "title": "Test Track",
+              "user": {
+                "id": 123456,
+                "kind": "user",
+                "username": "Test User"
+              }
+            },
+            {
+              "id": 587465125,
+              "kind": "track",
+              "monetization_model": "NOT_APPLICABLE",
+              "policy": "ALLOW"
+            }
+          ]
+        }
+      "#;
+
+    assert_eq!(
+      serde_json::from_str::<Resource>(data).unwrap(),
+      Resource::Playlist(Playlist {
+        id: 1045221943,
+        title: "Test Set".into(),
+        tracks: vec![
+          PlaylistTrack::Full(Track {
+            id: 587465124,
+            title: "Test Track".into(),
+            duration: 215373,
+            created_at: DateTime::<Utc>::from(DateTime::parse_from_rfc3339("2019-03-08T17:04:21Z").unwrap()),
+            user: User {
+              username: "Test User".into()
+            }
+          }),
+          PlaylistTrack::Stub(TrackStub { id: 587465125 })
+        ]
+      })
+    )
+  }
+
+  #[test]
+  fn deserialize_unknown_resource() {
+    let data = r#"{ "id": 123456, "kind": "user", "username": "Test User" }"#;
+    assert_eq!(serde_json::from_str::<Resource>(data).unwrap(), Resource::Other);
+  }
+}
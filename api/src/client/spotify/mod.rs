@@ -0,0 +1,353 @@
+mod schema;
+
+use crate::{common::platform::Platform, db::songs::SongData};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use secrecy::{ExposeSecret, Secret};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The `/tracks` endpoint accepts at most this many ids per request
+const MAX_TRACKS_PER_REQUEST: usize = 50;
+
+/// Tokens are renewed this long before they expire, so that they don't expire mid-request
+const TOKEN_EXPIRY_MARGIN_SECS: i64 = 60;
+
+#[derive(Clone)]
+pub struct SpotifyApi {
+  inner: reqwest::Client,
+  base_url: String,
+  accounts_url: String,
+  client_id: String,
+  client_secret: Secret<String>,
+  token: Arc<Mutex<Option<AccessToken>>>,
+}
+
+/// Access token of the client credentials flow
+#[derive(Clone)]
+struct AccessToken {
+  value: Secret<String>,
+  expires_at: DateTime<Utc>,
+}
+
+impl SpotifyApi {
+  pub fn new(
+    base_url: impl Into<String>,
+    accounts_url: impl Into<String>,
+    client_id: impl Into<String>,
+    client_secret: Secret<String>,
+  ) -> SpotifyApi {
+    Self {
+      inner: reqwest::Client::new(),
+      base_url: base_url.into(),
+      accounts_url: accounts_url.into(),
+      client_id: client_id.into(),
+      client_secret,
+      token: Arc::new(Mutex::new(None)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+  pub id: String,
+  pub title: String,
+  pub artist: Option<String>,
+  pub duration: chrono::Duration,
+  pub released_at: DateTime<Utc>,
+}
+
+impl From<Track> for SongData {
+  fn from(v: Track) -> Self {
+    let song = SongData::new(v.released_at, v.id, Platform::Spotify, v.title).with_duration(v.duration);
+    match v.artist {
+      Some(artist) => song.with_artist(artist),
+      None => song,
+    }
+  }
+}
+
+/// Parse a release date of any precision, imprecise dates refer to the start of the year or month
+fn release_date(v: &str) -> Option<DateTime<Utc>> {
+  let mut parts = v.splitn(3, '-').map(|part| part.parse::<u32>().ok());
+  let year = parts.next()??;
+  let month = parts.next().unwrap_or(Some(1))?;
+  let day = parts.next().unwrap_or(Some(1))?;
+  let date = NaiveDate::from_ymd_opt(year as i32, month, day)?;
+  Some(Utc.from_utc_datetime(&date.and_hms(0, 0, 0)))
+}
+
+impl From<schema::Track> for Track {
+  fn from(v: schema::Track) -> Self {
+    Self {
+      id: v.id,
+      title: v.name,
+      artist: Some(
+        v.artists
+          .into_iter()
+          .map(|artist| artist.name)
+          .collect::<Vec<_>>()
+          .join(", "),
+      )
+      .filter(|artist| !artist.is_empty()),
+      duration: chrono::Duration::milliseconds(v.duration_ms),
+      released_at: release_date(&v.album.release_date).unwrap_or_else(|| Utc.timestamp(0, 0)),
+    }
+  }
+}
+
+fn is_track_id(v: &str) -> bool {
+  v.len() == 22 && v.bytes().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Extract the track id from a track URL or URI, ids are returned as they are.
+///
+/// Supports `open.spotify.com/track/ID`, including localized `/intl-XX/track/ID` URLs, and `spotify:track:ID`.
+pub fn track_id(id_or_url: &str) -> Option<String> {
+  let id_or_url = id_or_url.trim();
+  if is_track_id(id_or_url) {
+    return Some(id_or_url.into());
+  }
+  if let Some(id) = id_or_url.strip_prefix("spotify:track:") {
+    return Some(id.to_string()).filter(|id| is_track_id(id));
+  }
+  let url = reqwest::Url::parse(id_or_url).ok()?;
+  if url.host_str()? != "open.spotify.com" {
+    return None;
+  }
+  let mut segments = url.path_segments()?.skip_while(|segment| segment.starts_with("intl-"));
+  let id = match segments.next() {
+    Some("track") => segments.next()?.to_string(),
+    _ => return None,
+  };
+  Some(id).filter(|id| is_track_id(id))
+}
+
+impl SpotifyApi {
+  /// Get an access token, requesting a new one if there is none or it is about to expire
+  async fn token(&self) -> reqwest::Result<Secret<String>> {
+    let mut token = self.token.lock().await;
+    if let Some(token) = token.as_ref().filter(|token| token.expires_at > Utc::now()) {
+      return Ok(token.value.clone());
+    }
+    let response = self
+      .inner
+      .post(format!("{}/api/token", self.accounts_url))
+      .basic_auth(&self.client_id, Some(self.client_secret.expose_secret()))
+      .form(&[("grant_type", "client_credentials")])
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<schema::Token>()
+      .await?;
+    let value = Secret::new(response.access_token);
+    let expires_at = Utc::now() + chrono::Duration::seconds(response.expires_in - TOKEN_EXPIRY_MARGIN_SECS);
+    *token = Some(AccessToken {
+      value: value.clone(),
+      expires_at,
+    });
+    Ok(value)
+  }
+
+  /// Fetch a single track by its id
+  pub async fn track(&self, id: &str) -> reqwest::Result<Option<Track>> {
+    Ok(self.tracks([id]).await?.into_iter().next())
+  }
+
+  /// Fetch tracks by id. Unknown ids are omitted from the result.
+  pub async fn tracks(&self, ids: impl IntoIterator<Item = &str>) -> reqwest::Result<Vec<Track>> {
+    let ids = ids.into_iter().collect::<Vec<_>>();
+    let mut result = Vec::with_capacity(ids.len());
+    for chunk in ids.chunks(MAX_TRACKS_PER_REQUEST) {
+      let token = self.token().await?;
+      let tracks = self
+        .inner
+        .get(format!("{}/tracks", self.base_url))
+        .bearer_auth(token.expose_secret())
+        .query(&[("ids", chunk.join(","))])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<schema::Tracks>()
+        .await?;
+      result.extend(tracks.tracks.into_iter().flatten().map(Track::from));
+    }
+    Ok(result)
+  }
+
+  /// Search for tracks matching `query`, in order of relevance
+  pub async fn search(&self, query: &str, limit: u32) -> reqwest::Result<Vec<Track>> {
+    let token = self.token().await?;
+    Ok(
+      self
+        .inner
+        .get(format!("{}/search", self.base_url))
+        .bearer_auth(token.expose_secret())
+        .query(&[("q", query), ("type", "track")])
+        .query(&[("limit", limit)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<schema::Search>()
+        .await?
+        .tracks
+        .items
+        .into_iter()
+        .map(Track::from)
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use wiremock::{
+    matchers::{header, method, path, query_param},
+    Mock, MockServer, Request, ResponseTemplate,
+  };
+
+  fn track(id: &str) -> schema::Track {
+    schema::Track {
+      id: id.into(),
+      name: format!("{id} title"),
+      duration_ms: 180_000,
+      artists: vec![
+        schema::Artist { name: "first".into() },
+        schema::Artist { name: "second".into() },
+      ],
+      album: schema::Album {
+        release_date: "2020-05".into(),
+      },
+    }
+  }
+
+  fn tracks_response(r: &Request) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(schema::Tracks {
+      tracks: r
+        .url
+        .query_pairs()
+        .filter_map(|(k, v)| if k == "ids" { Some(v.into_owned()) } else { None })
+        .flat_map(|ids| ids.split(',').map(String::from).collect::<Vec<_>>())
+        .map(|id| if id.starts_with('x') { None } else { Some(track(&id)) })
+        .collect(),
+    })
+  }
+
+  async fn mount_token(mock: &MockServer, expect: u64) {
+    Mock::given(path("/api/token"))
+      .and(method("POST"))
+      .and(header("authorization", "Basic aWQ6c2VjcmV0"))
+      .respond_with(ResponseTemplate::new(200).set_body_json(schema::Token {
+        access_token: "token".into(),
+        expires_in: 3600,
+      }))
+      .expect(expect)
+      .named("token")
+      .mount(mock)
+      .await;
+  }
+
+  fn client(mock: &MockServer) -> SpotifyApi {
+    SpotifyApi::new(mock.uri(), mock.uri(), "id", Secret::new("secret".into()))
+  }
+
+  #[test]
+  fn parse_track_ids() {
+    for v in [
+      "4uLU6hMCjMI75M1A2tKUQC",
+      "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+      "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+      "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC?si=abc",
+      "https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC",
+    ] {
+      assert_eq!(track_id(v).as_deref(), Some("4uLU6hMCjMI75M1A2tKUQC"), "{v}");
+    }
+    for v in [
+      "",
+      "4uLU6hMCjMI75M1A2tKUQ",
+      "spotify:album:4uLU6hMCjMI75M1A2tKUQC",
+      "https://open.spotify.com/album/4uLU6hMCjMI75M1A2tKUQC",
+      "https://example.com/track/4uLU6hMCjMI75M1A2tKUQC",
+    ] {
+      assert_eq!(track_id(v), None, "{v}");
+    }
+  }
+
+  #[test]
+  fn parse_release_dates() {
+    assert_eq!(release_date("2020"), Some(Utc.ymd(2020, 1, 1).and_hms(0, 0, 0)));
+    assert_eq!(release_date("2020-05"), Some(Utc.ymd(2020, 5, 1).and_hms(0, 0, 0)));
+    assert_eq!(release_date("2020-05-17"), Some(Utc.ymd(2020, 5, 17).and_hms(0, 0, 0)));
+    assert_eq!(release_date("0000"), Some(Utc.ymd(0, 1, 1).and_hms(0, 0, 0)));
+    assert_eq!(release_date("2020-13"), None);
+    assert_eq!(release_date(""), None);
+  }
+
+  #[actix_rt::test]
+  async fn tracks_reuse_token_and_skip_unknown() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+    mount_token(&mock, 1).await;
+    Mock::given(path("/tracks"))
+      .and(method("GET"))
+      .and(header("authorization", "Bearer token"))
+      .respond_with(tracks_response)
+      .expect(3)
+      .named("tracks")
+      .mount(&mock)
+      .await;
+
+    let client = client(&mock);
+    let ids = (0..60)
+      .map(|i| if i == 7 { "x".into() } else { i.to_string() })
+      .collect::<Vec<_>>();
+    let tracks = client.tracks(ids.iter().map(|id| id.as_str())).await?;
+    assert_eq!(tracks.len(), 59);
+    assert_eq!(tracks[0].artist.as_deref(), Some("first, second"));
+    assert_eq!(tracks[0].duration, chrono::Duration::seconds(180));
+    assert_eq!(client.track("x").await?, None);
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn search_tracks() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+    mount_token(&mock, 1).await;
+    Mock::given(path("/search"))
+      .and(method("GET"))
+      .and(query_param("q", "artist title"))
+      .and(query_param("type", "track"))
+      .and(query_param("limit", "10"))
+      .respond_with(ResponseTemplate::new(200).set_body_json(schema::Search {
+        tracks: schema::Page {
+          items: vec![track("a"), track("b")],
+        },
+      }))
+      .expect(1)
+      .named("search")
+      .mount(&mock)
+      .await;
+
+    let tracks = client(&mock).search("artist title", 10).await?;
+    assert_eq!(tracks.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn search_fails_on_error_status() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+    mount_token(&mock, 1).await;
+    Mock::given(path("/search"))
+      .respond_with(ResponseTemplate::new(429))
+      .expect(1)
+      .named("search")
+      .mount(&mock)
+      .await;
+
+    assert!(client(&mock).search("artist title", 10).await.is_err());
+
+    Ok(())
+  }
+}
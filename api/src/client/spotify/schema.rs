@@ -0,0 +1,71 @@
+/// Response of the client credentials flow
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Token {
+  pub access_token: String,
+  /// Lifetime of the token in seconds
+  pub expires_in: i64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Track {
+  pub id: String,
+  pub name: String,
+  pub duration_ms: i64,
+  pub artists: Vec<Artist>,
+  pub album: Album,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Artist {
+  pub name: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Album {
+  /// `YYYY`, `YYYY-MM` or `YYYY-MM-DD`, depending on how precisely the date is known
+  pub release_date: String,
+}
+
+/// Response of `/tracks`, unknown ids are `null`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Tracks {
+  pub tracks: Vec<Option<Track>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Search {
+  pub tracks: Page,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Page {
+  pub items: Vec<Track>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn deserialize_tracks_with_missing() {
+    let data = r#"
+    {
+      "tracks": [
+        {
+          "id": "4uLU6hMCjMI75M1A2tKUQC",
+          "name": "Never Gonna Give You Up",
+          "duration_ms": 213573,
+          "explicit": false,
+          "artists": [{ "id": "0gxyHStUsqpMadRV0Di1Qt", "name": "Rick Astley" }],
+          "album": { "name": "Whenever You Need Somebody", "release_date": "1987-11-12" }
+        },
+        null
+      ]
+    }
+    "#;
+    let tracks = serde_json::from_str::<Tracks>(data).unwrap();
+    assert_eq!(tracks.tracks.len(), 2);
+    assert_eq!(tracks.tracks[0].as_ref().unwrap().artists[0].name, "Rick Astley");
+    assert!(tracks.tracks[1].is_none());
+  }
+}
@@ -4,10 +4,24 @@ mod schema;
 
 use crate::{
   common::{platform::Platform, util::query_ext::QueryExt},
-  db::songs::SongData,
+  db::{
+    playlists::{PlaylistMetadata, PlaylistPage},
+    songs::SongData,
+  },
 };
 use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt};
 use secrecy::{ExposeSecret, Secret};
+use std::collections::{HashMap, VecDeque};
+
+/// How many pages of a playlist have their videos looked up at once
+const MAX_CONCURRENT_LOOKUPS: usize = 4;
+
+/// The API rejects requests for more videos than this
+const MAX_VIDEOS_PER_REQUEST: usize = 50;
+
+/// How many chunks of a `videos` lookup are requested at once
+const MAX_CONCURRENT_CHUNKS: usize = 4;
 
 #[derive(Clone)]
 pub struct YoutubeApiV3 {
@@ -26,17 +40,71 @@ impl YoutubeApiV3 {
   }
 }
 
+/// YouTube video ids are 11 characters of URL-safe base64
+fn is_video_id(v: &str) -> bool {
+  v.len() == 11 && v.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+}
+
+/// Extract the video id from a video URL, ids are returned as they are.
+///
+/// Supports `youtu.be/ID` and `youtube.com/watch?v=ID`, as well as `/shorts/ID`, `/embed/ID` and `/live/ID`.
+pub fn video_id(id_or_url: &str) -> Option<String> {
+  let id_or_url = id_or_url.trim();
+  if is_video_id(id_or_url) {
+    return Some(id_or_url.into());
+  }
+  let url = reqwest::Url::parse(id_or_url).ok()?;
+  let host = url.host_str()?;
+  let host = host
+    .strip_prefix("www.")
+    .or_else(|| host.strip_prefix("m."))
+    .or_else(|| host.strip_prefix("music."))
+    .unwrap_or(host);
+  let mut segments = url.path_segments()?;
+  let id = match (host, segments.next()) {
+    ("youtu.be", Some(id)) => id.to_string(),
+    ("youtube.com", Some("watch")) => url.query_pairs().find(|(k, _)| k == "v")?.1.into_owned(),
+    ("youtube.com", Some("shorts" | "embed" | "live")) => segments.next()?.to_string(),
+    _ => return None,
+  };
+  Some(id).filter(|id| is_video_id(id))
+}
+
+/// The result of looking up videos by id
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoLookup {
+  /// The videos which were found, in the order of their ids
+  pub found: Vec<Video>,
+  /// The ids of videos which don't exist (anymore), or are private
+  pub missing: Vec<String>,
+}
+
+/// State of listing the pages of a playlist, see `YoutubeApiV3::playlist_pages`
+struct ListingState {
+  client: YoutubeApiV3,
+  playlist_id: String,
+  /// The remaining known pages, starting with the next one
+  known: VecDeque<PlaylistPage>,
+  page_token: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Video {
   pub id: String,
   pub title: String,
   pub channel_id: String,
+  pub channel_title: String,
   pub published_at: DateTime<Utc>,
+  pub duration: Option<chrono::Duration>,
 }
 
 impl From<Video> for SongData {
   fn from(v: Video) -> Self {
-    Self::new(v.published_at, v.id, Platform::Youtube, v.title)
+    let data = Self::new(v.published_at, v.id, Platform::Youtube, v.title).with_artist(v.channel_title);
+    match v.duration {
+      Some(duration) => data.with_duration(duration),
+      None => data,
+    }
   }
 }
 
@@ -46,73 +114,269 @@ impl From<schema::VideoListItem> for Video {
       id: v.id,
       title: v.snippet.title,
       channel_id: v.snippet.channel_id,
+      channel_title: v.snippet.channel_title,
       published_at: v.snippet.published_at,
+      duration: v
+        .content_details
+        .and_then(|details| schema::parse_duration(&details.duration)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistDetails {
+  pub id: String,
+  pub title: String,
+  pub channel_id: String,
+  pub channel_title: String,
+  pub thumbnail_url: Option<String>,
+  pub item_count: i32,
+}
+
+impl From<PlaylistDetails> for PlaylistMetadata {
+  fn from(v: PlaylistDetails) -> Self {
+    Self {
+      title: v.title,
+      owner: v.channel_title,
+      owner_id: v.channel_id,
+      thumbnail_url: v.thumbnail_url,
+      item_count: v.item_count,
+    }
+  }
+}
+
+impl From<schema::PlaylistListItem> for PlaylistDetails {
+  fn from(v: schema::PlaylistListItem) -> Self {
+    Self {
+      id: v.id,
+      title: v.snippet.title,
+      channel_id: v.snippet.channel_id,
+      channel_title: v.snippet.channel_title,
+      thumbnail_url: v.snippet.thumbnails.largest().map(|thumbnail| thumbnail.url),
+      item_count: v.content_details.item_count,
     }
   }
 }
 
 impl YoutubeApiV3 {
-  // TODO: fetch video length -> allow skipping videos longer than some configurable value
-  // TODO: fetch contentDetails.ytRating -> allow skipping or automatically hiding age-restricted videos
-  pub async fn videos(&self, ids: impl IntoIterator<Item = &str>) -> reqwest::Result<Vec<Video>> {
+  /// Look up the details of a playlist, `None` if it doesn't exist or is private
+  pub async fn playlist(&self, playlist_id: &str) -> reqwest::Result<Option<PlaylistDetails>> {
     Ok(
       self
         .inner
-        .get(format!("{}/videos", self.base_url))
-        .query(&[("key", self.api_key.expose_secret().as_str()), ("part", "snippet")])
-        .query_iter("id", ids.into_iter())
+        .get(format!("{}/playlists", self.base_url))
+        .query(&[
+          ("key", self.api_key.expose_secret().as_str()),
+          ("part", "snippet,contentDetails"),
+          ("id", playlist_id),
+        ])
         .send()
         .await?
-        .json::<schema::VideoList>()
+        .json::<schema::PlaylistList>()
         .await?
         .items
         .into_iter()
-        .map(Video::from)
-        .collect(),
+        .next()
+        .map(PlaylistDetails::from),
     )
   }
 
+  // TODO: allow skipping videos longer than some configurable value
+  // TODO: fetch contentDetails.ytRating -> allow skipping or automatically hiding age-restricted videos
+  /// Look up videos by id, in chunks of `MAX_VIDEOS_PER_REQUEST` of which up to `MAX_CONCURRENT_CHUNKS` run at once
+  pub async fn videos(&self, ids: impl IntoIterator<Item = &str>) -> reqwest::Result<VideoLookup> {
+    let ids = ids.into_iter().map(String::from).collect::<Vec<_>>();
+    let chunks = ids
+      .chunks(MAX_VIDEOS_PER_REQUEST)
+      .map(|chunk| self.clone().videos_chunk(chunk.to_vec()))
+      .collect::<Vec<_>>();
+    let lookups = futures::stream::iter(chunks).buffered(MAX_CONCURRENT_CHUNKS);
+    futures::pin_mut!(lookups);
+
+    let mut result = VideoLookup::default();
+    while let Some(lookup) = lookups.try_next().await? {
+      result.found.extend(lookup.found);
+      result.missing.extend(lookup.missing);
+    }
+    Ok(result)
+  }
+
+  async fn videos_chunk(self, ids: Vec<String>) -> reqwest::Result<VideoLookup> {
+    let videos = self
+      .inner
+      .get(format!("{}/videos", self.base_url))
+      .query(&[
+        ("key", self.api_key.expose_secret().as_str()),
+        ("part", "snippet,contentDetails"),
+      ])
+      .query_iter("id", ids.iter())
+      .send()
+      .await?
+      .json::<schema::VideoList>()
+      .await?
+      .items
+      .into_iter()
+      .map(|item| (item.id.clone(), Video::from(item)))
+      .collect::<HashMap<_, _>>();
+    // the API returns videos in no particular order, and omits the ones which don't exist
+    let mut result = VideoLookup::default();
+    for id in ids {
+      match videos.get(&id) {
+        // ids may be requested more than once
+        Some(video) => result.found.push(video.clone()),
+        None => result.missing.push(id),
+      }
+    }
+    Ok(result)
+  }
+
+  /// Search for videos matching `query`, in order of relevance
+  pub async fn search(&self, query: &str, max_results: u32) -> reqwest::Result<Vec<Video>> {
+    let results = self
+      .inner
+      .get(format!("{}/search", self.base_url))
+      .query(&[
+        ("key", self.api_key.expose_secret().as_str()),
+        ("part", "id"),
+        ("type", "video"),
+        ("q", query),
+      ])
+      .query(&[("maxResults", max_results)])
+      .send()
+      .await?
+      .error_for_status()?
+      .json::<schema::SearchList>()
+      .await?;
+    let ids = results
+      .items
+      .into_iter()
+      .filter_map(|item| item.id.video_id)
+      .collect::<Vec<_>>();
+    if ids.is_empty() {
+      return Ok(vec![]);
+    }
+    Ok(self.videos(ids.iter().map(|id| id.as_str())).await?.found)
+  }
+
   pub async fn playlist_videos(&self, playlist_id: &str) -> reqwest::Result<Vec<Video>> {
-    let mut result = vec![];
-    let mut page_token = Option::<String>::None;
-    loop {
-      // 1. fetch playlist items
-      let playlist_items = self
-        .inner
-        .get(format!("{}/playlistItems", self.base_url))
-        .query(&[
-          ("key", self.api_key.expose_secret().as_str()),
-          ("part", "contentDetails,status"),
-          ("maxResults", "50"),
-          ("playlistId", playlist_id),
-        ])
-        .query_opt("pageToken", page_token.as_ref())
-        .send()
+    Ok(
+      self
+        .playlist_pages(playlist_id, &[], || {})
         .await?
-        .json::<schema::PlaylistItemList>()
-        .await?;
-      println!("{:#?}", playlist_items.items);
-      // 2. fetch videos
-      let videos = self
-        .videos(
-          playlist_items
-            .items
-            .iter()
-            .filter(|item| item.status.privacy_status != schema::PrivacyStatus::Unspecified)
-            .map(|v| v.content_details.video_id.as_str()),
-        )
-        .await?;
-      // 3. store videos
-      result.extend_from_slice(&videos[..]);
-      // 4. paginate playlist items
-      if let Some(next_page_token) = playlist_items.next_page_token {
-        page_token = Some(next_page_token);
-      } else {
-        break;
-      }
+        .into_iter()
+        .flat_map(|(_, videos)| videos.unwrap_or_default())
+        .collect(),
+    )
+  }
+
+  /// Fetch every page of a playlist, calling `on_page` after every fetched page.
+  ///
+  /// Pages are requested conditionally using the etags of the `known` pages.
+  /// Unchanged pages are returned as they are known, without their videos, and without fetching them.
+  ///
+  /// Listing the items of a page only depends on the previous page, so the videos of up to
+  /// `MAX_CONCURRENT_LOOKUPS` pages are looked up while the following pages are being listed.
+  pub async fn playlist_pages(
+    &self,
+    playlist_id: &str,
+    known: &[PlaylistPage],
+    mut on_page: impl FnMut(),
+  ) -> reqwest::Result<Vec<(PlaylistPage, Option<Vec<Video>>)>> {
+    // 1. list playlist items, page by page
+    // the steps own their state, otherwise the compiler fails to prove that callers' futures are `Send`
+    let state = ListingState {
+      client: self.clone(),
+      playlist_id: playlist_id.to_string(),
+      known: known.iter().cloned().collect(),
+      page_token: None,
+    };
+    let listed = futures::stream::try_unfold(Some(state), Self::next_playlist_items_page);
+    // 2. fetch videos of changed pages, keeping the pages in order
+    let pages = listed
+      .map_ok(|(page, changed)| self.clone().page_videos(page, changed))
+      .try_buffered(MAX_CONCURRENT_LOOKUPS);
+    futures::pin_mut!(pages);
+
+    let mut result = vec![];
+    while let Some(page) = pages.try_next().await? {
+      result.push(page);
+      on_page();
     }
     Ok(result)
   }
+
+  /// Step of listing the pages of a playlist, `None` once the last page was listed
+  async fn next_playlist_items_page(
+    state: Option<ListingState>,
+  ) -> reqwest::Result<Option<((PlaylistPage, bool), Option<ListingState>)>> {
+    let mut state = match state {
+      Some(state) => state,
+      None => return Ok(None),
+    };
+    let known = state.known.pop_front();
+    let (page, changed) = state
+      .client
+      .playlist_items_page(&state.playlist_id, state.page_token.as_deref(), known.as_ref())
+      .await?;
+    // 3. paginate playlist items
+    let next = page.next_page_token.clone().map(|token| ListingState {
+      page_token: Some(token),
+      ..state
+    });
+    Ok(Some(((page, changed), next)))
+  }
+
+  /// Look up the videos of a page if it `changed`
+  async fn page_videos(self, page: PlaylistPage, changed: bool) -> reqwest::Result<(PlaylistPage, Option<Vec<Video>>)> {
+    let videos = match changed {
+      true => Some(self.videos(page.video_ids.iter().map(|id| id.as_str())).await?.found),
+      false => None,
+    };
+    Ok((page, videos))
+  }
+
+  /// Fetch a page of playlist items, or return `known` if it didn't change.
+  ///
+  /// Returns `true` alongside the page if it changed.
+  async fn playlist_items_page(
+    &self,
+    playlist_id: &str,
+    page_token: Option<&str>,
+    known: Option<&PlaylistPage>,
+  ) -> reqwest::Result<(PlaylistPage, bool)> {
+    let mut request = self
+      .inner
+      .get(format!("{}/playlistItems", self.base_url))
+      .query(&[
+        ("key", self.api_key.expose_secret().as_str()),
+        ("part", "contentDetails,status"),
+        ("maxResults", "50"),
+        ("playlistId", playlist_id),
+      ])
+      .query_opt("pageToken", page_token);
+    if let Some(page) = known {
+      request = request.header(reqwest::header::IF_NONE_MATCH, format!("\"{}\"", page.etag));
+    }
+    let response = request.send().await?;
+    match known {
+      Some(page) if response.status() == reqwest::StatusCode::NOT_MODIFIED => Ok((page.clone(), false)),
+      _ => {
+        let playlist_items = response.json::<schema::PlaylistItemList>().await?;
+        let video_ids = playlist_items
+          .items
+          .into_iter()
+          .filter(|item| item.status.privacy_status != schema::PrivacyStatus::Unspecified)
+          .map(|item| item.content_details.video_id)
+          .collect();
+        let page = PlaylistPage {
+          etag: playlist_items.etag,
+          next_page_token: playlist_items.next_page_token,
+          video_ids,
+        };
+        Ok((page, true))
+      }
+    }
+  }
 }
 
 #[cfg(test)]
@@ -133,15 +397,110 @@ mod tests {
         .map(|i| schema::VideoListItem {
           snippet: schema::VideoListItemSnippet {
             channel_id: "test".into(),
+            channel_title: "test".into(),
             title: format!("{i} title"),
             published_at: Utc::now(),
           },
+          content_details: Some(schema::VideoListItemContentDetails {
+            duration: "PT3M".into(),
+          }),
           id: i.into(),
         })
         .collect(),
     })
   }
 
+  #[test]
+  fn parse_video_ids() {
+    for v in [
+      "veyAhNUwmPI",
+      "https://youtu.be/veyAhNUwmPI",
+      "https://youtu.be/veyAhNUwmPI?t=42",
+      "https://www.youtube.com/watch?v=veyAhNUwmPI&list=PL0",
+      "https://m.youtube.com/watch?feature=share&v=veyAhNUwmPI",
+      "https://music.youtube.com/watch?v=veyAhNUwmPI",
+      "https://youtube.com/shorts/veyAhNUwmPI",
+      " https://www.youtube.com/embed/veyAhNUwmPI ",
+    ] {
+      assert_eq!(video_id(v).as_deref(), Some("veyAhNUwmPI"), "{v}");
+    }
+    for v in [
+      "",
+      "veyAhNUwmP",
+      "https://youtu.be/",
+      "https://www.youtube.com/watch?list=PL0",
+      "https://www.youtube.com/channel/UC0000000000",
+      "https://example.com/veyAhNUwmPI",
+    ] {
+      assert_eq!(video_id(v), None, "{v}");
+    }
+  }
+
+  #[actix_rt::test]
+  async fn videos_chunks_and_reports_missing() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    // responds in reverse order, omitting deleted videos
+    Mock::given(path("/videos"))
+      .and(method("GET"))
+      .respond_with(|r: &Request| {
+        let ids = r
+          .url
+          .query_pairs()
+          .filter(|(k, _)| k == "id")
+          .map(|(_, v)| v.into_owned())
+          .collect::<Vec<_>>();
+        assert!(ids.len() <= MAX_VIDEOS_PER_REQUEST);
+        ResponseTemplate::new(200).set_body_json(schema::VideoList {
+          items: ids
+            .into_iter()
+            .rev()
+            .filter(|id| !id.starts_with("deleted"))
+            .map(|id| schema::VideoListItem {
+              snippet: schema::VideoListItemSnippet {
+                channel_id: "test".into(),
+                channel_title: "test".into(),
+                title: format!("{id} title"),
+                published_at: Utc::now(),
+              },
+              content_details: None,
+              id,
+            })
+            .collect(),
+        })
+      })
+      .expect(3)
+      .named("videos")
+      .mount(&mock)
+      .await;
+
+    let ids = (0..120)
+      .map(|i| {
+        if i % 7 == 0 {
+          format!("deleted{i}")
+        } else {
+          format!("video{i}")
+        }
+      })
+      .collect::<Vec<_>>();
+    let client = YoutubeApiV3::new(mock.uri(), Secret::new("test".into()));
+    let lookup = client.videos(ids.iter().map(|id| id.as_str())).await?;
+    assert_eq!(
+      lookup.found.iter().map(|v| v.id.as_str()).collect::<Vec<_>>(),
+      ids.iter().filter(|id| id.starts_with("video")).collect::<Vec<_>>()
+    );
+    assert_eq!(
+      lookup.missing,
+      ids
+        .iter()
+        .filter(|id| id.starts_with("deleted"))
+        .cloned()
+        .collect::<Vec<_>>()
+    );
+
+    Ok(())
+  }
+
   #[actix_rt::test]
   async fn playlist_happy_path() -> anyhow::Result<()> {
     let mock = MockServer::start().await;
@@ -151,7 +510,6 @@ mod tests {
       .respond_with(|_r: &Request| {
         ResponseTemplate::new(200).set_body_json(schema::PlaylistItemList {
           items: (0..25)
-            .into_iter()
             .map(|i| schema::PlaylistItem {
               content_details: schema::PlaylistItemContentDetails {
                 video_id: format!("video{i}"),
@@ -161,6 +519,7 @@ mod tests {
               },
             })
             .collect(),
+          etag: "etag".into(),
           next_page_token: None,
         })
       })
@@ -191,7 +550,6 @@ mod tests {
       .respond_with(|_r: &Request| {
         ResponseTemplate::new(200).set_body_json(schema::PlaylistItemList {
           items: (0..50)
-            .into_iter()
             .map(|i| schema::PlaylistItem {
               content_details: schema::PlaylistItemContentDetails {
                 video_id: format!("video{i}"),
@@ -205,6 +563,7 @@ mod tests {
               },
             })
             .collect(),
+          etag: "etag".into(),
           next_page_token: None,
         })
       })
@@ -237,7 +596,6 @@ mod tests {
         let id_start = if has_page_token { 25 } else { 0 };
         ResponseTemplate::new(200).set_body_json(schema::PlaylistItemList {
           items: (id_start..id_start + 25)
-            .into_iter()
             .map(|i| schema::PlaylistItem {
               content_details: schema::PlaylistItemContentDetails {
                 video_id: format!("video{i}"),
@@ -247,6 +605,7 @@ mod tests {
               },
             })
             .collect(),
+          etag: format!("etag{id_start}"),
           next_page_token: if has_page_token { None } else { Some("0".into()) },
         })
       })
@@ -268,4 +627,265 @@ mod tests {
 
     Ok(())
   }
+
+  #[actix_rt::test]
+  async fn playlist_reports_every_page() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/playlistItems"))
+      .and(method("GET"))
+      .respond_with(|r: &Request| {
+        let has_page_token = r.url.query().map(|q| q.contains("pageToken")).unwrap_or(false);
+        let id_start = if has_page_token { 25 } else { 0 };
+        ResponseTemplate::new(200).set_body_json(schema::PlaylistItemList {
+          items: (id_start..id_start + 25)
+            .map(|i| schema::PlaylistItem {
+              content_details: schema::PlaylistItemContentDetails {
+                video_id: format!("video{i}"),
+              },
+              status: schema::PlaylistItemStatus {
+                privacy_status: schema::PrivacyStatus::Public,
+              },
+            })
+            .collect(),
+          etag: format!("etag{id_start}"),
+          next_page_token: if has_page_token { None } else { Some("0".into()) },
+        })
+      })
+      .expect(2)
+      .named("playlist_items")
+      .mount(&mock)
+      .await;
+    Mock::given(path("/videos"))
+      .and(method("GET"))
+      .respond_with(videos_response)
+      .expect(2)
+      .named("videos")
+      .mount(&mock)
+      .await;
+
+    let client = YoutubeApiV3::new(mock.uri(), Secret::new("test".into()));
+    let mut pages = 0;
+    let response = client.playlist_pages("test", &[], || pages += 1).await?;
+    assert_eq!(response.len(), 2);
+    assert_eq!(pages, 2);
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn playlist_overlaps_listing_and_lookups() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    // 8 pages of 25 videos each, chained by their page tokens
+    Mock::given(path("/playlistItems"))
+      .and(method("GET"))
+      .respond_with(|r: &Request| {
+        let page = r
+          .url
+          .query_pairs()
+          .find(|(k, _)| k == "pageToken")
+          .map(|(_, v)| v.parse::<usize>().unwrap())
+          .unwrap_or(0);
+        ResponseTemplate::new(200).set_body_json(schema::PlaylistItemList {
+          items: (page * 25..page * 25 + 25)
+            .map(|i| schema::PlaylistItem {
+              content_details: schema::PlaylistItemContentDetails {
+                video_id: format!("video{i}"),
+              },
+              status: schema::PlaylistItemStatus {
+                privacy_status: schema::PrivacyStatus::Public,
+              },
+            })
+            .collect(),
+          etag: format!("etag{page}"),
+          next_page_token: if page < 7 { Some((page + 1).to_string()) } else { None },
+        })
+      })
+      .named("playlist_items")
+      .mount(&mock)
+      .await;
+    // looking up the videos of the first page doesn't finish during the test
+    Mock::given(path("/videos"))
+      .and(method("GET"))
+      .respond_with(|r: &Request| {
+        if r.url.query_pairs().any(|(k, v)| k == "id" && v == "video0") {
+          videos_response(r).set_delay(std::time::Duration::from_secs(3600))
+        } else {
+          videos_response(r)
+        }
+      })
+      .named("videos")
+      .mount(&mock)
+      .await;
+
+    let client = YoutubeApiV3::new(mock.uri(), Secret::new("test".into()));
+    let listing = actix_rt::spawn(async move { client.playlist_videos("test").await });
+    let requests = actix_rt::time::timeout(std::time::Duration::from_secs(10), async {
+      loop {
+        let requests = mock.received_requests().await.unwrap();
+        if requests.iter().filter(|r| r.url.path() == "/videos").count() == MAX_CONCURRENT_LOOKUPS {
+          return requests;
+        }
+        actix_rt::time::sleep(std::time::Duration::from_millis(10)).await;
+      }
+    })
+    .await?;
+    listing.abort();
+
+    let page_tokens = requests
+      .iter()
+      .filter(|r| r.url.path() == "/playlistItems")
+      .map(|r| {
+        r.url
+          .query_pairs()
+          .find(|(k, _)| k == "pageToken")
+          .map(|(_, v)| v.into_owned())
+      })
+      .collect::<Vec<_>>();
+    // the following pages were listed while the first one was still being looked up,
+    // until `MAX_CONCURRENT_LOOKUPS` lookups were in flight
+    assert_eq!(
+      page_tokens,
+      [None, Some("1".into()), Some("2".into()), Some("3".into())]
+    );
+    let first_lookup = requests.iter().position(|r| r.url.path() == "/videos").unwrap();
+    let last_listing = requests.iter().rposition(|r| r.url.path() == "/playlistItems").unwrap();
+    assert!(first_lookup < last_listing);
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn playlist_skips_unchanged_pages() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/playlistItems"))
+      .and(method("GET"))
+      .respond_with(|r: &Request| {
+        let has_page_token = r.url.query().map(|q| q.contains("pageToken")).unwrap_or(false);
+        let id_start = if has_page_token { 25 } else { 0 };
+        let etag = format!("etag{id_start}");
+        if r.headers.get(&"if-none-match".into()).map(|v| v.as_str()) == Some(format!("\"{etag}\"").as_str()) {
+          return ResponseTemplate::new(304);
+        }
+        ResponseTemplate::new(200).set_body_json(schema::PlaylistItemList {
+          items: (id_start..id_start + 25)
+            .map(|i| schema::PlaylistItem {
+              content_details: schema::PlaylistItemContentDetails {
+                video_id: format!("video{i}"),
+              },
+              status: schema::PlaylistItemStatus {
+                privacy_status: schema::PrivacyStatus::Public,
+              },
+            })
+            .collect(),
+          etag,
+          next_page_token: if has_page_token { None } else { Some("0".into()) },
+        })
+      })
+      .expect(2)
+      .named("playlist_items")
+      .mount(&mock)
+      .await;
+    // only the changed page is looked up
+    Mock::given(path("/videos"))
+      .and(method("GET"))
+      .respond_with(videos_response)
+      .expect(1)
+      .named("videos")
+      .mount(&mock)
+      .await;
+
+    let known = vec![
+      PlaylistPage {
+        etag: "etag0".into(),
+        next_page_token: Some("0".into()),
+        video_ids: vec!["video0".into()],
+      },
+      PlaylistPage {
+        etag: "outdated".into(),
+        next_page_token: None,
+        video_ids: vec![],
+      },
+    ];
+    let client = YoutubeApiV3::new(mock.uri(), Secret::new("test".into()));
+    let pages = client.playlist_pages("test", &known, || {}).await?;
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0], (known[0].clone(), None));
+    assert_eq!(pages[1].0.etag, "etag25");
+    assert_eq!(pages[1].1.as_ref().map(|v| v.len()), Some(25));
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn search_fetches_video_details() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/search"))
+      .and(method("GET"))
+      .respond_with(ResponseTemplate::new(200).set_body_json(schema::SearchList {
+        items: vec![
+          schema::SearchResult {
+            id: schema::SearchResultId {
+              video_id: Some("video0".into()),
+            },
+          },
+          schema::SearchResult {
+            id: schema::SearchResultId { video_id: None },
+          },
+          schema::SearchResult {
+            id: schema::SearchResultId {
+              video_id: Some("video1".into()),
+            },
+          },
+        ],
+      }))
+      .expect(1)
+      .named("search")
+      .mount(&mock)
+      .await;
+    Mock::given(path("/videos"))
+      .and(method("GET"))
+      .respond_with(videos_response)
+      .expect(1)
+      .named("videos")
+      .mount(&mock)
+      .await;
+
+    let client = YoutubeApiV3::new(mock.uri(), Secret::new("test".into()));
+    let videos = client.search("test", 5).await?;
+    assert_eq!(
+      videos.iter().map(|v| v.id.as_str()).collect::<Vec<_>>(),
+      vec!["video0", "video1"]
+    );
+    assert_eq!(videos[0].duration, Some(chrono::Duration::minutes(3)));
+
+    Ok(())
+  }
+
+  #[actix_rt::test]
+  async fn search_fails_on_quota_exceeded() -> anyhow::Result<()> {
+    let mock = MockServer::start().await;
+
+    Mock::given(path("/search"))
+      .respond_with(ResponseTemplate::new(403))
+      .expect(1)
+      .named("search")
+      .mount(&mock)
+      .await;
+    Mock::given(path("/videos"))
+      .respond_with(videos_response)
+      .expect(0)
+      .named("videos")
+      .mount(&mock)
+      .await;
+
+    let client = YoutubeApiV3::new(mock.uri(), Secret::new("test".into()));
+    let error = client.search("test", 5).await.unwrap_err();
+    assert_eq!(error.status(), Some(reqwest::StatusCode::FORBIDDEN));
+
+    Ok(())
+  }
 }
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct PageInfo {
   #[serde(rename = "totalResults")]
@@ -15,6 +16,8 @@ pub struct VideoList {
 pub struct VideoListItem {
   pub id: String,
   pub snippet: VideoListItemSnippet,
+  #[serde(rename = "contentDetails")]
+  pub content_details: Option<VideoListItemContentDetails>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
@@ -22,12 +25,124 @@ pub struct VideoListItemSnippet {
   #[serde(rename = "channelId")]
   pub channel_id: String,
   pub title: String,
+  #[serde(rename = "channelTitle")]
+  pub channel_title: String,
   #[serde(rename = "publishedAt")]
   pub published_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct VideoListItemContentDetails {
+  /// ISO 8601 duration, e.g. `PT4M13S`
+  pub duration: String,
+}
+
+/// Parse an ISO 8601 duration as returned in `contentDetails.duration`.
+///
+/// Only days, hours, minutes and seconds are supported, which is all YouTube uses.
+pub fn parse_duration(v: &str) -> Option<chrono::Duration> {
+  let v = v.strip_prefix('P')?;
+  let (days, time) = match v.split_once('T') {
+    Some((days, time)) => (days, time),
+    None => (v, ""),
+  };
+  let mut seconds = 0i64;
+  for (part, units) in [
+    (days, &[('D', 86400)][..]),
+    (time, &[('H', 3600), ('M', 60), ('S', 1)][..]),
+  ] {
+    let mut rest = part;
+    for (unit, multiplier) in units {
+      if let Some((value, tail)) = rest.split_once(*unit) {
+        seconds += value.parse::<i64>().ok()? * multiplier;
+        rest = tail;
+      }
+    }
+    if !rest.is_empty() {
+      return None;
+    }
+  }
+  Some(chrono::Duration::seconds(seconds))
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SearchList {
+  pub items: Vec<SearchResult>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SearchResult {
+  pub id: SearchResultId,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SearchResultId {
+  /// Only present for results of kind `youtube#video`
+  #[serde(rename = "videoId")]
+  pub video_id: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct PlaylistList {
+  pub items: Vec<PlaylistListItem>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct PlaylistListItem {
+  pub id: String,
+  pub snippet: PlaylistListItemSnippet,
+  #[serde(rename = "contentDetails")]
+  pub content_details: PlaylistListItemContentDetails,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct PlaylistListItemSnippet {
+  pub title: String,
+  #[serde(rename = "channelId")]
+  pub channel_id: String,
+  #[serde(rename = "channelTitle")]
+  pub channel_title: String,
+  #[serde(default)]
+  pub thumbnails: Thumbnails,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct PlaylistListItemContentDetails {
+  /// Includes private and deleted videos
+  #[serde(rename = "itemCount")]
+  pub item_count: i32,
+}
+
+/// Only the sizes which are available are returned
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Thumbnails {
+  pub default: Option<Thumbnail>,
+  pub medium: Option<Thumbnail>,
+  pub high: Option<Thumbnail>,
+  pub standard: Option<Thumbnail>,
+  pub maxres: Option<Thumbnail>,
+}
+
+impl Thumbnails {
+  /// The largest available thumbnail
+  pub fn largest(self) -> Option<Thumbnail> {
+    self
+      .maxres
+      .or(self.standard)
+      .or(self.high)
+      .or(self.medium)
+      .or(self.default)
+  }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct Thumbnail {
+  pub url: String,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
 pub struct PlaylistItemList {
+  pub etag: String,
   pub items: Vec<PlaylistItem>,
   #[serde(rename = "nextPageToken")]
   pub next_page_token: Option<String>,
@@ -77,6 +192,14 @@ mod tests {
               "kind": "youtube#video",
               "etag": "kFGyuGlSrGmFEXBAJORgWejiNEQ",
               "id": "Ks-_Mh1QhMc",
+              "contentDetails": {
+                "duration": "PT21M3S",
+                "dimension": "2d",
+                "definition": "hd",
+                "caption": "true",
+                "licensedContent": true,
+                "projection": "rectangular"
+              },
               "snippet": {
                 "publishedAt": "2012-10-01T15:27:35Z",
                 "channelId": "UCAuUUnT6oDeKwE6v1NGQxug",
@@ -212,16 +335,22 @@ mod tests {
             snippet: VideoListItemSnippet {
               channel_id: "UCAuUUnT6oDeKwE6v1NGQxug".into(),
               title: "Your body language may shape who you are | Amy Cuddy".into(),
+              channel_title: "TED".into(),
               published_at: DateTime::<Utc>::from(DateTime::parse_from_rfc3339("2012-10-01T15:27:35Z").unwrap())
-            }
+            },
+            content_details: Some(VideoListItemContentDetails {
+              duration: "PT21M3S".into()
+            })
           },
           VideoListItem {
             id: "Ks-_Mh1QhMc".into(),
             snippet: VideoListItemSnippet {
               channel_id: "UCAuUUnT6oDeKwE6v1NGQxug".into(),
               title: "Your body language may shape who you are | Amy Cuddy".into(),
+              channel_title: "TED".into(),
               published_at: DateTime::<Utc>::from(DateTime::parse_from_rfc3339("2012-10-01T15:27:35Z").unwrap())
-            }
+            },
+            content_details: None
           }
         ],
       }
@@ -271,6 +400,7 @@ mod tests {
     assert_eq!(
       serde_json::from_str::<PlaylistItemList>(data).unwrap(),
       PlaylistItemList {
+        etag: "m6PJ_s7BOULkhCMdNyYGNcZSZf8".into(),
         items: vec![
           PlaylistItem {
             content_details: PlaylistItemContentDetails {
@@ -293,4 +423,125 @@ mod tests {
       }
     )
   }
+
+  #[test]
+  fn deserialize_playlists() {
+    let data = r#"
+        {
+          "kind": "youtube#playlistListResponse",
+          "etag": "6Gd5nGdJYcJhnkqpNrb-Sel5YLE",
+          "pageInfo": {
+            "totalResults": 1,
+            "resultsPerPage": 5
+          },
+          "items": [
+            {
+              "kind": "youtube#playlist",
+              "etag": "jmNh7u_CrC2yrXkyo1VC_Gj2ux4",
+              "id": "PLHHYuo8wPxUxa_yHIB3Je8RsNzDP08uut",
+              "snippet": {
+                "publishedAt": "2016-01-21T13:35:46Z",
+                "channelId": "UCZ3r3_XMx4kVX8wnJB3xW4Q",
+                "title": "stream",
+                "description": "",
+                "thumbnails": {
+                  "default": {
+                    "url": "https://i.ytimg.com/vi/kOCxHu_F5xo/default.jpg",
+                    "width": 120,
+                    "height": 90
+                  },
+                  "high": {
+                    "url": "https://i.ytimg.com/vi/kOCxHu_F5xo/hqdefault.jpg",
+                    "width": 480,
+                    "height": 360
+                  }
+                },
+                "channelTitle": "moscowwbish",
+                "localized": {
+                  "title": "stream",
+                  "description": ""
+                }
+              },
+              "contentDetails": {
+                "itemCount": 539
+              }
+            }
+          ]
+        }
+      "#;
+
+    let playlists = serde_json::from_str::<PlaylistList>(data).unwrap();
+    assert_eq!(
+      playlists.items[0].content_details,
+      PlaylistListItemContentDetails { item_count: 539 }
+    );
+    assert_eq!(
+      playlists.items[0].snippet.clone().thumbnails.largest(),
+      Some(Thumbnail {
+        url: "https://i.ytimg.com/vi/kOCxHu_F5xo/hqdefault.jpg".into()
+      })
+    );
+    assert_eq!(playlists.items[0].snippet.channel_title, "moscowwbish");
+  }
+
+  #[test]
+  fn parse_iso8601_durations() {
+    assert_eq!(parse_duration("PT4M13S"), Some(chrono::Duration::seconds(253)));
+    assert_eq!(parse_duration("PT1H"), Some(chrono::Duration::hours(1)));
+    assert_eq!(parse_duration("P1DT2S"), Some(chrono::Duration::seconds(86402)));
+    assert_eq!(parse_duration("P0D"), Some(chrono::Duration::zero()));
+    assert_eq!(parse_duration("PT4S3M"), None);
+    assert_eq!(parse_duration("4M13S"), None);
+    assert_eq!(parse_duration("PTXS"), None);
+  }
+
+  #[test]
+  fn deserialize_search_results() {
+    let data = r#"
+        {
+          "kind": "youtube#searchListResponse",
+          "etag": "q4ibjmYp1KA3RqMF4jFLl6PBwOg",
+          "nextPageToken": "CAUQAA",
+          "regionCode": "NL",
+          "pageInfo": {
+            "totalResults": 1000000,
+            "resultsPerPage": 2
+          },
+          "items": [
+            {
+              "kind": "youtube#searchResult",
+              "etag": "QCsHBifbaernVCbLv8Cu6rAeaDQ",
+              "id": {
+                "kind": "youtube#video",
+                "videoId": "TmKh7lAwnBI"
+              }
+            },
+            {
+              "kind": "youtube#searchResult",
+              "etag": "ZeLl1N5IYwbk0NizFnSQ4xsDHsE",
+              "id": {
+                "kind": "youtube#channel",
+                "channelId": "UCAuUUnT6oDeKwE6v1NGQxug"
+              }
+            }
+          ]
+        }
+      "#;
+
+    assert_eq!(
+      serde_json::from_str::<SearchList>(data).unwrap(),
+      SearchList {
+        items: vec![
+          SearchResult {
+            id: SearchResultId {
+              video_id: Some("TmKh7lAwnBI".into())
+            }
+          },
+          SearchResult {
+            id: SearchResultId { video_id: None }
+          }
+        ]
+      }
+    )
+  }
 }
@@ -1,4 +1,6 @@
+use crate::client::oembed::Provider;
 use secrecy::Secret;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 fn parse_duration(src: &str) -> Result<chrono::Duration, humantime::DurationError> {
@@ -15,6 +17,34 @@ pub struct Config {
     help = "Google API key, follow the steps at https://developers.google.com/youtube/v3/getting-started#before-you-start to obtain one"
   )]
   pub youtube_key: Secret<String>,
+  #[structopt(
+    long,
+    env = "SR_API_SOUNDCLOUD_CLIENT_ID",
+    help = "SoundCloud client ID, SoundCloud support is disabled if this is not set"
+  )]
+  pub soundcloud_client_id: Option<Secret<String>>,
+  #[structopt(
+    long,
+    env = "SR_API_SPOTIFY_CLIENT_ID",
+    help = "Spotify client ID, Spotify support is disabled if this or the client secret is not set"
+  )]
+  pub spotify_client_id: Option<String>,
+  #[structopt(long, env = "SR_API_SPOTIFY_CLIENT_SECRET", help = "Spotify client secret")]
+  pub spotify_client_secret: Option<Secret<String>>,
+  #[structopt(
+    long,
+    env = "SR_API_OEMBED_PROVIDERS",
+    use_delimiter = true,
+    help = "Comma-separated list of allowed oEmbed providers in the form `HOST=ENDPOINT`, e.g. `vimeo.com=https://vimeo.com/api/oembed.json`"
+  )]
+  pub oembed_providers: Vec<Provider>,
+  #[structopt(
+    long,
+    env = "SR_API_LOCAL_LIBRARY",
+    help = "Directory of audio files served as the `local` platform, disabled if this is not set",
+    parse(from_os_str)
+  )]
+  pub local_library: Option<PathBuf>,
   #[structopt(long, env = "SR_API_DATABASE_URL", help = "PostgreSQL database URL")]
   pub database_url: String,
   #[structopt(long, env = "SR_API_PORT", help = "Port to bind on")]
@@ -26,4 +56,66 @@ pub struct Config {
     parse(try_from_str = parse_duration)
   )]
   pub playlist_refresh_interval: chrono::Duration,
+  #[structopt(
+    long,
+    env = "SR_API_PLAYLIST_REFRESH_CHECK_INTERVAL",
+    help = "How often to check for playlists which should be re-fetched in the background",
+    default_value = "1m",
+    parse(try_from_str = parse_duration)
+  )]
+  pub playlist_refresh_check_interval: chrono::Duration,
+  #[structopt(
+    long,
+    env = "SR_API_PLAYLIST_REFRESH_JITTER",
+    help = "Maximum random delay before each background re-fetch",
+    default_value = "30s",
+    parse(try_from_str = parse_duration)
+  )]
+  pub playlist_refresh_jitter: chrono::Duration,
+  #[structopt(
+    long,
+    env = "SR_API_PLAYLIST_REFRESH_CONCURRENCY",
+    help = "Maximum number of playlists re-fetched in the background at once",
+    default_value = "4"
+  )]
+  pub playlist_refresh_concurrency: usize,
+  #[structopt(
+    long,
+    env = "SR_API_AVAILABILITY_CHECK_INTERVAL",
+    help = "How often to check whether songs are still available in the background",
+    default_value = "10m",
+    parse(try_from_str = parse_duration)
+  )]
+  pub availability_check_interval: chrono::Duration,
+  #[structopt(
+    long,
+    env = "SR_API_AVAILABILITY_RECHECK_INTERVAL",
+    help = "How often the availability of every song should be re-checked",
+    default_value = "7days",
+    parse(try_from_str = parse_duration)
+  )]
+  pub availability_recheck_interval: chrono::Duration,
+  #[structopt(
+    long,
+    env = "SR_API_PLAYBACK_FAILURE_THRESHOLD",
+    help = "Number of reported playback failures after which a song is flagged as unplayable",
+    default_value = "3"
+  )]
+  pub playback_failure_threshold: i64,
+  #[structopt(
+    long,
+    env = "SR_API_MATCH_MISS_TTL",
+    help = "How long it is remembered that a song has no match on a platform, before matching it is attempted again",
+    default_value = "7days",
+    parse(try_from_str = parse_duration)
+  )]
+  pub match_miss_ttl: chrono::Duration,
+  #[structopt(
+    long,
+    env = "SR_API_STATS_REFRESH_INTERVAL",
+    help = "How often the channel statistics are recomputed from the play history",
+    default_value = "5m",
+    parse(try_from_str = parse_duration)
+  )]
+  pub stats_refresh_interval: chrono::Duration,
 }
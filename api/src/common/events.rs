@@ -0,0 +1,88 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+
+/// Number of events buffered per channel, players which fall further behind miss the oldest ones
+const CAPACITY: usize = 16;
+
+/// Something which happened on a channel, sent to its players through `/channel/events`
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelEvent {
+  /// A vote to skip the play passed, players should move on to the next song
+  Skip { play: i64 },
+}
+
+/// Fans out the events of each channel to the players subscribed to it on this instance.
+///
+/// This is shared between all workers, so it must be created outside of the `HttpServer` factory.
+#[derive(Clone, Default)]
+pub struct ChannelEvents {
+  senders: Arc<Mutex<HashMap<String, broadcast::Sender<ChannelEvent>>>>,
+}
+
+impl ChannelEvents {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Receive the events sent to `channel` from now on
+  ///
+  /// Channels whose subscribers are all gone are dropped on the way, even if nothing is sent to them anymore.
+  pub fn subscribe(&self, channel: &str) -> broadcast::Receiver<ChannelEvent> {
+    let mut senders = self.senders.lock().unwrap();
+    senders.retain(|_, sender| sender.receiver_count() > 0);
+    senders
+      .entry(channel.to_owned())
+      .or_insert_with(|| broadcast::channel(CAPACITY).0)
+      .subscribe()
+  }
+
+  /// Send an event to the subscribers of `channel`, returning how many there were
+  pub fn send(&self, channel: &str, event: ChannelEvent) -> usize {
+    let mut senders = self.senders.lock().unwrap();
+    let sent = senders
+      .get(channel)
+      .map(|sender| sender.send(event).unwrap_or(0))
+      .unwrap_or(0);
+    // every subscriber is gone, don't keep the channel around
+    if sent == 0 {
+      senders.remove(channel);
+    }
+    sent
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[actix_rt::test]
+  async fn sends_to_subscribers_of_the_channel() {
+    let events = ChannelEvents::new();
+    let mut a = events.subscribe("a");
+    let mut also_a = events.subscribe("a");
+    let mut b = events.subscribe("b");
+
+    assert_eq!(events.send("a", ChannelEvent::Skip { play: 1 }), 2);
+    assert_eq!(a.recv().await.unwrap(), ChannelEvent::Skip { play: 1 });
+    assert_eq!(also_a.recv().await.unwrap(), ChannelEvent::Skip { play: 1 });
+    assert!(b.try_recv().is_err());
+
+    drop(b);
+    assert_eq!(events.send("b", ChannelEvent::Skip { play: 2 }), 0);
+    assert!(events.senders.lock().unwrap().get("b").is_none());
+  }
+
+  #[test]
+  fn subscribing_drops_channels_without_subscribers() {
+    let events = ChannelEvents::new();
+    drop(events.subscribe("a"));
+    let _b = events.subscribe("b");
+    let senders = events.senders.lock().unwrap();
+    assert!(senders.get("a").is_none());
+    assert!(senders.get("b").is_some());
+  }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+
+/// Songs whose durations differ by more than this are never considered the same
+pub const DURATION_TOLERANCE_SECS: i32 = 10;
+
+/// Candidates scoring lower than this are never considered the same
+pub const MIN_SCORE: f64 = 0.75;
+
+/// Words which commonly appear in titles and channel names, but say nothing about the song
+const NOISE: &[&str] = &[
+  "official", "video", "audio", "lyrics", "lyric", "music", "hd", "hq", "mv", "feat", "ft", "vevo", "topic",
+];
+
+/// Lowercase `v`, strip anything in brackets, and split it into alphanumeric words without noise words.
+pub fn normalize(v: &str) -> Vec<String> {
+  let mut depth = 0usize;
+  let mut stripped = String::with_capacity(v.len());
+  for c in v.chars() {
+    match c {
+      '(' | '[' | '{' => depth += 1,
+      ')' | ']' | '}' => depth = depth.saturating_sub(1),
+      _ if depth == 0 => stripped.extend(c.to_lowercase()),
+      _ => {}
+    }
+  }
+  stripped
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty() && !NOISE.contains(word))
+    .map(String::from)
+    .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate<'a> {
+  pub title: &'a str,
+  pub artist: Option<&'a str>,
+  /// Duration in seconds
+  pub duration: Option<i32>,
+}
+
+/// Fraction of `words` which appear in `haystack`
+fn recall(words: &[String], haystack: &HashSet<String>) -> Option<f64> {
+  if words.is_empty() {
+    None
+  } else {
+    Some(words.iter().filter(|w| haystack.contains(*w)).count() as f64 / words.len() as f64)
+  }
+}
+
+/// Score how likely `candidate` is the same song as `source`, between `0.0` and `1.0`.
+///
+/// Returns `None` if their durations are both known and too far apart.
+pub fn score(source: &Candidate, candidate: &Candidate) -> Option<f64> {
+  if let (Some(a), Some(b)) = (source.duration, candidate.duration) {
+    if (a - b).abs() > DURATION_TOLERANCE_SECS {
+      return None;
+    }
+  }
+  // titles on e.g. YouTube are often `Artist - Title`, so search for both in both fields
+  let haystack = normalize(candidate.title)
+    .into_iter()
+    .chain(candidate.artist.map(normalize).unwrap_or_default())
+    .collect::<HashSet<_>>();
+  let title = recall(&normalize(source.title), &haystack)?;
+  Some(match source.artist.map(normalize).and_then(|a| recall(&a, &haystack)) {
+    Some(artist) => 0.7 * title + 0.3 * artist,
+    None => title,
+  })
+}
+
+/// Find the index of the best match for `source` among `candidates`.
+///
+/// Ties are broken by the smaller duration difference, then by the earlier candidate.
+pub fn best<'a>(source: &Candidate, candidates: impl IntoIterator<Item = Candidate<'a>>) -> Option<usize> {
+  let mut best = Option::<(usize, f64, i32)>::None;
+  for (i, candidate) in candidates.into_iter().enumerate() {
+    let score = match score(source, &candidate) {
+      Some(score) if score >= MIN_SCORE => score,
+      _ => continue,
+    };
+    let distance = match (source.duration, candidate.duration) {
+      (Some(a), Some(b)) => (a - b).abs(),
+      _ => DURATION_TOLERANCE_SECS,
+    };
+    let is_better = match best {
+      None => true,
+      Some((_, best_score, best_distance)) => score > best_score || (score == best_score && distance < best_distance),
+    };
+    if is_better {
+      best = Some((i, score, distance));
+    }
+  }
+  best.map(|(i, _, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn song<'a>(title: &'a str, artist: Option<&'a str>, duration: Option<i32>) -> Candidate<'a> {
+    Candidate {
+      title,
+      artist,
+      duration,
+    }
+  }
+
+  #[test]
+  fn normalize_strips_noise() {
+    assert_eq!(
+      normalize("Daft Punk - Get Lucky (Official Video) [HD] feat. Pharrell"),
+      vec!["daft", "punk", "get", "lucky", "pharrell"]
+    );
+    assert_eq!(normalize("(Official Audio)"), Vec::<String>::new());
+    assert_eq!(normalize("Ünïcode – Tïtle"), vec!["ünïcode", "tïtle"]);
+  }
+
+  #[test]
+  fn score_artist_in_title() {
+    let source = song("Get Lucky", Some("Daft Punk"), Some(248));
+    let candidate = song(
+      "Daft Punk - Get Lucky (Official Audio)",
+      Some("DaftPunkVEVO"),
+      Some(250),
+    );
+    assert_eq!(score(&source, &candidate), Some(1.0));
+  }
+
+  #[test]
+  fn score_rejects_duration_mismatch() {
+    let source = song("Get Lucky", Some("Daft Punk"), Some(248));
+    let candidate = song("Daft Punk - Get Lucky (Extended)", None, Some(369));
+    assert_eq!(score(&source, &candidate), None);
+    // unknown durations are not held against the candidate
+    let candidate = song("Daft Punk - Get Lucky", None, None);
+    assert_eq!(score(&source, &candidate), Some(1.0));
+  }
+
+  #[test]
+  fn best_prefers_score_then_duration() {
+    let source = song("Get Lucky", Some("Daft Punk"), Some(248));
+    let candidates = vec![
+      song("Get Lucky", Some("Someone Else"), Some(248)),
+      song("Daft Punk - Get Lucky", None, Some(255)),
+      song("Daft Punk - Get Lucky", None, Some(249)),
+      song("Something Else Entirely", Some("Daft Punk"), Some(248)),
+    ];
+    assert_eq!(best(&source, candidates), Some(2));
+    assert_eq!(
+      best(&source, vec![song("Harder Better Faster Stronger", None, None)]),
+      None
+    );
+  }
+}
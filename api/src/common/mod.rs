@@ -1,3 +1,7 @@
 pub mod config;
+pub mod events;
+pub mod matching;
 pub mod platform;
+pub mod role;
+pub mod single_flight;
 pub mod util;
@@ -1,14 +1,24 @@
-#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, sqlx::Type)]
 #[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "TEXT", rename_all = "lowercase")]
 pub enum Platform {
   Youtube,
+  Soundcloud,
+  Spotify,
+  /// Any allowed oEmbed provider, songs are identified by their URL
+  Oembed,
+  /// Audio files in the configured local library, songs are identified by their relative path
+  Local,
 }
 
 impl Platform {
   pub fn as_str(self) -> &'static str {
     match self {
       Platform::Youtube => "youtube",
+      Platform::Soundcloud => "soundcloud",
+      Platform::Spotify => "spotify",
+      Platform::Oembed => "oembed",
+      Platform::Local => "local",
     }
   }
 }
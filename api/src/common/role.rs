@@ -0,0 +1,14 @@
+/// A viewer's role in a channel, from the least to the most privileged
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Role {
+  #[default]
+  User,
+  Subscriber,
+  Vip,
+  Moderator,
+  Broadcaster,
+}
@@ -0,0 +1,124 @@
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::{
+  collections::HashMap,
+  future::Future,
+  hash::Hash,
+  sync::{Arc, Mutex},
+};
+
+/// Coalesces concurrent executions of the same work.
+///
+/// While the work for a key is in flight, every caller with the same key awaits
+/// the same execution and receives a clone of its result, instead of repeating it.
+/// Once the work completes, the next call with that key starts it again.
+///
+/// This is shared between all workers, so it must be created outside of the `HttpServer` factory.
+pub struct SingleFlight<K, V> {
+  in_flight: Arc<Mutex<HashMap<K, Shared<BoxFuture<'static, V>>>>>,
+}
+
+impl<K, V> Clone for SingleFlight<K, V> {
+  fn clone(&self) -> Self {
+    Self {
+      in_flight: self.in_flight.clone(),
+    }
+  }
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+  fn default() -> Self {
+    Self {
+      in_flight: Default::default(),
+    }
+  }
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+  K: Hash + Eq + Clone + Send + 'static,
+  V: Clone + Send + Sync + 'static,
+{
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Await the work in flight for `key`, or start it using `work` if there is none.
+  ///
+  /// The work runs on its own task, so it completes even if every caller stops awaiting it.
+  pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = V> + Send + 'static,
+  {
+    let shared = {
+      let mut in_flight = self.in_flight.lock().unwrap();
+      match in_flight.get(&key) {
+        Some(shared) => shared.clone(),
+        None => {
+          let this = self.clone();
+          let fut = work();
+          let owned_key = key.clone();
+          let handle = actix_rt::spawn(async move {
+            let result = fut.await;
+            this.in_flight.lock().unwrap().remove(&owned_key);
+            result
+          });
+          let shared = handle
+            .map(|result| result.expect("Work in flight panicked"))
+            .boxed()
+            .shared();
+          in_flight.insert(key, shared.clone());
+          shared
+        }
+      }
+    };
+    shared.await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  #[actix_rt::test]
+  async fn coalesces_concurrent_calls() {
+    let flight = SingleFlight::<&str, usize>::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let work = || {
+      let runs = runs.clone();
+      async move {
+        actix_rt::time::sleep(std::time::Duration::from_millis(20)).await;
+        runs.fetch_add(1, Ordering::SeqCst) + 1
+      }
+    };
+
+    let (a, b, c) = futures::join!(flight.run("a", work), flight.run("a", work), flight.run("b", work));
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+
+    // finished work is not cached
+    flight.run("a", work).await;
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+  }
+
+  #[actix_rt::test]
+  async fn completes_work_without_callers() {
+    let flight = SingleFlight::<&str, ()>::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let work = || {
+      let runs = runs.clone();
+      async move {
+        actix_rt::time::sleep(std::time::Duration::from_millis(20)).await;
+        runs.fetch_add(1, Ordering::SeqCst);
+      }
+    };
+
+    let call = actix_rt::time::timeout(std::time::Duration::from_millis(5), flight.run("a", work)).await;
+    assert!(call.is_err());
+    actix_rt::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert!(flight.in_flight.lock().unwrap().is_empty());
+  }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// An opaque position in a list for keyset pagination, pointing after the last item of a page.
+///
+/// The empty string points at the start of the list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cursor {
+  after: Option<(i32, i32)>,
+}
+
+impl Cursor {
+  /// Point after the item with the key `(rank, position)`
+  pub fn after(rank: i32, position: i32) -> Self {
+    Self {
+      after: Some((rank, position)),
+    }
+  }
+
+  /// The key of the last item before this cursor, `None` at the start of the list
+  pub fn key(&self) -> Option<(i32, i32)> {
+    self.after
+  }
+}
+
+impl fmt::Display for Cursor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.after {
+      Some((rank, position)) => write!(f, "p{rank:x}.{position:x}"),
+      None => Ok(()),
+    }
+  }
+}
+
+impl FromStr for Cursor {
+  type Err = &'static str;
+
+  fn from_str(v: &str) -> Result<Self, Self::Err> {
+    if v.is_empty() {
+      return Ok(Self::default());
+    }
+    let parse = |v: &str| i32::from_str_radix(v, 16).ok().filter(|v| *v >= 0);
+    v.strip_prefix('p')
+      .and_then(|key| key.split_once('.'))
+      .and_then(|(rank, position)| Some(Self::after(parse(rank)?, parse(position)?)))
+      .ok_or("invalid cursor")
+  }
+}
+
+impl Serialize for Cursor {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let v = <&'de str as Deserialize<'de>>::deserialize(deserializer)?;
+    v.parse().map_err(serde::de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Test {
+    cursor: Option<Cursor>,
+  }
+
+  fn de(v: &str) -> Result<Test, serde_urlencoded::de::Error> {
+    serde_urlencoded::from_str::<Test>(v)
+  }
+
+  #[test]
+  fn roundtrips() {
+    for cursor in [Cursor::default(), Cursor::after(0, 0), Cursor::after(2, 1234)] {
+      assert_eq!(cursor.to_string().parse(), Ok(cursor));
+    }
+  }
+
+  #[test]
+  fn parses_query() {
+    assert_eq!(de("").unwrap(), Test { cursor: None });
+    assert_eq!(
+      de("cursor=").unwrap(),
+      Test {
+        cursor: Some(Cursor::default())
+      }
+    );
+    assert_eq!(
+      de("cursor=p1.1f").unwrap(),
+      Test {
+        cursor: Some(Cursor::after(1, 31))
+      }
+    );
+    assert!(de("cursor=1.1f").is_err());
+    assert!(de("cursor=p1f").is_err());
+    assert!(de("cursor=p0.-1").is_err());
+  }
+}
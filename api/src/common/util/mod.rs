@@ -1,2 +1,3 @@
+pub mod cursor;
 pub mod loose_bool;
 pub mod query_ext;
@@ -0,0 +1,235 @@
+use super::Database;
+use chrono::{DateTime, Duration, Utc};
+
+/// How the pending requests of a channel are ordered, pinned requests always come first
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum QueueMode {
+  /// In the order they were requested, requesting a queued song again is rejected
+  #[default]
+  Fifo,
+  /// By their number of upvotes, then in the order they were requested.
+  /// Requesting a queued song again upvotes it.
+  Votes,
+}
+
+/// Configuration of a channel
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct ChannelSettings {
+  pub channel: String,
+  /// Songs played within this many minutes can't be requested again
+  pub cooldown_minutes: Option<i32>,
+  /// Songs played within this many songs can't be requested again
+  pub cooldown_songs: Option<i32>,
+  /// A vote to skip the current song passes with this many votes
+  pub skip_votes: Option<i32>,
+  /// A vote to skip the current song passes once this percentage of the active chatters voted
+  pub skip_percent: Option<i32>,
+  #[serde(default)]
+  pub queue_mode: QueueMode,
+  /// How many pending requests a viewer can upvote at once
+  pub max_upvotes: Option<i32>,
+  /// Requests of more privileged roles are played first
+  #[serde(default)]
+  pub priority_by_role: bool,
+  /// Requests of subscribers and above are played in a separate lane, this many of them per regular request
+  pub subscriber_lane: Option<i32>,
+  /// How many of their requests a VIP can move to the front of the queue per stream
+  pub vip_tokens: Option<i32>,
+}
+
+impl ChannelSettings {
+  /// The defaults for channels which were never configured
+  pub fn new(channel: impl Into<String>) -> Self {
+    Self {
+      channel: channel.into(),
+      cooldown_minutes: None,
+      cooldown_songs: None,
+      skip_votes: None,
+      skip_percent: None,
+      queue_mode: QueueMode::Fifo,
+      max_upvotes: None,
+      priority_by_role: false,
+      subscriber_lane: None,
+      vip_tokens: None,
+    }
+  }
+
+  pub fn has_cooldown(&self) -> bool {
+    self.cooldown_minutes.is_some() || self.cooldown_songs.is_some()
+  }
+
+  pub fn has_vote_skip(&self) -> bool {
+    self.skip_votes.is_some() || self.skip_percent.is_some()
+  }
+
+  /// Number of votes required to skip a song, whichever threshold is reached first.
+  ///
+  /// `None` if vote-skip is disabled, or it only has a percentage threshold and `chatters` is unknown.
+  pub fn skip_threshold(&self, chatters: Option<i64>) -> Option<i64> {
+    let absolute = self.skip_votes.map(i64::from);
+    let relative = self
+      .skip_percent
+      .zip(chatters)
+      .map(|(percent, chatters)| (i64::from(percent) * chatters + 99) / 100);
+    let threshold = match (absolute, relative) {
+      (Some(absolute), Some(relative)) => absolute.min(relative),
+      (absolute, relative) => absolute.or(relative)?,
+    };
+    // a vote by nobody never passes
+    Some(threshold.max(1))
+  }
+
+  /// Why a song whose last play is `last` can't be requested yet, `None` if it can
+  pub fn cooldown_reason(&self, last: &LastPlay, now: DateTime<Utc>) -> Option<String> {
+    if let Some(minutes) = self.cooldown_minutes {
+      let available_at = last.started_at + Duration::minutes(minutes.into());
+      if now < available_at {
+        let remaining = ((available_at - now).num_seconds() + 59) / 60;
+        return Some(format!(
+          "This song was played recently, it can be requested again in {remaining} minute{}",
+          if remaining == 1 { "" } else { "s" }
+        ));
+      }
+    }
+    if let Some(songs) = self.cooldown_songs {
+      if last.plays_since < songs.into() {
+        let remaining = i64::from(songs) - last.plays_since;
+        return Some(format!(
+          "This song was played recently, it can be requested again after {remaining} more song{}",
+          if remaining == 1 { "" } else { "s" }
+        ));
+      }
+    }
+    None
+  }
+}
+
+/// The last time a song was played on a channel
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct LastPlay {
+  pub started_at: DateTime<Utc>,
+  /// How many songs were played on the channel since
+  pub plays_since: i64,
+}
+
+/// The settings of a channel, or the defaults if it was never configured
+pub async fn settings(db: &Database, channel: &str) -> sqlx::Result<ChannelSettings> {
+  let settings = sqlx::query_as(r#"SELECT * FROM channel_settings WHERE channel = $1"#)
+    .bind(channel)
+    .fetch_optional(db)
+    .await?;
+  Ok(settings.unwrap_or_else(|| ChannelSettings::new(channel)))
+}
+
+/// Insert or replace the settings of a channel
+pub async fn set_settings(db: &Database, settings: &ChannelSettings) -> sqlx::Result<ChannelSettings> {
+  sqlx::query_as(
+    r#"
+      INSERT INTO channel_settings (
+        channel, cooldown_minutes, cooldown_songs, skip_votes, skip_percent, queue_mode, max_upvotes,
+        priority_by_role, subscriber_lane, vip_tokens
+      )
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+      ON CONFLICT (channel) DO UPDATE SET
+        cooldown_minutes = EXCLUDED.cooldown_minutes,
+        cooldown_songs = EXCLUDED.cooldown_songs,
+        skip_votes = EXCLUDED.skip_votes,
+        skip_percent = EXCLUDED.skip_percent,
+        queue_mode = EXCLUDED.queue_mode,
+        max_upvotes = EXCLUDED.max_upvotes,
+        priority_by_role = EXCLUDED.priority_by_role,
+        subscriber_lane = EXCLUDED.subscriber_lane,
+        vip_tokens = EXCLUDED.vip_tokens
+      RETURNING *
+    "#,
+  )
+  .bind(&settings.channel)
+  .bind(settings.cooldown_minutes)
+  .bind(settings.cooldown_songs)
+  .bind(settings.skip_votes)
+  .bind(settings.skip_percent)
+  .bind(settings.queue_mode)
+  .bind(settings.max_upvotes)
+  .bind(settings.priority_by_role)
+  .bind(settings.subscriber_lane)
+  .bind(settings.vip_tokens)
+  .fetch_one(db)
+  .await
+}
+
+/// A stream of a channel
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct Stream {
+  pub channel: String,
+  pub started_at: DateTime<Utc>,
+}
+
+/// Record that a stream started on `channel`, handing out VIP tokens again
+pub async fn start_stream(db: &Database, channel: &str) -> sqlx::Result<Stream> {
+  sqlx::query_as(r#"INSERT INTO streams (channel) VALUES ($1) RETURNING channel, started_at"#)
+    .bind(channel)
+    .fetch_one(db)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn settings(cooldown_minutes: Option<i32>, cooldown_songs: Option<i32>) -> ChannelSettings {
+    ChannelSettings {
+      cooldown_minutes,
+      cooldown_songs,
+      ..ChannelSettings::new("test")
+    }
+  }
+
+  #[test]
+  fn cooldown_by_time() {
+    let now = Utc::now();
+    let last = |minutes_ago: i64| LastPlay {
+      started_at: now - Duration::minutes(minutes_ago),
+      plays_since: 100,
+    };
+    let settings = settings(Some(30), None);
+    assert_eq!(
+      settings.cooldown_reason(&last(10), now).as_deref(),
+      Some("This song was played recently, it can be requested again in 20 minutes")
+    );
+    assert!(settings.cooldown_reason(&last(30), now).is_none());
+    assert!(ChannelSettings::new("test").cooldown_reason(&last(0), now).is_none());
+  }
+
+  #[test]
+  fn cooldown_by_songs() {
+    let now = Utc::now();
+    let last = |plays_since: i64| LastPlay {
+      started_at: now - Duration::days(1),
+      plays_since,
+    };
+    let settings = settings(Some(30), Some(5));
+    assert_eq!(
+      settings.cooldown_reason(&last(4), now).as_deref(),
+      Some("This song was played recently, it can be requested again after 1 more song")
+    );
+    assert!(settings.cooldown_reason(&last(5), now).is_none());
+  }
+
+  #[test]
+  fn skip_threshold() {
+    let settings = |skip_votes, skip_percent| ChannelSettings {
+      skip_votes,
+      skip_percent,
+      ..ChannelSettings::new("test")
+    };
+    assert_eq!(settings(None, None).skip_threshold(Some(10)), None);
+    assert_eq!(settings(Some(5), None).skip_threshold(None), Some(5));
+    assert_eq!(settings(None, Some(50)).skip_threshold(None), None);
+    assert_eq!(settings(None, Some(50)).skip_threshold(Some(7)), Some(4));
+    assert_eq!(settings(Some(5), Some(50)).skip_threshold(Some(20)), Some(5));
+    assert_eq!(settings(Some(5), Some(50)).skip_threshold(Some(4)), Some(2));
+    assert_eq!(settings(None, Some(50)).skip_threshold(Some(0)), Some(1));
+  }
+}
@@ -0,0 +1,51 @@
+use super::Database;
+use crate::common::events::{ChannelEvent, ChannelEvents};
+use sqlx::postgres::PgListener;
+
+/// The Postgres channel events are published on, so that every API instance can forward them to its players
+const NOTIFY_CHANNEL: &str = "channel_events";
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Notification {
+  channel: String,
+  event: ChannelEvent,
+}
+
+/// Publish an event to the players of `channel`, on every API instance
+pub async fn publish(db: &Database, channel: &str, event: ChannelEvent) -> anyhow::Result<()> {
+  let payload = serde_json::to_string(&Notification {
+    channel: channel.to_owned(),
+    event,
+  })?;
+  sqlx::query(r#"SELECT pg_notify($1, $2)"#)
+    .bind(NOTIFY_CHANNEL)
+    .bind(payload)
+    .execute(db)
+    .await?;
+  Ok(())
+}
+
+/// Forward the published events to the players connected to this instance, until the listener can't connect.
+///
+/// Events published while the connection is lost are missed.
+pub async fn forward(db: Database, events: ChannelEvents) -> sqlx::Result<()> {
+  let mut listener = PgListener::connect_with(&db).await?;
+  listener.listen(NOTIFY_CHANNEL).await?;
+  loop {
+    // reconnects on the next call if the connection was lost
+    let notification = match listener.recv().await {
+      Ok(notification) => notification,
+      Err(e) => {
+        log::error!("Failed to receive channel events: {e:?}");
+        actix_rt::time::sleep(std::time::Duration::from_secs(1)).await;
+        continue;
+      }
+    };
+    match serde_json::from_str::<Notification>(notification.payload()) {
+      Ok(Notification { channel, event }) => {
+        events.send(&channel, event);
+      }
+      Err(e) => log::error!("Received an invalid channel event: {e:?}"),
+    }
+  }
+}
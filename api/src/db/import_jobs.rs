@@ -0,0 +1,200 @@
+use super::Database;
+use crate::common::platform::Platform;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Running jobs which haven't made progress for this long are considered dead
+const JOB_TIMEOUT_SECS: i64 = 5 * 60;
+
+/// Finished jobs are deleted once they haven't changed for this long
+const JOB_RETENTION_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum ImportStatus {
+  Running,
+  Done,
+  Failed,
+}
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow, getset::Getters)]
+#[getset(get = "pub")]
+pub struct ImportJob {
+  #[sqlx(rename = "job_id")]
+  id: Uuid,
+  platform: Platform,
+  #[sqlx(rename = "platform_playlist_id")]
+  playlist_id: String,
+  status: ImportStatus,
+  pages_fetched: i32,
+  /// Only known once the job is done, as the songs are stored all at once
+  #[serde(skip_serializing_if = "Option::is_none")]
+  songs_stored: Option<i32>,
+  errors: Vec<String>,
+  created_at: DateTime<Utc>,
+  updated_at: DateTime<Utc>,
+}
+
+pub async fn get(db: &Database, id: Uuid) -> sqlx::Result<Option<ImportJob>> {
+  sqlx::query_as(r#"SELECT * FROM import_jobs WHERE job_id = $1"#)
+    .bind(id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Start an import of a playlist, unless one is already running
+///
+/// - Fails running jobs which timed out
+/// - Deletes finished jobs which are older than the retention period
+/// - Creates a `running` job, or returns the one which is already running
+///
+/// Returns `true` alongside the job if it was created.
+pub async fn start(db: &Database, platform: Platform, playlist_id: &str) -> sqlx::Result<(ImportJob, bool)> {
+  let mut tx = db.begin().await?;
+
+  sqlx::query(
+    r#"
+      UPDATE import_jobs
+      SET status = 'failed', errors = array_append(errors, 'Timed out'), updated_at = now()
+      WHERE status = 'running' AND updated_at < now() - make_interval(secs => $1)
+    "#,
+  )
+  .bind(JOB_TIMEOUT_SECS as f64)
+  .execute(&mut tx)
+  .await?;
+
+  sqlx::query(
+    r#"
+      DELETE FROM import_jobs
+      WHERE status <> 'running' AND updated_at < now() - make_interval(secs => $1)
+    "#,
+  )
+  .bind(JOB_RETENTION_SECS as f64)
+  .execute(&mut tx)
+  .await?;
+
+  let created: Option<ImportJob> = sqlx::query_as(
+    r#"
+      INSERT INTO import_jobs (job_id, platform, platform_playlist_id, status)
+      VALUES ($1, $2, $3, 'running')
+      ON CONFLICT (platform, platform_playlist_id) WHERE status = 'running' DO NOTHING
+      RETURNING *
+    "#,
+  )
+  .bind(Uuid::new_v4())
+  .bind(platform)
+  .bind(playlist_id)
+  .fetch_optional(&mut tx)
+  .await?;
+  let result = match created {
+    Some(job) => (job, true),
+    None => (
+      sqlx::query_as(
+        r#"
+          SELECT * FROM import_jobs
+          WHERE (platform, platform_playlist_id) = ($1, $2) AND status = 'running'
+        "#,
+      )
+      .bind(platform)
+      .bind(playlist_id)
+      .fetch_one(&mut tx)
+      .await?,
+      false,
+    ),
+  };
+
+  tx.commit().await?;
+  Ok(result)
+}
+
+pub async fn progress(db: &Database, id: Uuid, pages_fetched: i32) -> sqlx::Result<()> {
+  sqlx::query(r#"UPDATE import_jobs SET pages_fetched = $2, updated_at = now() WHERE job_id = $1"#)
+    .bind(id)
+    .bind(pages_fetched)
+    .execute(db)
+    .await?;
+  Ok(())
+}
+
+pub async fn finish(db: &Database, id: Uuid, songs_stored: i32) -> sqlx::Result<()> {
+  sqlx::query(
+    r#"
+      UPDATE import_jobs
+      SET status = 'done', songs_stored = $2, updated_at = now()
+      WHERE job_id = $1
+    "#,
+  )
+  .bind(id)
+  .bind(songs_stored)
+  .execute(db)
+  .await?;
+  Ok(())
+}
+
+pub async fn fail(db: &Database, id: Uuid, error: &str) -> sqlx::Result<()> {
+  sqlx::query(
+    r#"
+      UPDATE import_jobs
+      SET status = 'failed', errors = array_append(errors, $2), updated_at = now()
+      WHERE job_id = $1
+    "#,
+  )
+  .bind(id)
+  .bind(error)
+  .execute(db)
+  .await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  crate::db_test!(job_lifecycle, db, prefix, cleanup [r#"DELETE FROM import_jobs WHERE starts_with(platform_playlist_id, $1)"#] {
+    let (playlist, other) = (format!("{prefix}-0"), format!("{prefix}-1"));
+
+    let (job, created) = start(db, Platform::Youtube, &playlist).await?;
+    assert!(created);
+    assert_eq!(job.status, ImportStatus::Running);
+    // a running import is joined instead of starting another one
+    let (joined, created) = start(db, Platform::Youtube, &playlist).await?;
+    assert!(!created);
+    assert_eq!(joined.id, job.id);
+
+    progress(db, job.id, 2).await?;
+    let running = get(db, job.id).await?.unwrap();
+    assert_eq!(running.pages_fetched, 2);
+    assert_eq!(running.songs_stored, None);
+
+    finish(db, job.id, 10).await?;
+    let done = get(db, job.id).await?.unwrap();
+    assert_eq!(done.status, ImportStatus::Done);
+    assert_eq!(done.songs_stored, Some(10));
+
+    // once the import finished, the next one starts a new job
+    let (next, created) = start(db, Platform::Youtube, &playlist).await?;
+    assert!(created);
+    assert_ne!(next.id, job.id);
+    fail(db, next.id, "Failed to fetch playlist").await?;
+    let failed = get(db, next.id).await?.unwrap();
+    assert_eq!(failed.status, ImportStatus::Failed);
+    assert_eq!(failed.errors, ["Failed to fetch playlist"]);
+    assert_eq!(failed.songs_stored, None);
+
+    // finished jobs are deleted once they are past the retention period
+    sqlx::query(r#"UPDATE import_jobs SET updated_at = now() - interval '2 days' WHERE job_id = $1"#)
+      .bind(job.id)
+      .execute(db)
+      .await?;
+    start(db, Platform::Youtube, &other).await?;
+    let remaining: Vec<Uuid> =
+      sqlx::query_scalar(r#"SELECT job_id FROM import_jobs WHERE platform_playlist_id IN ($1, $2) ORDER BY created_at"#)
+        .bind(&playlist)
+        .bind(&other)
+        .fetch_all(db)
+        .await?;
+    assert_eq!(remaining.len(), 2);
+    assert!(!remaining.contains(&job.id));
+  });
+}
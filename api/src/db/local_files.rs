@@ -0,0 +1,249 @@
+use super::{songs::*, Database};
+use crate::common::platform::Platform;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, getset::Getters)]
+#[getset(get = "pub")]
+pub struct LocalFileData {
+  song: SongData,
+  size: i64,
+  modified_at: DateTime<Utc>,
+}
+
+impl LocalFileData {
+  pub fn new(song: SongData, size: i64, modified_at: DateTime<Utc>) -> Self {
+    Self {
+      song,
+      size,
+      modified_at,
+    }
+  }
+}
+
+/// Number of files affected by a [`sync`]
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct SyncSummary {
+  pub added: usize,
+  pub removed: usize,
+  pub changed: usize,
+}
+
+/// Whether the file `id` is at or under one of the `failed` paths of a scan
+fn is_under(id: &str, failed: &[String]) -> bool {
+  failed.iter().any(|path| {
+    path.is_empty() || id == path || id.strip_prefix(path.as_str()).is_some_and(|rest| rest.starts_with('/'))
+  })
+}
+
+/// Split `scanned` into files which are new or changed since the last sync,
+/// and return the ids of known files which are missing from it, except for those under the `failed` paths of the scan.
+fn diff(
+  known: HashMap<String, (i64, DateTime<Utc>)>,
+  scanned: Vec<LocalFileData>,
+  failed: &[String],
+) -> (Vec<LocalFileData>, Vec<LocalFileData>, Vec<String>) {
+  let mut known = known;
+  let mut added = vec![];
+  let mut changed = vec![];
+  for file in scanned {
+    match known.remove(file.song.song_id()) {
+      None => added.push(file),
+      Some((size, modified_at)) if (size, modified_at) != (file.size, file.modified_at) => changed.push(file),
+      Some(_) => {}
+    }
+  }
+  let removed = known.into_keys().filter(|id| !is_under(id, failed)).collect();
+  (added, changed, removed)
+}
+
+/// Size and modification time of every known file, by id
+async fn known(conn: &mut PgConnection) -> sqlx::Result<HashMap<String, (i64, DateTime<Utc>)>> {
+  Ok(
+    sqlx::query_as::<_, (String, i64, DateTime<Utc>)>(
+      r#"
+        SELECT songs.platform_song_id, local_files.size, local_files.modified_at
+        FROM local_files
+        JOIN songs ON songs.song_id = local_files.song_id
+      "#,
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|(id, size, modified_at)| (id, (size, modified_at)))
+    .collect(),
+  )
+}
+
+/// Mark the songs of removed files as unavailable, keeping their plays, and forget the files
+async fn remove(conn: &mut PgConnection, ids: &[String]) -> sqlx::Result<()> {
+  sqlx::query(
+    r#"
+      WITH removed AS (
+        UPDATE songs SET availability = $3
+        WHERE platform = $1 AND platform_song_id = ANY($2::text[])
+        RETURNING song_id
+      )
+      DELETE FROM local_files
+      WHERE song_id IN (SELECT song_id FROM removed)
+    "#,
+  )
+  .bind(Platform::Local)
+  .bind(ids)
+  .bind(Availability::Unavailable)
+  .execute(conn)
+  .await?;
+  Ok(())
+}
+
+/// Insert or update the songs of new and changed files, making returning files available again
+async fn store(conn: &mut PgConnection, files: Vec<LocalFileData>) -> sqlx::Result<()> {
+  let size = files.iter().map(|f| f.size).collect::<Vec<_>>();
+  let modified_at = files.iter().map(|f| f.modified_at).collect::<Vec<_>>();
+  let songs = SongData::soa(files.into_iter().map(|f| f.song).collect());
+  sqlx::query(
+    r#"
+      WITH
+      upserted AS (
+        INSERT INTO songs (published_at, platform, platform_song_id, title, artist, duration)
+        SELECT * FROM UNNEST($1::timestamptz[], $2::text[], $3::text[], $4::text[], $5::text[], $6::int4[])
+        ON CONFLICT (platform, platform_song_id) DO UPDATE
+          SET published_at = excluded.published_at,
+              title = excluded.title,
+              artist = excluded.artist,
+              duration = excluded.duration,
+              availability = 'available'
+        RETURNING song_id, platform_song_id
+      ),
+      files AS (
+        SELECT * FROM UNNEST($3::text[], $7::int8[], $8::timestamptz[]) AS f (platform_song_id, size, modified_at)
+      )
+      INSERT INTO local_files (song_id, size, modified_at)
+      SELECT upserted.song_id, files.size, files.modified_at
+      FROM upserted
+      JOIN files ON files.platform_song_id = upserted.platform_song_id
+      ON CONFLICT (song_id) DO UPDATE
+        SET size = excluded.size,
+            modified_at = excluded.modified_at
+    "#,
+  )
+  .bind(&songs.published_at)
+  .bind(&songs.platform)
+  .bind(&songs.song_id)
+  .bind(&songs.title)
+  .bind(&songs.artist)
+  .bind(&songs.duration)
+  .bind(&size)
+  .bind(&modified_at)
+  .execute(conn)
+  .await?;
+  Ok(())
+}
+
+/// Synchronize the `local` songs with the result of a library scan
+///
+/// - Marks `songs` of files which no longer exist as unavailable, keeping their plays.
+///   Files under the `failed` paths of the scan are left as they are
+/// - Inserts `songs` of new files, and updates the metadata of changed files, making returning files available again
+/// - Records the size and modification time of every file in `local_files`
+///
+pub async fn sync(db: &Database, scanned: Vec<LocalFileData>, failed: &[String]) -> sqlx::Result<SyncSummary> {
+  let mut tx = db.begin().await?;
+
+  let (added, changed, removed) = diff(known(&mut tx).await?, scanned, failed);
+  let summary = SyncSummary {
+    added: added.len(),
+    removed: removed.len(),
+    changed: changed.len(),
+  };
+  remove(&mut tx, &removed).await?;
+  store(&mut tx, added.into_iter().chain(changed).collect()).await?;
+
+  tx.commit().await?;
+  Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db;
+  use chrono::Duration;
+
+  fn file(id: &str, size: i64, modified_at: DateTime<Utc>) -> LocalFileData {
+    LocalFileData::new(
+      SongData::new(modified_at, id.into(), Platform::Local, id.into()),
+      size,
+      modified_at,
+    )
+  }
+
+  #[test]
+  fn diff_scan_with_known_files() {
+    let now = Utc::now();
+    let known = HashMap::from([
+      ("unchanged".to_string(), (10, now)),
+      ("resized".to_string(), (10, now)),
+      ("touched".to_string(), (10, now)),
+      ("removed".to_string(), (10, now)),
+    ]);
+    let scanned = vec![
+      file("unchanged", 10, now),
+      file("resized", 20, now),
+      file("touched", 10, now + Duration::seconds(1)),
+      file("added", 10, now),
+    ];
+
+    let (added, changed, removed) = diff(known, scanned, &[]);
+    let ids = |v: &[LocalFileData]| v.iter().map(|f| f.song.song_id().clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&added), vec!["added"]);
+    assert_eq!(ids(&changed), vec!["resized", "touched"]);
+    assert_eq!(removed, vec!["removed"]);
+  }
+
+  #[test]
+  fn diff_keeps_files_under_failed_paths() {
+    let now = Utc::now();
+    let known = ["album/a.mp3", "album/b.mp3", "albums/c.mp3", "broken.mp3", "d.mp3"]
+      .into_iter()
+      .map(|id| (id.to_string(), (10, now)))
+      .collect::<HashMap<_, _>>();
+
+    let (_, _, mut removed) = diff(known.clone(), vec![], &["album".into(), "broken.mp3".into()]);
+    removed.sort();
+    assert_eq!(removed, vec!["albums/c.mp3", "d.mp3"]);
+    // the root itself failed
+    let (_, _, removed) = diff(known, vec![], &["".into()]);
+    assert!(removed.is_empty());
+  }
+
+  crate::db_test!(removed_files_keep_their_plays, tx {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now();
+    store(&mut tx, vec![file(&id, 10, now)]).await?;
+    let song = db::songs::get(&mut tx, Platform::Local, &id).await?.unwrap();
+    assert!(known(&mut tx).await?.contains_key(&id));
+    sqlx::query(r#"INSERT INTO plays (channel, song_id) VALUES ($1, $2)"#)
+      .bind(&id)
+      .bind(song.id())
+      .execute(&mut tx)
+      .await?;
+
+    remove(&mut tx, std::slice::from_ref(&id)).await?;
+    let removed = db::songs::get(&mut tx, Platform::Local, &id).await?.unwrap();
+    assert_eq!(removed.availability(), &Availability::Unavailable);
+    assert!(!known(&mut tx).await?.contains_key(&id));
+    let plays: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM plays WHERE song_id = $1"#)
+      .bind(song.id())
+      .fetch_one(&mut tx)
+      .await?;
+    assert_eq!(plays, 1);
+
+    // the file returned
+    store(&mut tx, vec![file(&id, 10, now)]).await?;
+    let returned = db::songs::get(&mut tx, Platform::Local, &id).await?.unwrap();
+    assert_eq!(returned.id(), song.id());
+    assert_eq!(returned.availability(), &Availability::Available);
+    assert!(known(&mut tx).await?.contains_key(&id));
+  });
+}
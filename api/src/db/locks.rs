@@ -0,0 +1,70 @@
+use sqlx::PgConnection;
+
+/// Namespaces of advisory locks, used as the first key of `pg_try_advisory_lock(int, int)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(i32)]
+pub enum LockClass {
+  /// Keyed by `playlists.playlist_id`
+  PlaylistRefresh = 1,
+  /// Keyed by `AVAILABILITY_CHECK_KEY`, there is only ever one check at a time
+  AvailabilityCheck = 2,
+  /// Keyed by `STATS_REFRESH_KEY`, there is only ever one refresh at a time
+  StatsRefresh = 3,
+  /// Keyed by the hash of a channel and one of its viewers, see [`lock_xact`]
+  QueueViewer = 4,
+}
+
+/// The only key of `LockClass::AvailabilityCheck`
+pub const AVAILABILITY_CHECK_KEY: i32 = 0;
+
+/// The only key of `LockClass::StatsRefresh`
+pub const STATS_REFRESH_KEY: i32 = 0;
+
+/// Try to acquire a session-level advisory lock, without waiting for it.
+///
+/// The lock is held by `conn` until [`unlock`] is called, or the connection is closed.
+pub async fn try_lock(conn: &mut PgConnection, class: LockClass, key: i32) -> sqlx::Result<bool> {
+  sqlx::query_scalar(r#"SELECT pg_try_advisory_lock($1, $2)"#)
+    .bind(class as i32)
+    .bind(key)
+    .fetch_one(conn)
+    .await
+}
+
+/// Release a lock acquired through [`try_lock`] on the same connection.
+pub async fn unlock(conn: &mut PgConnection, class: LockClass, key: i32) -> sqlx::Result<bool> {
+  sqlx::query_scalar(r#"SELECT pg_advisory_unlock($1, $2)"#)
+    .bind(class as i32)
+    .bind(key)
+    .fetch_one(conn)
+    .await
+}
+
+/// Acquire a transaction-level advisory lock keyed by the hash of `key`, waiting for it.
+///
+/// The lock is held until the transaction of `conn` ends. Distinct keys may share a hash, and thereby a lock.
+pub async fn lock_xact(conn: &mut PgConnection, class: LockClass, key: &str) -> sqlx::Result<()> {
+  sqlx::query(r#"SELECT pg_advisory_xact_lock($1, hashtext($2))"#)
+    .bind(class as i32)
+    .bind(key)
+    .execute(conn)
+    .await?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db;
+
+  crate::db_test!(lock_is_exclusive_across_connections, tx {
+    let pool = db::connect_from_env().await?;
+    let mut other = pool.acquire().await?;
+
+    assert!(try_lock(&mut tx, LockClass::PlaylistRefresh, -1).await?);
+    assert!(!try_lock(&mut other, LockClass::PlaylistRefresh, -1).await?);
+    assert!(unlock(&mut tx, LockClass::PlaylistRefresh, -1).await?);
+    assert!(try_lock(&mut other, LockClass::PlaylistRefresh, -1).await?);
+    assert!(unlock(&mut other, LockClass::PlaylistRefresh, -1).await?);
+  });
+}
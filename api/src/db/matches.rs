@@ -0,0 +1,81 @@
+use super::songs::Song;
+use crate::common::platform::Platform;
+
+/// Look up a cached match of the song with `song_id` on the `target` platform
+///
+/// - `None` if no match was attempted yet, or nothing was found longer than `miss_ttl` ago
+/// - `Some(None)` if a match was attempted, but nothing was found
+pub async fn get<'db, E>(
+  db: E,
+  song_id: i32,
+  target: Platform,
+  miss_ttl: chrono::Duration,
+) -> sqlx::Result<Option<Option<Song>>>
+where
+  E: sqlx::PgExecutor<'db> + 'db + Copy,
+{
+  let matched = sqlx::query_scalar::<_, Option<i32>>(
+    r#"
+      SELECT matched_song_id FROM song_matches
+      WHERE (song_id, target_platform) = ($1, $2)
+        AND (matched_song_id IS NOT NULL OR matched_at > now() - make_interval(secs => $3))
+    "#,
+  )
+  .bind(song_id)
+  .bind(target)
+  .bind(miss_ttl.num_seconds() as f64)
+  .fetch_optional(db)
+  .await?;
+  Ok(match matched {
+    Some(Some(matched_song_id)) => Some(
+      sqlx::query_as(r#"SELECT * FROM songs WHERE song_id = $1"#)
+        .bind(matched_song_id)
+        .fetch_optional(db)
+        .await?,
+    ),
+    Some(None) => Some(None),
+    None => None,
+  })
+}
+
+/// Cache the match of the song with `song_id` on the `target` platform, or the lack of one
+pub async fn set<'db, E>(db: E, song_id: i32, target: Platform, matched_song_id: Option<i32>) -> sqlx::Result<()>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query(
+    r#"
+      INSERT INTO song_matches (song_id, target_platform, matched_song_id)
+      VALUES ($1, $2, $3)
+      ON CONFLICT (song_id, target_platform) DO UPDATE
+        SET matched_song_id = excluded.matched_song_id,
+            matched_at = now()
+    "#,
+  )
+  .bind(song_id)
+  .bind(target)
+  .bind(matched_song_id)
+  .execute(db)
+  .await?;
+  Ok(())
+}
+
+/// Known songs on `platform` with a title similar to `query`, most similar first
+pub async fn candidates<'db, E>(db: E, platform: Platform, query: &str, limit: i64) -> sqlx::Result<Vec<Song>>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query_as(
+    r#"
+      SELECT * FROM songs
+      WHERE platform = $1 AND title % $2 AND availability = 'available'
+      ORDER BY similarity(title, $2) DESC
+      LIMIT $3
+    "#,
+  )
+  .bind(platform)
+  .bind(query)
+  .bind(limit)
+  .fetch_all(db)
+  .await
+}
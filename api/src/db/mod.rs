@@ -1,5 +1,16 @@
+pub mod channels;
+pub mod events;
+pub mod import_jobs;
+pub mod local_files;
+pub mod locks;
+pub mod matches;
+pub mod playback_failures;
 pub mod playlists;
+pub mod plays;
+pub mod queue;
+pub mod skip_votes;
 pub mod songs;
+pub mod stats;
 
 pub type Database = sqlx::PgPool;
 
@@ -39,10 +50,66 @@ pub async fn connect_from_env() -> sqlx::Result<Database> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+  use super::{connect_from_env, Database};
+
+  /// Connection for tests of functions which commit their own transactions
+  ///
+  /// The rows of a test are keyed by `key`, and deleted by the `cleanup` statements (with `key` as `$1`)
+  /// once the fixture is dropped, also when the test fails.
+  pub struct Fixture {
+    pub db: Database,
+    pub key: String,
+    cleanup: &'static [&'static str],
+  }
+
+  impl Fixture {
+    pub async fn new(key: String, cleanup: &'static [&'static str]) -> sqlx::Result<Self> {
+      Ok(Self {
+        db: connect_from_env().await?,
+        key,
+        cleanup,
+      })
+    }
+  }
+
+  impl Drop for Fixture {
+    fn drop(&mut self) {
+      let (key, cleanup) = (self.key.clone(), self.cleanup);
+      // the runtime of the test can't be blocked on, so the cleanup runs on its own
+      let result = std::thread::spawn(move || {
+        actix_rt::System::new().block_on(async move {
+          let db = connect_from_env().await?;
+          for statement in cleanup {
+            sqlx::query(statement).bind(&key).execute(&db).await?;
+          }
+          sqlx::Result::Ok(())
+        })
+      })
+      .join();
+      match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("Failed to clean up the rows of {}: {e}", self.key),
+        Err(_) => eprintln!("Failed to clean up the rows of {}", self.key),
+      }
+    }
+  }
+
   // TODO: connect to db without name, start a new logical db per test
   #[macro_export]
   macro_rules! db_test {
+    ($name:ident, $db:ident, $key:ident $(= $init:expr)?, cleanup [$($cleanup:expr),* $(,)?] $body:block) => {
+      #[actix_rt::test]
+      #[cfg_attr(not(feature = "test-database"), ignore)]
+      async fn $name() -> anyhow::Result<()> {
+        let fixture = $crate::db::tests::Fixture::new($crate::db_test!(@key $($init)?), &[$($cleanup),*]).await?;
+        let ($db, $key) = (&fixture.db, fixture.key.clone());
+
+        $body
+
+        Ok(())
+      }
+    };
     ($name:ident, $tx:ident $body:block) => {
       #[actix_rt::test]
       #[cfg_attr(not(feature = "test-database"), ignore)]
@@ -55,6 +122,12 @@ mod tests {
         $tx.rollback().await?;
         Ok(())
       }
-    }
+    };
+    (@key) => {
+      uuid::Uuid::new_v4().to_string()
+    };
+    (@key $init:expr) => {
+      $init
+    };
   }
 }
@@ -0,0 +1,163 @@
+use super::{
+  songs::{Availability, Song},
+  Database,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, Row};
+
+/// YouTube player error codes which mean that the video can never be played:
+/// it doesn't exist or is private (`100`), or its owner disabled embedding (`101`, `150`)
+const PERMANENT_ERROR_CODES: &[i32] = &[100, 101, 150];
+
+/// Repeated reports of the same song by the same channel within this window only count once
+const REPORT_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// The failures reported for a song after recording a new one
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct ReportSummary {
+  pub reports: i64,
+  pub availability: Availability,
+}
+
+/// A song which was flagged as unplayable, with its reported failures
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FlaggedSong {
+  #[serde(flatten)]
+  pub song: Song,
+  pub reports: i64,
+  /// Every distinct error code which was reported
+  pub error_codes: Vec<i32>,
+  pub last_reported_at: DateTime<Utc>,
+}
+
+impl<'r> sqlx::FromRow<'r, PgRow> for FlaggedSong {
+  fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+    Ok(Self {
+      song: Song::from_row(row)?,
+      reports: row.try_get("reports")?,
+      error_codes: row.try_get("error_codes")?,
+      last_reported_at: row.try_get("last_reported_at")?,
+    })
+  }
+}
+
+/// Record a failure of `channel`'s player to play a song
+///
+/// - Inserts the report, unless `channel` already reported the song within the report window,
+///   with an error code which is permanent if and only if this one is
+/// - Marks an available song as `Unplayable` once it has at least `threshold` reports with permanent error codes
+pub async fn report(
+  db: &Database,
+  song_id: i32,
+  channel: &str,
+  error_code: i32,
+  threshold: i64,
+) -> sqlx::Result<ReportSummary> {
+  let mut tx = db.begin().await?;
+
+  // serializes concurrent reports of the song, so that they are deduplicated
+  sqlx::query(r#"SELECT 1 FROM songs WHERE song_id = $1 FOR UPDATE"#)
+    .bind(song_id)
+    .execute(&mut tx)
+    .await?;
+  sqlx::query(
+    r#"
+      INSERT INTO playback_failures (song_id, channel, error_code)
+      SELECT $1, $2, $3
+      WHERE NOT EXISTS (
+        SELECT FROM playback_failures
+        WHERE song_id = $1 AND channel = $2 AND reported_at > now() - make_interval(secs => $4)
+          AND (error_code = ANY($5)) = ($3 = ANY($5))
+      )
+    "#,
+  )
+  .bind(song_id)
+  .bind(channel)
+  .bind(error_code)
+  .bind(REPORT_WINDOW_SECS as f64)
+  .bind(PERMANENT_ERROR_CODES)
+  .execute(&mut tx)
+  .await?;
+
+  let summary = sqlx::query_as(
+    r#"
+      WITH reports AS (
+        SELECT COUNT(*) AS reports FROM playback_failures WHERE song_id = $1 AND error_code = ANY($3)
+      )
+      UPDATE songs
+      SET availability = CASE
+        WHEN availability = 'available' AND (SELECT reports FROM reports) >= $2 THEN 'unplayable'
+        ELSE availability
+      END
+      WHERE song_id = $1
+      RETURNING (SELECT reports FROM reports), availability
+    "#,
+  )
+  .bind(song_id)
+  .bind(threshold)
+  .bind(PERMANENT_ERROR_CODES)
+  .fetch_one(&mut tx)
+  .await?;
+
+  tx.commit().await?;
+  Ok(summary)
+}
+
+/// Songs flagged as unplayable, most recently reported first.
+///
+/// Only reports with permanent error codes are counted, but every reported error code is listed.
+pub async fn flagged(db: &Database) -> sqlx::Result<Vec<FlaggedSong>> {
+  sqlx::query_as(
+    r#"
+      SELECT
+        songs.*,
+        COUNT(*) FILTER (WHERE error_code = ANY($1)) AS reports,
+        array_agg(DISTINCT error_code) AS error_codes,
+        max(reported_at) AS last_reported_at
+      FROM songs
+      JOIN playback_failures USING (song_id)
+      WHERE availability = 'unplayable'
+      GROUP BY song_id
+      ORDER BY last_reported_at DESC
+    "#,
+  )
+  .bind(PERMANENT_ERROR_CODES)
+  .fetch_all(db)
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    common::platform::Platform,
+    db::{self, songs::SongData},
+  };
+
+  crate::db_test!(report_counts_permanent_failures_once_per_channel, db, youtube_id, cleanup [r#"DELETE FROM songs WHERE platform_song_id = $1"#] {
+    let song = db::songs::create(
+      db,
+      SongData::new(Utc::now(), youtube_id, Platform::Youtube, "test".into()),
+    )
+    .await?;
+    let id = *song.id();
+
+    // transient errors are recorded, but don't count
+    assert_eq!(report(db, id, "a", 5, 2).await?.reports, 0);
+    // repeated reports by the same channel count once
+    assert_eq!(report(db, id, "a", 150, 2).await?.reports, 1);
+    let summary = report(db, id, "a", 101, 2).await?;
+    assert_eq!(summary.reports, 1);
+    assert_eq!(summary.availability, Availability::Available);
+    // the threshold is reached once another channel reports it
+    let summary = report(db, id, "b", 100, 2).await?;
+    assert_eq!(summary.reports, 2);
+    assert_eq!(summary.availability, Availability::Unplayable);
+
+    let stored: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM playback_failures WHERE song_id = $1"#)
+      .bind(id)
+      .fetch_one(db)
+      .await?;
+    assert_eq!(stored, 3);
+  });
+}
@@ -1,23 +1,67 @@
 use super::{songs::*, Database};
 use crate::common::platform::Platform;
 use chrono::{DateTime, Utc};
+use sqlx::{
+  postgres::{PgArguments, PgRow},
+  query::QueryAs,
+  Postgres, Row,
+};
+use std::collections::HashSet;
 
-#[derive(Debug, Clone, sqlx::FromRow, getset::Getters)]
+/// Changes of playlists are deleted once they are older than this
+const CHANGE_RETENTION_DAYS: i32 = 30;
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow, getset::Getters)]
 #[getset(get = "pub")]
 pub struct Playlist {
+  #[serde(skip)]
   #[sqlx(rename = "playlist_id")]
   id: i32,
   updated_at: DateTime<Utc>,
   platform: Platform,
+  #[serde(rename = "id")]
   #[sqlx(rename = "platform_playlist_id")]
   playlist_id: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  title: Option<String>,
+  /// Name of the channel or user who owns the playlist
+  #[serde(skip_serializing_if = "Option::is_none")]
+  owner: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  owner_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  thumbnail_url: Option<String>,
+  /// Number of items as reported by the platform, which may include unavailable songs
+  #[serde(skip_serializing_if = "Option::is_none")]
+  item_count: Option<i32>,
 }
 
-#[derive(Debug, Clone)]
+/// Details of a playlist as reported by its platform
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistMetadata {
+  pub title: String,
+  pub owner: String,
+  pub owner_id: String,
+  pub thumbnail_url: Option<String>,
+  pub item_count: i32,
+}
+
+/// A page of playlist items as it was last fetched, allowing it to be fetched conditionally
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct PlaylistPage {
+  pub etag: String,
+  pub next_page_token: Option<String>,
+  pub video_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, getset::Getters)]
+#[getset(get = "pub")]
 pub struct PlaylistData {
   platform: Platform,
   playlist_id: String,
   songs: Vec<SongData>,
+  pages: Vec<PlaylistPage>,
+  metadata: Option<PlaylistMetadata>,
 }
 
 impl PlaylistData {
@@ -26,8 +70,20 @@ impl PlaylistData {
       platform,
       playlist_id: id,
       songs,
+      pages: vec![],
+      metadata: None,
     }
   }
+
+  pub fn with_pages(mut self, pages: Vec<PlaylistPage>) -> Self {
+    self.pages = pages;
+    self
+  }
+
+  pub fn with_metadata(mut self, metadata: Option<PlaylistMetadata>) -> Self {
+    self.metadata = metadata;
+    self
+  }
 }
 
 pub async fn get(db: &Database, platform: Platform, id: &str) -> sqlx::Result<Option<Playlist>> {
@@ -38,104 +94,695 @@ pub async fn get(db: &Database, platform: Platform, id: &str) -> sqlx::Result<Op
     .await
 }
 
+/// Playlists which were last updated before `updated_before`, least recently updated first
+pub async fn stale<'db, E>(db: E, updated_before: DateTime<Utc>, limit: i64) -> sqlx::Result<Vec<Playlist>>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query_as(
+    r#"
+      SELECT * FROM playlists
+      WHERE updated_at < $1 AND (retry_at IS NULL OR retry_at < now())
+      ORDER BY updated_at
+      LIMIT $2
+    "#,
+  )
+  .bind(updated_before)
+  .bind(limit)
+  .fetch_all(db)
+  .await
+}
+
+/// Record that refreshing a playlist in the background failed, so that `stale` skips it for a while.
+///
+/// The delay starts at `base_delay` and doubles with every consecutive failure, up to `max_delay`.
+pub async fn refresh_failed<'db, E>(
+  db: E,
+  id: i32,
+  base_delay: chrono::Duration,
+  max_delay: chrono::Duration,
+) -> sqlx::Result<()>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query(
+    r#"
+      UPDATE playlists SET
+        refresh_failures = refresh_failures + 1,
+        retry_at = now() + LEAST(
+          make_interval(secs => $2) * power(2, LEAST(refresh_failures, 30)),
+          make_interval(secs => $3)
+        )
+      WHERE playlist_id = $1
+    "#,
+  )
+  .bind(id)
+  .bind(base_delay.num_seconds() as f64)
+  .bind(max_delay.num_seconds() as f64)
+  .execute(db)
+  .await?;
+  Ok(())
+}
+
+/// The pages of a playlist as they were last fetched, in order
+pub async fn pages(db: &Database, platform: Platform, id: &str) -> sqlx::Result<Vec<PlaylistPage>> {
+  sqlx::query_as(
+    r#"
+      SELECT etag, next_page_token, video_ids FROM playlist_pages
+      WHERE playlist_id = (
+        SELECT playlist_id FROM playlists
+        WHERE (platform, platform_playlist_id) = ($1, $2)
+      )
+      ORDER BY page
+    "#,
+  )
+  .bind(platform.as_str())
+  .bind(id)
+  .fetch_all(db)
+  .await
+}
+
+/// Mark a playlist as up to date without changing its songs, returning how many songs it has
+///
+/// The stored metadata is only replaced if `metadata` is given.
+pub async fn touch(
+  db: &Database,
+  platform: Platform,
+  id: &str,
+  metadata: Option<&PlaylistMetadata>,
+) -> sqlx::Result<i64> {
+  sqlx::query_scalar(
+    r#"
+      UPDATE playlists SET
+        updated_at = now(),
+        refresh_failures = 0,
+        retry_at = NULL,
+        title = COALESCE($3, title),
+        owner = COALESCE($4, owner),
+        owner_id = COALESCE($5, owner_id),
+        thumbnail_url = CASE WHEN $3 IS NULL THEN thumbnail_url ELSE $6 END,
+        item_count = COALESCE($7, item_count)
+      WHERE (platform, platform_playlist_id) = ($1, $2)
+      RETURNING (SELECT COUNT(*) FROM playlists_songs WHERE playlist_id = playlists.playlist_id)
+    "#,
+  )
+  .bind(platform.as_str())
+  .bind(id)
+  .bind(metadata.map(|m| &m.title))
+  .bind(metadata.map(|m| &m.owner))
+  .bind(metadata.map(|m| &m.owner_id))
+  .bind(metadata.and_then(|m| m.thumbnail_url.as_ref()))
+  .bind(metadata.map(|m| m.item_count))
+  .fetch_one(db)
+  .await
+}
+
+/// Split the songs of two versions of a playlist into the ones which were added and removed.
+///
+/// Songs which are in a playlist more than once are only reported once,
+/// and only if they were added or removed entirely.
+fn diff(previous: &[i32], current: &[i32]) -> (Vec<i32>, Vec<i32>) {
+  let previous_set = previous.iter().copied().collect::<HashSet<_>>();
+  let current_set = current.iter().copied().collect::<HashSet<_>>();
+  let mut seen = HashSet::new();
+  let added = current
+    .iter()
+    .copied()
+    .filter(|id| !previous_set.contains(id) && seen.insert(*id))
+    .collect();
+  let removed = previous
+    .iter()
+    .copied()
+    .filter(|id| !current_set.contains(id) && seen.insert(*id))
+    .collect();
+  (added, removed)
+}
+
 /// Insert or update a playlist
 ///
 /// - Creates a `playlists` table entry, or sets its `updated_at = now()` on conflict
+/// - Stores `PlaylistData.metadata`, keeping the stored metadata if there is none
 /// - Inserts new songs from `PlaylistData.songs`
 /// - Deletes all `playlists_songs` entries with `playlist_id = playlist.id`
-/// - Creates `playlists_songs` entries, joining `playlist.id` with every `id` in new songs
+/// - Creates `playlists_songs` entries, joining `playlist.id` with every `id` in new songs at its position,
+///   keeping the `added_at` of songs which were in the playlist before
+/// - Replaces the stored `playlist_pages` with `PlaylistData.pages`
+/// - Records the songs which were added or removed in `playlist_changes`, unless the playlist is new
+/// - Deletes the changes of the playlist which are older than the retention period
 ///
 pub async fn upsert(db: &Database, playlist: PlaylistData) -> sqlx::Result<()> {
   let mut tx = db.begin().await?;
 
-  let playlist_id: i32 = sqlx::query_scalar(
+  // `xmax` is only zero for freshly inserted rows
+  let metadata = playlist.metadata.as_ref();
+  let (playlist_id, created): (i32, bool) = sqlx::query_as(
     r#"
-      INSERT INTO playlists (updated_at, platform, platform_playlist_id)
-      VALUES (now()::timestamptz, $1::text, $2::text)
-      ON CONFLICT (platform, platform_playlist_id) DO UPDATE SET updated_at = now()
-      RETURNING playlist_id
+      INSERT INTO playlists (updated_at, platform, platform_playlist_id, title, owner, owner_id, thumbnail_url, item_count)
+      VALUES (now()::timestamptz, $1::text, $2::text, $3, $4, $5, $6, $7)
+      ON CONFLICT (platform, platform_playlist_id) DO UPDATE SET
+        updated_at = now(),
+        refresh_failures = 0,
+        retry_at = NULL,
+        title = COALESCE(EXCLUDED.title, playlists.title),
+        owner = COALESCE(EXCLUDED.owner, playlists.owner),
+        owner_id = COALESCE(EXCLUDED.owner_id, playlists.owner_id),
+        thumbnail_url = CASE WHEN EXCLUDED.title IS NULL THEN playlists.thumbnail_url ELSE EXCLUDED.thumbnail_url END,
+        item_count = COALESCE(EXCLUDED.item_count, playlists.item_count)
+      RETURNING playlist_id, xmax = 0
     "#,
   )
   .bind(playlist.platform.as_str())
   .bind(&playlist.playlist_id)
+  .bind(metadata.map(|m| &m.title))
+  .bind(metadata.map(|m| &m.owner))
+  .bind(metadata.map(|m| &m.owner_id))
+  .bind(metadata.and_then(|m| m.thumbnail_url.as_ref()))
+  .bind(metadata.map(|m| m.item_count))
   .fetch_one(&mut tx)
   .await?;
 
-  sqlx::query(
+  let (previous, previous_added_at): (Vec<i32>, Vec<DateTime<Utc>>) = sqlx::query_as::<_, (i32, DateTime<Utc>)>(
     r#"
       DELETE FROM playlists_songs
       WHERE playlist_id = $1
+      RETURNING song_id, added_at
     "#,
   )
   .bind(playlist_id)
-  .execute(&mut tx)
-  .await?;
+  .fetch_all(&mut tx)
+  .await?
+  .into_iter()
+  .unzip();
 
   let songs = SongData::soa(playlist.songs);
-  // TODO: order by song position when inserting
   sqlx::query(
     r#"
-      WITH
-      -- either select or insert every song, getting all ids
-      selected_songs AS (
-        SELECT song_id FROM songs
-        WHERE (platform, platform_song_id) IN (SELECT * FROM UNNEST($3::text[], $4::text[]))
-      ),
-      inserted_songs as (
-        INSERT INTO songs (published_at, platform, platform_song_id, title)
-        SELECT * FROM UNNEST($2::timestamptz[], $3::text[], $4::text[], $5::text[])
-        ON CONFLICT DO NOTHING
-        RETURNING song_id
-      ),
-      -- join the selected with the inserted ids
-      song_ids AS (
-        SELECT * FROM selected_songs
-        UNION
-        SELECT * FROM inserted_songs
-        ORDER BY song_id
+      INSERT INTO songs (published_at, platform, platform_song_id, title, artist, duration, thumbnail_url)
+      SELECT * FROM UNNEST(
+        $1::timestamptz[], $2::text[], $3::text[], $4::text[], $5::text[], $6::int4[], $7::text[]
       )
-      -- insert into playlists<->songs table
-      INSERT INTO playlists_songs (playlist_id, song_id)
-      SELECT $1 AS playlist_id, song_id FROM song_ids
       ON CONFLICT DO NOTHING
     "#,
   )
-  .bind(&playlist_id)
   .bind(&songs.published_at)
   .bind(&songs.platform)
   .bind(&songs.song_id)
   .bind(&songs.title)
+  .bind(&songs.artist)
+  .bind(&songs.duration)
+  .bind(&songs.thumbnail_url)
+  .execute(&mut tx)
+  .await?;
+  // songs which appear more than once keep their first position, songs which stay keep the time they were added
+  sqlx::query(
+    r#"
+      INSERT INTO playlists_songs (playlist_id, song_id, position, added_at)
+      SELECT $1, song_id, MIN(items.position) - 1, COALESCE(MIN(previous.added_at), now()) FROM songs
+      JOIN UNNEST($2::text[], $3::text[]) WITH ORDINALITY AS items (platform, platform_song_id, position)
+      USING (platform, platform_song_id)
+      LEFT JOIN UNNEST($4::int4[], $5::timestamptz[]) AS previous (song_id, added_at)
+      USING (song_id)
+      GROUP BY song_id
+    "#,
+  )
+  .bind(playlist_id)
+  .bind(&songs.platform)
+  .bind(&songs.song_id)
+  .bind(&previous)
+  .bind(&previous_added_at)
+  .execute(&mut tx)
+  .await?;
+
+  if !created {
+    let current: Vec<i32> = sqlx::query_scalar(r#"SELECT song_id FROM playlists_songs WHERE playlist_id = $1"#)
+      .bind(playlist_id)
+      .fetch_all(&mut tx)
+      .await?;
+    let (added, removed) = diff(&previous, &current);
+    sqlx::query(
+      r#"
+        INSERT INTO playlist_changes (playlist_id, song_id, change)
+        SELECT $1, song_id, 'added' FROM UNNEST($2::int4[]) AS song_id
+        UNION ALL
+        SELECT $1, song_id, 'removed' FROM UNNEST($3::int4[]) AS song_id
+      "#,
+    )
+    .bind(playlist_id)
+    .bind(&added)
+    .bind(&removed)
+    .execute(&mut tx)
+    .await?;
+  }
+  sqlx::query(
+    r#"
+      DELETE FROM playlist_changes
+      WHERE playlist_id = $1 AND changed_at < now() - make_interval(days => $2)
+    "#,
+  )
+  .bind(playlist_id)
+  .bind(CHANGE_RETENTION_DAYS)
   .execute(&mut tx)
   .await?;
 
+  sqlx::query(r#"DELETE FROM playlist_pages WHERE playlist_id = $1"#)
+    .bind(playlist_id)
+    .execute(&mut tx)
+    .await?;
+  for (i, page) in playlist.pages.iter().enumerate() {
+    sqlx::query(
+      r#"
+        INSERT INTO playlist_pages (playlist_id, page, etag, next_page_token, video_ids)
+        VALUES ($1, $2, $3, $4, $5)
+      "#,
+    )
+    .bind(playlist_id)
+    .bind(i as i32)
+    .bind(&page.etag)
+    .bind(&page.next_page_token)
+    .bind(&page.video_ids)
+    .execute(&mut tx)
+    .await?;
+  }
+
   tx.commit().await?;
   Ok(())
 }
 
-// get playlist items (keyset pagination)
-pub async fn get_page(db: &Database, playlist_id: &str, offset: i32, limit: i32) -> sqlx::Result<Vec<Song>> {
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Change {
+  Added,
+  Removed,
+}
+
+/// A song which was added to or removed from a playlist
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PlaylistChange {
+  pub change: Change,
+  pub changed_at: DateTime<Utc>,
+  pub song: Song,
+}
+
+impl<'r> sqlx::FromRow<'r, PgRow> for PlaylistChange {
+  fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+    Ok(Self {
+      change: row.try_get("change")?,
+      changed_at: row.try_get("changed_at")?,
+      song: Song::from_row(row)?,
+    })
+  }
+}
+
+/// Songs which were added to or removed from a playlist after `since`, oldest change first
+pub async fn changes(
+  db: &Database,
+  platform: Platform,
+  id: &str,
+  since: DateTime<Utc>,
+) -> sqlx::Result<Vec<PlaylistChange>> {
   sqlx::query_as(
     r#"
-      WITH song_ids AS (
-        SELECT song_id FROM playlists_songs
-        WHERE playlist_id = (
-          SELECT playlist_id FROM playlists
-          WHERE platform_playlist_id = $1
-        )
-        OFFSET $2
-        LIMIT $3
+      SELECT songs.*, change, changed_at FROM playlist_changes
+      JOIN songs USING (song_id)
+      WHERE playlist_id = (
+        SELECT playlist_id FROM playlists
+        WHERE (platform, platform_playlist_id) = ($1, $2)
       )
-      SELECT * FROM songs
-      WHERE song_id IN (SELECT * FROM song_ids)
+      AND changed_at > $3
+      ORDER BY changed_at, change, song_id
     "#,
   )
-  .bind(playlist_id)
-  .bind(offset)
-  .bind(limit)
+  .bind(platform.as_str())
+  .bind(id)
+  .bind(since)
   .fetch_all(db)
   .await
 }
 
+/// A song along with its position in a pool of playlists
+#[derive(Debug, Clone)]
+pub struct PlaylistSong {
+  /// Index of the first playlist of the pool which contains the song
+  pub rank: i32,
+  /// Position of the song in that playlist
+  pub position: i32,
+  pub song: Song,
+}
+
+impl<'r> sqlx::FromRow<'r, PgRow> for PlaylistSong {
+  fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+    Ok(Self {
+      rank: row.try_get("rank")?,
+      position: row.try_get("position")?,
+      song: Song::from_row(row)?,
+    })
+  }
+}
+
+/// What playlist items are sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+  /// Position in the playlist, songs of a pool are sorted by playlist first
+  #[default]
+  Position,
+  PublishedAt,
+  Title,
+  /// When the song was added to the playlist
+  AddedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+  #[default]
+  Asc,
+  Desc,
+}
+
+/// Sorting and filtering of playlist items
+#[derive(Debug, Clone, Default)]
+pub struct PageQuery {
+  pub sort: SortBy,
+  pub order: SortOrder,
+  /// Only songs whose title contains this, ignoring case
+  pub title: Option<String>,
+  pub published_after: Option<DateTime<Utc>>,
+  pub published_before: Option<DateTime<Utc>>,
+  /// Only songs which are at most this many seconds long, or whose duration is unknown
+  pub max_duration: Option<i32>,
+  /// Songs to leave out by platform and platform id, e.g. the ones which were played already
+  pub exclude: Vec<(Platform, String)>,
+}
+
+impl PageQuery {
+  /// The `ORDER BY` clause, ties are broken by position
+  fn order_by(&self) -> String {
+    let direction = match self.order {
+      SortOrder::Asc => "ASC",
+      SortOrder::Desc => "DESC",
+    };
+    let column = match self.sort {
+      SortBy::Position => return format!("rank {direction}, position {direction}"),
+      SortBy::PublishedAt => "songs.published_at",
+      SortBy::Title => "songs.title",
+      SortBy::AddedAt => "items.added_at",
+    };
+    format!("{column} {direction}, rank {direction}, position {direction}")
+  }
+}
+
+/// Available items of a pool of playlists (`$1`, by internal id) matching the filters of a `PageQuery` as `$2..=$7`
+///
+/// Songs which are in more than one of the playlists are only included once,
+/// at their position in the first one of them.
+const PAGE_ITEMS: &str = r#"
+  WITH items AS (
+    SELECT DISTINCT ON (song_id) song_id, array_position($1, playlist_id) - 1 AS rank, position, added_at
+    FROM playlists_songs
+    WHERE playlist_id = ANY($1)
+    ORDER BY song_id, rank, position
+  )
+  SELECT songs.*, rank, position FROM items
+  JOIN songs USING (song_id)
+  WHERE availability = 'available'
+  AND ($2::text IS NULL OR strpos(lower(songs.title), lower($2)) > 0)
+  AND ($3::timestamptz IS NULL OR songs.published_at >= $3)
+  AND ($4::timestamptz IS NULL OR songs.published_at < $4)
+  AND ($5::int4 IS NULL OR songs.duration IS NULL OR songs.duration <= $5)
+  AND (songs.platform, songs.platform_song_id) NOT IN (SELECT * FROM UNNEST($6::text[], $7::text[]))
+"#;
+
+fn page_query<'q, O>(sql: &'q str, playlists: &'q [i32], query: &'q PageQuery) -> QueryAs<'q, Postgres, O, PgArguments>
+where
+  O: for<'r> sqlx::FromRow<'r, PgRow>,
+{
+  sqlx::query_as(sql)
+    .bind(playlists)
+    .bind(&query.title)
+    .bind(query.published_after)
+    .bind(query.published_before)
+    .bind(query.max_duration)
+    .bind(
+      query
+        .exclude
+        .iter()
+        .map(|(platform, _)| platform.as_str())
+        .collect::<Vec<_>>(),
+    )
+    .bind(query.exclude.iter().map(|(_, id)| id.clone()).collect::<Vec<_>>())
+}
+
+/// Get a page of available items of a pool of playlists matching `query`, skipping the first `offset`
+pub async fn get_page(
+  db: &Database,
+  playlists: &[i32],
+  query: &PageQuery,
+  offset: i32,
+  limit: i32,
+) -> sqlx::Result<Vec<Song>> {
+  let sql = format!("{PAGE_ITEMS} ORDER BY {} OFFSET $8 LIMIT $9", query.order_by());
+  page_query(&sql, playlists, query)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
+
+/// Get a page of available items of a pool of playlists matching `query` in playlist order (or reverse playlist order),
+/// starting after the item at `(rank, position)` (keyset pagination)
+///
+/// `query.sort` is ignored, only positions are unique and stable enough to page by.
+pub async fn get_page_after(
+  db: &Database,
+  playlists: &[i32],
+  query: &PageQuery,
+  after: Option<(i32, i32)>,
+  limit: i32,
+) -> sqlx::Result<Vec<PlaylistSong>> {
+  let sql = match query.order {
+    SortOrder::Asc => format!(
+      "{PAGE_ITEMS} AND ($8::int4 IS NULL OR (rank, position) > ($8, $9)) ORDER BY rank ASC, position ASC LIMIT $10"
+    ),
+    SortOrder::Desc => format!(
+      "{PAGE_ITEMS} AND ($8::int4 IS NULL OR (rank, position) < ($8, $9)) ORDER BY rank DESC, position DESC LIMIT $10"
+    ),
+  };
+  page_query(&sql, playlists, query)
+    .bind(after.map(|(rank, _)| rank))
+    .bind(after.map(|(_, position)| position))
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
+
+/// A random available song from a pool of playlists (by internal id), except the ones in `exclude`
+pub async fn random(db: &Database, playlists: &[i32], exclude: &[i32]) -> sqlx::Result<Option<Song>> {
+  sqlx::query_as(
+    r#"
+      SELECT * FROM songs
+      WHERE availability = 'available'
+      AND song_id IN (SELECT song_id FROM playlists_songs WHERE playlist_id = ANY($1))
+      AND NOT song_id = ANY($2)
+      ORDER BY random()
+      LIMIT 1
+    "#,
+  )
+  .bind(playlists)
+  .bind(exclude)
+  .fetch_optional(db)
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db;
+  use chrono::{Duration, TimeZone};
+
+  #[test]
+  fn diff_added_and_removed() {
+    assert_eq!(diff(&[1, 2, 3], &[1, 2, 3]), (vec![], vec![]));
+    assert_eq!(diff(&[], &[3, 1, 2]), (vec![3, 1, 2], vec![]));
+    assert_eq!(diff(&[3, 1, 2], &[]), (vec![], vec![3, 1, 2]));
+    assert_eq!(diff(&[1, 2, 3], &[4, 2, 5]), (vec![4, 5], vec![1, 3]));
+    // moving songs is no change
+    assert_eq!(diff(&[1, 2, 3], &[3, 1, 2]), (vec![], vec![]));
+  }
+
+  #[test]
+  fn diff_duplicates() {
+    // songs which are in the playlist more than once are reported once
+    assert_eq!(diff(&[1], &[2, 1, 2]), (vec![2], vec![]));
+    assert_eq!(diff(&[1, 3, 1, 3], &[2]), (vec![2], vec![1, 3]));
+    // adding or removing further copies of a song is no change
+    assert_eq!(diff(&[1, 2], &[1, 2, 2]), (vec![], vec![]));
+    assert_eq!(diff(&[1, 1, 2], &[1, 2]), (vec![], vec![]));
+  }
+
+  #[test]
+  fn page_order_breaks_ties_by_position() {
+    let query = |sort, order| PageQuery {
+      sort,
+      order,
+      ..Default::default()
+    };
+    assert_eq!(
+      query(SortBy::Position, SortOrder::Asc).order_by(),
+      "rank ASC, position ASC"
+    );
+    assert_eq!(
+      query(SortBy::Title, SortOrder::Desc).order_by(),
+      "songs.title DESC, rank DESC, position DESC"
+    );
+    assert_eq!(
+      query(SortBy::AddedAt, SortOrder::Asc).order_by(),
+      "items.added_at ASC, rank ASC, position ASC"
+    );
+  }
+
+  async fn titles(db: &Database, playlist: i32, query: PageQuery) -> sqlx::Result<Vec<String>> {
+    Ok(
+      get_page(db, &[playlist], &query, 0, 10)
+        .await?
+        .iter()
+        .map(|song| song.title().clone())
+        .collect(),
+    )
+  }
+
+  crate::db_test!(page_sorting_and_filters, db, prefix, cleanup [
+    r#"DELETE FROM playlists_songs WHERE playlist_id IN (SELECT playlist_id FROM playlists WHERE platform_playlist_id = $1)"#,
+    r#"DELETE FROM playlists WHERE platform_playlist_id = $1"#,
+    r#"DELETE FROM songs WHERE starts_with(platform_song_id, $1)"#,
+  ] {
+    let day = |day| Utc.ymd(2022, 1, day).and_hms(0, 0, 0);
+    let songs = [
+      ("hello world", 1, Some(100)),
+      ("say Hello", 3, None),
+      ("100% pure", 2, Some(300)),
+      ("another", 4, Some(200)),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(i, (title, published, duration))| {
+      let song = SongData::new(day(published), format!("{prefix}-{i}"), Platform::Youtube, title.into());
+      match duration {
+        Some(duration) => song.with_duration(Duration::seconds(duration)),
+        None => song,
+      }
+    })
+    .collect();
+    upsert(db, PlaylistData::new(Platform::Youtube, prefix.clone(), songs)).await?;
+    let playlist = *get(db, Platform::Youtube, &prefix).await?.unwrap().id();
+    let items: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM playlists_songs WHERE playlist_id = $1"#)
+      .bind(playlist)
+      .fetch_one(db)
+      .await?;
+    assert_eq!(items, 4);
+    // titles are stored in lower case, except for songs which were written by other means
+    sqlx::query(r#"UPDATE songs SET title = 'say Hello' WHERE platform_song_id = $1"#)
+      .bind(format!("{prefix}-1"))
+      .execute(db)
+      .await?;
+
+    assert_eq!(
+      titles(db, playlist, PageQuery::default()).await?,
+      ["hello world", "say Hello", "100% pure", "another"]
+    );
+    // titles are matched ignoring case, and literally
+    let query = |title: &str| PageQuery {
+      title: Some(title.into()),
+      ..Default::default()
+    };
+    assert_eq!(titles(db, playlist, query("HELLO")).await?, ["hello world", "say Hello"]);
+    assert_eq!(titles(db, playlist, query("0%")).await?, ["100% pure"]);
+    assert!(titles(db, playlist, query("_")).await?.is_empty());
+
+    let sorted = |sort, order| PageQuery {
+      sort,
+      order,
+      ..Default::default()
+    };
+    assert_eq!(
+      titles(db, playlist, sorted(SortBy::Title, SortOrder::Asc)).await?,
+      ["100% pure", "another", "hello world", "say Hello"]
+    );
+    assert_eq!(
+      titles(db, playlist, sorted(SortBy::PublishedAt, SortOrder::Desc)).await?,
+      ["another", "say Hello", "100% pure", "hello world"]
+    );
+    assert_eq!(
+      titles(db, playlist, sorted(SortBy::Position, SortOrder::Desc)).await?,
+      ["another", "100% pure", "say Hello", "hello world"]
+    );
+
+    let published = PageQuery {
+      published_after: Some(day(2)),
+      published_before: Some(day(4)),
+      ..Default::default()
+    };
+    assert_eq!(titles(db, playlist, published).await?, ["say Hello", "100% pure"]);
+    // songs of unknown duration are kept
+    let short = PageQuery {
+      max_duration: Some(150),
+      ..Default::default()
+    };
+    assert_eq!(titles(db, playlist, short).await?, ["hello world", "say Hello"]);
+    let exclude = PageQuery {
+      exclude: vec![
+        (Platform::Youtube, format!("{prefix}-0")),
+        (Platform::Youtube, format!("{prefix}-3")),
+        // the same id on another platform is a different song
+        (Platform::Soundcloud, format!("{prefix}-1")),
+      ],
+      ..Default::default()
+    };
+    assert_eq!(titles(db, playlist, exclude).await?, ["say Hello", "100% pure"]);
+
+    let page = get_page(db, &[playlist], &PageQuery::default(), 1, 2).await?;
+    assert_eq!(page.iter().map(|s| s.title().as_str()).collect::<Vec<_>>(), ["say Hello", "100% pure"]);
+    let first = get_page_after(db, &[playlist], &PageQuery::default(), None, 2).await?;
+    let last = first.last().unwrap();
+    let next = get_page_after(db, &[playlist], &PageQuery::default(), Some((last.rank, last.position)), 2).await?;
+    assert_eq!(next.iter().map(|s| s.song.title().as_str()).collect::<Vec<_>>(), ["100% pure", "another"]);
+  });
+
+  crate::db_test!(failing_refreshes_back_off, tx {
+    let id: i32 = sqlx::query_scalar(
+      r#"
+        INSERT INTO playlists (updated_at, platform, platform_playlist_id)
+        VALUES (now() - interval '1 day', 'youtube', 'test-backoff')
+        RETURNING playlist_id
+      "#,
+    )
+    .fetch_one(&mut tx)
+    .await?;
+    let is_stale = |playlists: Vec<Playlist>| playlists.iter().any(|p| p.id == id);
+    assert!(is_stale(stale(&mut tx, Utc::now(), 1000).await?));
+
+    let mut delays = vec![];
+    for _ in 0..4 {
+      refresh_failed(&mut tx, id, Duration::minutes(1), Duration::minutes(5)).await?;
+      let delay: f64 =
+        sqlx::query_scalar(r#"SELECT EXTRACT(EPOCH FROM retry_at - now())::float8 FROM playlists WHERE playlist_id = $1"#)
+          .bind(id)
+          .fetch_one(&mut tx)
+          .await?;
+      delays.push(delay as i64);
+      assert!(!is_stale(stale(&mut tx, Utc::now(), 1000).await?));
+    }
+    assert_eq!(delays, [60, 120, 240, 300]);
+
+    sqlx::query(r#"UPDATE playlists SET retry_at = now() - interval '1 second' WHERE playlist_id = $1"#)
+      .bind(id)
+      .execute(&mut tx)
+      .await?;
+    assert!(is_stale(stale(&mut tx, Utc::now(), 1000).await?));
+  });
+}
+
 // TODO: start a new logical database for every test run to allow for using transactions in queries
 /* #[cfg(test)]
 mod tests {
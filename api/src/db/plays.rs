@@ -0,0 +1,213 @@
+use super::{channels::LastPlay, songs::Song, Database};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, Row};
+
+/// How a play ended
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum PlayOutcome {
+  /// The song played until its end
+  Finished,
+  Skipped,
+  /// The player failed to play the song
+  Error,
+}
+
+/// A song played on a channel
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Play {
+  pub id: i64,
+  pub channel: String,
+  pub song: Song,
+  pub requester: Option<String>,
+  pub started_at: DateTime<Utc>,
+  pub ended_at: Option<DateTime<Utc>>,
+  /// `None` while the song is playing
+  pub outcome: Option<PlayOutcome>,
+}
+
+impl<'r> sqlx::FromRow<'r, PgRow> for Play {
+  fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+    Ok(Self {
+      id: row.try_get("play_id")?,
+      channel: row.try_get("channel")?,
+      song: Song::from_row(row)?,
+      requester: row.try_get("requester")?,
+      started_at: row.try_get("started_at")?,
+      ended_at: row.try_get("ended_at")?,
+      outcome: row.try_get("outcome")?,
+    })
+  }
+}
+
+/// Record that a song started playing on `channel`
+pub async fn start(db: &Database, channel: &str, song_id: i32, requester: Option<&str>) -> sqlx::Result<Play> {
+  sqlx::query_as(
+    r#"
+      WITH started AS (
+        INSERT INTO plays (channel, song_id, requester)
+        VALUES ($1, $2, $3)
+        RETURNING *
+      )
+      SELECT * FROM started
+      JOIN songs USING (song_id)
+    "#,
+  )
+  .bind(channel)
+  .bind(song_id)
+  .bind(requester)
+  .fetch_one(db)
+  .await
+}
+
+/// Record how a play ended, `None` if it doesn't exist or has ended already
+pub async fn end(db: &Database, play_id: i64, outcome: PlayOutcome) -> sqlx::Result<Option<Play>> {
+  sqlx::query_as(
+    r#"
+      WITH ended AS (
+        UPDATE plays SET ended_at = now(), outcome = $2
+        WHERE play_id = $1 AND ended_at IS NULL
+        RETURNING *
+      )
+      SELECT * FROM ended
+      JOIN songs USING (song_id)
+    "#,
+  )
+  .bind(play_id)
+  .bind(outcome)
+  .fetch_optional(db)
+  .await
+}
+
+/// The song playing on `channel`, `None` if its last play has ended
+pub async fn current(db: &Database, channel: &str) -> sqlx::Result<Option<Play>> {
+  sqlx::query_as(
+    r#"
+      SELECT * FROM (
+        SELECT * FROM plays
+        WHERE channel = $1
+        ORDER BY play_id DESC
+        LIMIT 1
+      ) AS last_play
+      JOIN songs USING (song_id)
+      WHERE ended_at IS NULL
+    "#,
+  )
+  .bind(channel)
+  .fetch_optional(db)
+  .await
+}
+
+/// Up to `limit` plays on `channel` which started before the play `before`, most recent first
+pub async fn history(db: &Database, channel: &str, before: Option<i64>, limit: i64) -> sqlx::Result<Vec<Play>> {
+  sqlx::query_as(
+    r#"
+      SELECT * FROM plays
+      JOIN songs USING (song_id)
+      WHERE channel = $1 AND ($2::int8 IS NULL OR play_id < $2)
+      ORDER BY play_id DESC
+      LIMIT $3
+    "#,
+  )
+  .bind(channel)
+  .bind(before)
+  .bind(limit)
+  .fetch_all(db)
+  .await
+}
+
+/// The last time a song was played on `channel`, `None` if it never was
+pub async fn last_play(db: &Database, channel: &str, song_id: i32) -> sqlx::Result<Option<LastPlay>> {
+  sqlx::query_as(
+    r#"
+      SELECT
+        started_at,
+        (SELECT COUNT(*) FROM plays AS later WHERE later.channel = $1 AND later.play_id > plays.play_id) AS plays_since
+      FROM plays
+      WHERE channel = $1 AND song_id = $2
+      ORDER BY play_id DESC
+      LIMIT 1
+    "#,
+  )
+  .bind(channel)
+  .bind(song_id)
+  .fetch_optional(db)
+  .await
+}
+
+/// Songs played on `channel` within the last `minutes`, or within the last `songs` plays, including the current one
+pub async fn recent(db: &Database, channel: &str, minutes: Option<i32>, songs: Option<i32>) -> sqlx::Result<Vec<i32>> {
+  sqlx::query_scalar(
+    r#"
+      SELECT song_id FROM plays
+      WHERE channel = $1 AND started_at > now() - make_interval(mins => $2)
+      UNION
+      SELECT song_id FROM (
+        SELECT song_id FROM plays
+        WHERE channel = $1
+        ORDER BY play_id DESC
+        LIMIT COALESCE($3, 0)
+      ) AS last_plays
+    "#,
+  )
+  .bind(channel)
+  .bind(minutes)
+  .bind(songs)
+  .fetch_all(db)
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    common::platform::Platform,
+    db::{self, songs::SongData},
+  };
+
+  crate::db_test!(plays_start_end_and_history, db, channel, cleanup [
+    r#"DELETE FROM plays WHERE channel = $1"#,
+    r#"DELETE FROM songs WHERE platform_song_id = $1"#,
+  ] {
+    let song = db::songs::create(db, SongData::new(Utc::now(), channel.clone(), Platform::Youtube, "test".into())).await?;
+    let song_id = *song.id();
+
+    let first = start(db, &channel, song_id, None).await?;
+    assert_eq!(first.song.id(), &song_id);
+    assert_eq!(first.outcome, None);
+    assert_eq!(current(db, &channel).await?.map(|play| play.id), Some(first.id));
+    let ended = end(db, first.id, PlayOutcome::Finished).await?.unwrap();
+    assert_eq!(ended.outcome, Some(PlayOutcome::Finished));
+    assert!(ended.ended_at.is_some());
+    assert!(current(db, &channel).await?.is_none());
+
+    // only one of concurrent ends is recorded
+    let second = start(db, &channel, song_id, Some("viewer")).await?;
+    let (skipped, failed) = futures::join!(
+      end(db, second.id, PlayOutcome::Skipped),
+      end(db, second.id, PlayOutcome::Error)
+    );
+    let ends = [skipped?, failed?].into_iter().flatten().collect::<Vec<_>>();
+    assert_eq!(ends.len(), 1);
+    let outcome: Option<PlayOutcome> = sqlx::query_scalar(r#"SELECT outcome FROM plays WHERE play_id = $1"#)
+      .bind(second.id)
+      .fetch_one(db)
+      .await?;
+    assert_eq!(outcome, ends[0].outcome);
+    assert!(end(db, second.id, PlayOutcome::Finished).await?.is_none());
+
+    let third = start(db, &channel, song_id, None).await?;
+    let ids = |plays: Vec<Play>| plays.into_iter().map(|play| play.id).collect::<Vec<_>>();
+    assert_eq!(ids(history(db, &channel, None, 2).await?), [third.id, second.id]);
+    assert_eq!(ids(history(db, &channel, Some(second.id), 2).await?), [first.id]);
+    assert_eq!(history(db, &channel, None, 10).await?[1].requester.as_deref(), Some("viewer"));
+
+    // played songs can't be deleted
+    assert!(sqlx::query(r#"DELETE FROM songs WHERE song_id = $1"#)
+      .bind(song_id)
+      .execute(db)
+      .await
+      .is_err());
+  });
+}
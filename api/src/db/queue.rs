@@ -0,0 +1,618 @@
+use super::{
+  channels::{ChannelSettings, QueueMode},
+  locks::{self, LockClass},
+  songs::Song,
+  Database,
+};
+use crate::common::{platform::Platform, role::Role};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, Row};
+
+/// Queue entries with their song and number of upvotes, as `entries`
+const ENTRIES: &str = r#"
+  entries AS (
+    SELECT *, (SELECT COUNT(*) FROM queue_votes WHERE queue_votes.entry_id = queue_entries.entry_id) AS score
+    FROM queue_entries
+    JOIN songs USING (song_id)
+  )
+"#;
+
+/// A song requested on a channel
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct QueueEntry {
+  pub id: i64,
+  pub channel: String,
+  pub platform: Platform,
+  pub song: Song,
+  pub requester: String,
+  /// The requester's role when they requested the song
+  pub role: Role,
+  pub requested_at: DateTime<Utc>,
+  /// Number of upvotes
+  pub score: i64,
+  /// When a moderator pinned the entry to the top, `None` if they didn't
+  pub pinned_at: Option<DateTime<Utc>>,
+  /// When a VIP moved the entry to the front using a token, `None` if they didn't
+  pub front_at: Option<DateTime<Utc>>,
+}
+
+impl<'r> sqlx::FromRow<'r, PgRow> for QueueEntry {
+  fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+    Ok(Self {
+      id: row.try_get("entry_id")?,
+      channel: row.try_get("channel")?,
+      platform: row.try_get("platform")?,
+      song: Song::from_row(row)?,
+      requester: row.try_get("requester")?,
+      role: row.try_get("role")?,
+      requested_at: row.try_get("requested_at")?,
+      score: row.try_get("score")?,
+      pinned_at: row.try_get("pinned_at")?,
+      front_at: row.try_get("front_at")?,
+    })
+  }
+}
+
+/// Whether requests of `role` are played in the subscriber lane, if the channel has one
+fn in_subscriber_lane(role: Role) -> bool {
+  role >= Role::Subscriber
+}
+
+/// The position of the `rank`th entry of a lane among the entries of both lanes,
+/// when `per_regular` entries of the subscriber lane are played per regular entry.
+///
+/// `streak` is the number of entries of the subscriber lane which were played since the last regular entry.
+/// The positions of both lanes don't overlap, but may have gaps.
+fn lane_position(subscriber: bool, rank: usize, per_regular: usize, streak: usize) -> usize {
+  // subscriber entries before the first regular entry
+  let first = per_regular - streak.min(per_regular);
+  if !subscriber {
+    first + rank * (per_regular + 1)
+  } else if rank < first {
+    rank
+  } else {
+    let rank = rank - first;
+    first + 1 + rank / per_regular * (per_regular + 1) + rank % per_regular
+  }
+}
+
+/// Sort pending entries in the order they will be played.
+///
+/// Pinned entries come first, then entries moved to the front with a VIP's token, both in the order that happened.
+/// The rest are ordered by role and score if the channel is configured to, then in the order they were requested,
+/// and interleaved with the subscriber lane if the channel has one. See [`lane_position`] for `streak`.
+pub fn order(mut entries: Vec<QueueEntry>, settings: &ChannelSettings, streak: usize) -> Vec<QueueEntry> {
+  entries.sort_by(|a, b| {
+    let by_role = match settings.priority_by_role {
+      true => b.role.cmp(&a.role),
+      false => std::cmp::Ordering::Equal,
+    };
+    let by_score = match settings.queue_mode {
+      QueueMode::Votes => b.score.cmp(&a.score),
+      QueueMode::Fifo => std::cmp::Ordering::Equal,
+    };
+    by_role
+      .then(by_score)
+      .then(a.requested_at.cmp(&b.requested_at))
+      .then(a.id.cmp(&b.id))
+  });
+  let per_regular = settings.subscriber_lane.map(|n| n.max(1) as usize);
+  // the number of entries in the regular and subscriber lane so far
+  let mut ranks = [0, 0];
+  let mut keyed = entries
+    .into_iter()
+    .enumerate()
+    .map(|(index, entry)| {
+      let position = match per_regular {
+        // pinned entries and entries at the front are played before both lanes
+        Some(per_regular) if entry.pinned_at.is_none() && entry.front_at.is_none() => {
+          let subscriber = in_subscriber_lane(entry.role);
+          let rank = &mut ranks[subscriber as usize];
+          *rank += 1;
+          lane_position(subscriber, *rank - 1, per_regular, streak)
+        }
+        _ => index,
+      };
+      let key = (
+        entry.pinned_at.is_none(),
+        entry.pinned_at,
+        entry.front_at.is_none(),
+        entry.front_at,
+        position,
+      );
+      (key, entry)
+    })
+    .collect::<Vec<_>>();
+  keyed.sort_by_key(|(key, _)| *key);
+  keyed.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// The pending entries of `channel` in the order they were requested, see [`order`] for the order they're played in
+pub async fn pending(db: &Database, channel: &str) -> sqlx::Result<Vec<QueueEntry>> {
+  sqlx::query_as(&format!(
+    r#"
+      WITH {ENTRIES}
+      SELECT * FROM entries
+      WHERE channel = $1 AND dequeued_at IS NULL
+      ORDER BY requested_at, entry_id
+    "#
+  ))
+  .bind(channel)
+  .fetch_all(db)
+  .await
+}
+
+/// The songs of the pending entries of `channel`
+pub async fn pending_songs(db: &Database, channel: &str) -> sqlx::Result<Vec<i32>> {
+  sqlx::query_scalar(
+    r#"
+      SELECT song_id FROM queue_entries
+      WHERE channel = $1 AND dequeued_at IS NULL
+    "#,
+  )
+  .bind(channel)
+  .fetch_all(db)
+  .await
+}
+
+/// The number of entries of the subscriber lane played on `channel` since the last regular entry, up to `max`
+pub async fn subscriber_streak(db: &Database, channel: &str, max: usize) -> sqlx::Result<usize> {
+  let roles: Vec<Role> = sqlx::query_scalar(
+    r#"
+      SELECT role FROM queue_entries
+      WHERE channel = $1 AND dequeued_at IS NOT NULL AND pinned_at IS NULL AND front_at IS NULL
+      ORDER BY dequeued_at DESC, entry_id DESC
+      LIMIT $2
+    "#,
+  )
+  .bind(channel)
+  .bind(max as i64)
+  .fetch_all(db)
+  .await?;
+  Ok(roles.into_iter().take_while(|role| in_subscriber_lane(*role)).count())
+}
+
+/// The entry with the given id, `None` if it doesn't exist or isn't pending anymore
+pub async fn get_pending(db: &Database, entry_id: i64) -> sqlx::Result<Option<QueueEntry>> {
+  sqlx::query_as(&format!(
+    r#"
+      WITH {ENTRIES}
+      SELECT * FROM entries
+      WHERE entry_id = $1 AND dequeued_at IS NULL
+    "#
+  ))
+  .bind(entry_id)
+  .fetch_optional(db)
+  .await
+}
+
+/// The pending entry of a song on `channel`, `None` if the song isn't queued
+pub async fn find_pending(db: &Database, channel: &str, song_id: i32) -> sqlx::Result<Option<QueueEntry>> {
+  sqlx::query_as(&format!(
+    r#"
+      WITH {ENTRIES}
+      SELECT * FROM entries
+      WHERE channel = $1 AND song_id = $2 AND dequeued_at IS NULL
+    "#
+  ))
+  .bind(channel)
+  .bind(song_id)
+  .fetch_optional(db)
+  .await
+}
+
+/// The result of [`add`]
+#[derive(Debug, Clone)]
+pub enum Added {
+  Entry(Box<QueueEntry>),
+  /// The song is queued already
+  Queued,
+  /// The requester used all of their tokens for the stream
+  NoTokens,
+}
+
+/// Queue a song on `channel`.
+///
+/// With `tokens`, the entry is moved to the front using one of the requester's tokens,
+/// unless they moved this many entries to the front since the stream started.
+pub async fn add(
+  db: &Database,
+  channel: &str,
+  song_id: i32,
+  requester: &str,
+  role: Role,
+  tokens: Option<i32>,
+) -> sqlx::Result<Added> {
+  let mut tx = db.begin().await?;
+
+  if tokens.is_some() {
+    // serializes the requester's use of tokens on the channel, so that concurrent requests can't exceed `tokens`
+    locks::lock_xact(&mut tx, LockClass::QueueViewer, &format!("{channel}:{requester}")).await?;
+  }
+  let entry = sqlx::query_as(
+    r#"
+      WITH added AS (
+        INSERT INTO queue_entries (channel, song_id, requester, role, front_at)
+        SELECT $1, $2, $3, $4, CASE WHEN $5::int4 IS NOT NULL THEN now() END
+        WHERE $5::int4 IS NULL OR (
+          SELECT COUNT(*) FROM queue_entries
+          WHERE channel = $1 AND requester = $3
+            AND front_at >= COALESCE((SELECT MAX(started_at) FROM streams WHERE channel = $1), '-infinity')
+        ) < $5
+        ON CONFLICT DO NOTHING
+        RETURNING *
+      )
+      -- a new entry has no upvotes
+      SELECT *, 0::int8 AS score FROM added
+      JOIN songs USING (song_id)
+    "#,
+  )
+  .bind(channel)
+  .bind(song_id)
+  .bind(requester)
+  .bind(role)
+  .bind(tokens)
+  .fetch_optional(&mut tx)
+  .await?;
+  let added = match entry {
+    Some(entry) => Added::Entry(Box::new(entry)),
+    None if tokens.is_none() => Added::Queued,
+    None => {
+      let queued = sqlx::query_scalar(
+        r#"SELECT EXISTS (SELECT FROM queue_entries WHERE channel = $1 AND song_id = $2 AND dequeued_at IS NULL)"#,
+      )
+      .bind(channel)
+      .bind(song_id)
+      .fetch_one(&mut tx)
+      .await?;
+      match queued {
+        true => Added::Queued,
+        false => Added::NoTokens,
+      }
+    }
+  };
+
+  tx.commit().await?;
+  Ok(added)
+}
+
+/// The result of an [`upvote`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Upvote {
+  Added,
+  /// The voter upvoted the entry already
+  Repeated,
+  /// The voter upvoted the maximum number of pending entries of the channel already
+  LimitReached,
+}
+
+/// Upvote an entry of `channel`, unless `voter` upvoted it already, or upvoted `max` of its pending entries
+pub async fn upvote(
+  db: &Database,
+  channel: &str,
+  entry_id: i64,
+  voter: &str,
+  max: Option<i32>,
+) -> sqlx::Result<Upvote> {
+  let mut tx = db.begin().await?;
+
+  // serializes the upvotes of the voter on the channel, so that concurrent ones can't exceed `max`
+  locks::lock_xact(&mut tx, LockClass::QueueViewer, &format!("{channel}:{voter}")).await?;
+  let result = sqlx::query(
+    r#"
+      INSERT INTO queue_votes (entry_id, voter)
+      SELECT $1, $2
+      WHERE $4::int4 IS NULL OR (
+        SELECT COUNT(*) FROM queue_votes
+        JOIN queue_entries USING (entry_id)
+        WHERE voter = $2 AND channel = $3 AND dequeued_at IS NULL
+      ) < $4
+      ON CONFLICT DO NOTHING
+    "#,
+  )
+  .bind(entry_id)
+  .bind(voter)
+  .bind(channel)
+  .bind(max)
+  .execute(&mut tx)
+  .await?;
+  let upvote = if result.rows_affected() > 0 {
+    Upvote::Added
+  } else if sqlx::query_scalar(r#"SELECT EXISTS (SELECT FROM queue_votes WHERE entry_id = $1 AND voter = $2)"#)
+    .bind(entry_id)
+    .bind(voter)
+    .fetch_one(&mut tx)
+    .await?
+  {
+    Upvote::Repeated
+  } else {
+    Upvote::LimitReached
+  };
+
+  tx.commit().await?;
+  Ok(upvote)
+}
+
+/// Pin a pending entry to the top of the queue, or unpin it, `false` if it isn't pending
+pub async fn pin(db: &Database, entry_id: i64, pinned: bool) -> sqlx::Result<bool> {
+  let result = sqlx::query(
+    r#"
+      UPDATE queue_entries SET pinned_at = CASE WHEN $2 THEN COALESCE(pinned_at, now()) END
+      WHERE entry_id = $1 AND dequeued_at IS NULL
+    "#,
+  )
+  .bind(entry_id)
+  .bind(pinned)
+  .execute(db)
+  .await?;
+  Ok(result.rows_affected() > 0)
+}
+
+/// Take the next entry from the queue of the channel, `None` if it is empty
+pub async fn next(db: &Database, settings: &ChannelSettings) -> sqlx::Result<Option<QueueEntry>> {
+  loop {
+    let streak = match settings.subscriber_lane {
+      Some(per_regular) => subscriber_streak(db, &settings.channel, per_regular.max(1) as usize).await?,
+      None => 0,
+    };
+    let entries = order(pending(db, &settings.channel).await?, settings, streak);
+    let entry = match entries.into_iter().next() {
+      Some(entry) => entry,
+      None => return Ok(None),
+    };
+    let dequeued = sqlx::query(
+      r#"
+        UPDATE queue_entries SET dequeued_at = now()
+        WHERE entry_id = $1 AND dequeued_at IS NULL
+      "#,
+    )
+    .bind(entry.id)
+    .execute(db)
+    .await?;
+    // otherwise a concurrent call took it, try the next one
+    if dequeued.rows_affected() > 0 {
+      return Ok(Some(entry));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::{self, channels, songs::SongData};
+  use chrono::TimeZone;
+
+  /// A pending entry of a user, requested `requested` seconds into the stream
+  fn entry(id: i64, requested: i64) -> QueueEntry {
+    let start = Utc.ymd(2022, 3, 1).and_hms(20, 0, 0);
+    QueueEntry {
+      id,
+      channel: "channel".into(),
+      platform: Platform::Youtube,
+      song: Song::from_data(
+        id as i32,
+        SongData::new(start, id.to_string(), Platform::Youtube, id.to_string()),
+      ),
+      requester: format!("viewer {id}"),
+      role: Role::User,
+      requested_at: start + chrono::Duration::seconds(requested),
+      score: 0,
+      pinned_at: None,
+      front_at: None,
+    }
+  }
+
+  fn at(secs: i64) -> Option<DateTime<Utc>> {
+    Some(Utc.ymd(2022, 3, 1).and_hms(20, 0, 0) + chrono::Duration::seconds(secs))
+  }
+
+  fn ids(entries: Vec<QueueEntry>) -> Vec<i64> {
+    entries.into_iter().map(|entry| entry.id).collect()
+  }
+
+  #[test]
+  fn orders_fifo_by_request_time() {
+    let settings = channels::ChannelSettings::new("channel");
+    let entries = vec![
+      QueueEntry {
+        score: 5,
+        ..entry(1, 30)
+      },
+      entry(2, 10),
+      // requested at the same time as 2, but later by id
+      entry(3, 10),
+      entry(0, 20),
+    ];
+    assert_eq!(ids(order(entries, &settings, 0)), [2, 3, 0, 1]);
+  }
+
+  #[test]
+  fn orders_votes_by_score_then_request_time() {
+    let settings = channels::ChannelSettings {
+      queue_mode: QueueMode::Votes,
+      ..channels::ChannelSettings::new("channel")
+    };
+    let entries = vec![
+      QueueEntry {
+        score: 1,
+        ..entry(1, 30)
+      },
+      QueueEntry {
+        score: 3,
+        ..entry(2, 40)
+      },
+      QueueEntry {
+        score: 1,
+        ..entry(3, 10)
+      },
+      entry(4, 0),
+    ];
+    assert_eq!(ids(order(entries, &settings, 0)), [2, 3, 1, 4]);
+  }
+
+  #[test]
+  fn orders_pinned_first_in_the_order_they_were_pinned() {
+    for queue_mode in [QueueMode::Fifo, QueueMode::Votes] {
+      let settings = channels::ChannelSettings {
+        queue_mode,
+        ..channels::ChannelSettings::new("channel")
+      };
+      let entries = vec![
+        QueueEntry {
+          score: 10,
+          ..entry(1, 0)
+        },
+        QueueEntry {
+          pinned_at: at(100),
+          ..entry(2, 50)
+        },
+        QueueEntry {
+          pinned_at: at(60),
+          ..entry(3, 60)
+        },
+        entry(4, 10),
+      ];
+      assert_eq!(ids(order(entries, &settings, 0)), [3, 2, 1, 4], "{queue_mode:?}");
+    }
+  }
+
+  /// Entries of every role, pinned, moved to the front, or neither
+  fn mixed_entries() -> Vec<QueueEntry> {
+    let entry = |id, role, requested| QueueEntry {
+      role,
+      ..entry(id, requested)
+    };
+    vec![
+      entry(1, Role::User, 0),
+      entry(2, Role::Subscriber, 10),
+      QueueEntry {
+        front_at: at(25),
+        ..entry(3, Role::Vip, 20)
+      },
+      entry(4, Role::Moderator, 30),
+      QueueEntry {
+        pinned_at: at(50),
+        ..entry(5, Role::User, 40)
+      },
+      QueueEntry {
+        score: 5,
+        ..entry(6, Role::User, 5)
+      },
+      QueueEntry {
+        front_at: at(15),
+        ..entry(7, Role::Broadcaster, 50)
+      },
+    ]
+  }
+
+  #[test]
+  fn orders_by_role_after_pinned_and_front() {
+    let settings = channels::ChannelSettings {
+      priority_by_role: true,
+      ..channels::ChannelSettings::new("channel")
+    };
+    assert_eq!(ids(order(mixed_entries(), &settings, 0)), [5, 7, 3, 4, 2, 1, 6]);
+    // roles come before upvotes
+    let settings = channels::ChannelSettings {
+      queue_mode: QueueMode::Votes,
+      ..settings
+    };
+    assert_eq!(ids(order(mixed_entries(), &settings, 0)), [5, 7, 3, 4, 2, 6, 1]);
+  }
+
+  #[test]
+  fn orders_lanes_after_pinned_and_front() {
+    let settings = channels::ChannelSettings {
+      priority_by_role: true,
+      subscriber_lane: Some(1),
+      ..channels::ChannelSettings::new("channel")
+    };
+    assert_eq!(ids(order(mixed_entries(), &settings, 0)), [5, 7, 3, 4, 1, 2, 6]);
+    // a subscriber's request was played last, so a regular one is next
+    assert_eq!(ids(order(mixed_entries(), &settings, 1)), [5, 7, 3, 1, 4, 6, 2]);
+    let settings = channels::ChannelSettings {
+      priority_by_role: false,
+      ..settings
+    };
+    assert_eq!(ids(order(mixed_entries(), &settings, 0)), [5, 7, 3, 2, 1, 4, 6]);
+  }
+
+  /// The lanes of `subscribers` and `regulars` entries interleaved, as `S` and `R`
+  fn interleave(subscribers: usize, regulars: usize, per_regular: usize, streak: usize) -> String {
+    let mut positions = (0..subscribers)
+      .map(|rank| (lane_position(true, rank, per_regular, streak), 'S'))
+      .chain((0..regulars).map(|rank| (lane_position(false, rank, per_regular, streak), 'R')))
+      .collect::<Vec<_>>();
+    positions.sort();
+    positions.into_iter().map(|(_, lane)| lane).collect()
+  }
+
+  #[test]
+  fn interleaves_lanes() {
+    assert_eq!(interleave(5, 3, 2, 0), "SSRSSRSR");
+    assert_eq!(interleave(5, 3, 2, 1), "SRSSRSSR");
+    assert_eq!(interleave(5, 3, 2, 2), "RSSRSSRS");
+    assert_eq!(interleave(3, 3, 1, 0), "SRSRSR");
+    // a lane runs out
+    assert_eq!(interleave(1, 3, 2, 0), "SRRR");
+    assert_eq!(interleave(4, 0, 2, 2), "SSSS");
+  }
+
+  crate::db_test!(pending_songs_of_channel, db, channel, cleanup [
+    r#"DELETE FROM queue_entries WHERE channel = $1"#,
+    r#"DELETE FROM songs WHERE starts_with(platform_song_id, $1)"#,
+  ] {
+    let song = db::songs::create(db, SongData::new(Utc::now(), channel.clone(), Platform::Youtube, "test".into())).await?;
+
+    assert!(pending_songs(db, &channel).await?.is_empty());
+    assert!(matches!(add(db, &channel, *song.id(), "viewer", Role::User, None).await?, Added::Entry(_)));
+    assert_eq!(pending_songs(db, &channel).await?, [*song.id()]);
+    assert!(pending_songs(db, "other").await?.iter().all(|id| id != song.id()));
+    next(db, &channels::ChannelSettings::new(&channel)).await?.unwrap();
+    assert!(pending_songs(db, &channel).await?.is_empty());
+
+    let entries: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM queue_entries WHERE channel = $1"#)
+      .bind(&channel)
+      .fetch_one(db)
+      .await?;
+    assert_eq!(entries, 1);
+  });
+
+  crate::db_test!(tokens_are_used_once, db, channel, cleanup [
+    r#"DELETE FROM queue_entries WHERE channel = $1"#,
+    r#"DELETE FROM songs WHERE starts_with(platform_song_id, $1)"#,
+  ] {
+    let mut songs = vec![];
+    for i in 0..3 {
+      let data = SongData::new(Utc::now(), format!("{channel}-{i}"), Platform::Youtube, "test".into());
+      songs.push(*db::songs::create(db, data).await?.id());
+    }
+
+    // concurrent requests can't use more tokens than the allowance
+    let (a, b) = futures::join!(
+      add(db, &channel, songs[0], "vip", Role::Vip, Some(1)),
+      add(db, &channel, songs[1], "vip", Role::Vip, Some(1))
+    );
+    let (a, b) = (a?, b?);
+    let added = match (&a, &b) {
+      (Added::Entry(entry), Added::NoTokens) | (Added::NoTokens, Added::Entry(entry)) => entry,
+      _ => panic!("expected one entry and one rejection, got {a:?} and {b:?}"),
+    };
+    assert!(added.front_at.is_some());
+    // requesting a queued song doesn't use a token
+    assert!(matches!(
+      add(db, &channel, added.song.id().to_owned(), "vip", Role::Vip, Some(1)).await?,
+      Added::Queued
+    ));
+    // other requesters have their own tokens, and requests without a token aren't limited
+    assert!(matches!(add(db, &channel, songs[2], "other", Role::Vip, Some(1)).await?, Added::Entry(_)));
+    let other = if added.song.id() == &songs[0] { songs[1] } else { songs[0] };
+    assert!(matches!(add(db, &channel, other, "vip", Role::Vip, None).await?, Added::Entry(_)));
+
+    let front: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM queue_entries WHERE channel = $1 AND front_at IS NOT NULL"#)
+      .bind(&channel)
+      .fetch_one(db)
+      .await?;
+    assert_eq!(front, 2);
+  });
+}
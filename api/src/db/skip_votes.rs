@@ -0,0 +1,23 @@
+use super::Database;
+
+/// Vote to skip a play, returning the number of votes it has.
+///
+/// Each voter is only counted once per play.
+pub async fn vote(db: &Database, play_id: i64, voter: &str) -> sqlx::Result<i64> {
+  sqlx::query_scalar(
+    r#"
+      WITH inserted AS (
+        INSERT INTO skip_votes (play_id, voter)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        RETURNING voter
+      )
+      -- the statement doesn't see its own insert
+      SELECT (SELECT COUNT(*) FROM skip_votes WHERE play_id = $1) + (SELECT COUNT(*) FROM inserted)
+    "#,
+  )
+  .bind(play_id)
+  .bind(voter)
+  .fetch_one(db)
+  .await
+}
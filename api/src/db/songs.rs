@@ -2,6 +2,17 @@ use chrono::{DateTime, Utc};
 
 use crate::common::platform::Platform;
 
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Availability {
+  Available,
+  /// The song was deleted or made private on its platform
+  Unavailable,
+  /// Players reported too many failures to play the song, it is flagged for review
+  Unplayable,
+}
+
 #[derive(Debug, Clone, serde::Serialize, getset::Getters, sqlx::FromRow)]
 #[getset(get = "pub")]
 pub struct Song {
@@ -18,6 +29,18 @@ pub struct Song {
   #[sqlx(rename = "platform_song_id")]
   song_id: String,
   title: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  artist: Option<String>,
+  /// Duration in seconds
+  #[serde(skip_serializing_if = "Option::is_none")]
+  duration: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  thumbnail_url: Option<String>,
+  #[serde(skip)]
+  availability: Availability,
+  /// When the song's availability was last checked, `None` if it wasn't since it was added
+  #[serde(skip)]
+  last_checked_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, getset::Getters)]
@@ -27,6 +50,9 @@ pub struct SongData {
   platform: Platform,
   song_id: String,
   title: String,
+  artist: Option<String>,
+  duration: Option<i32>,
+  thumbnail_url: Option<String>,
 }
 
 pub struct SongDataSoa {
@@ -34,6 +60,43 @@ pub struct SongDataSoa {
   pub song_id: Vec<String>,
   pub platform: Vec<&'static str>,
   pub title: Vec<String>,
+  pub artist: Vec<Option<String>>,
+  pub duration: Vec<Option<i32>>,
+  pub thumbnail_url: Vec<Option<String>>,
+}
+
+impl From<Song> for SongData {
+  fn from(v: Song) -> Self {
+    Self {
+      published_at: v.published_at,
+      platform: v.platform,
+      song_id: v.song_id,
+      title: v.title,
+      artist: v.artist,
+      duration: v.duration,
+      thumbnail_url: v.thumbnail_url,
+    }
+  }
+}
+
+#[cfg(test)]
+impl Song {
+  /// A song as if it was stored under `id`, for tests which don't need the database
+  pub fn from_data(id: i32, data: SongData) -> Self {
+    Self {
+      id,
+      added_at: data.published_at,
+      published_at: data.published_at,
+      platform: data.platform,
+      song_id: data.song_id,
+      title: data.title,
+      artist: data.artist,
+      duration: data.duration,
+      thumbnail_url: data.thumbnail_url,
+      availability: Availability::Available,
+      last_checked_at: None,
+    }
+  }
 }
 
 impl SongData {
@@ -43,25 +106,52 @@ impl SongData {
       song_id,
       platform,
       title: title.to_lowercase(),
+      artist: None,
+      duration: None,
+      thumbnail_url: None,
     }
   }
 
+  pub fn with_artist(mut self, artist: impl Into<String>) -> Self {
+    self.artist = Some(artist.into());
+    self
+  }
+
+  pub fn with_duration(mut self, duration: chrono::Duration) -> Self {
+    self.duration = Some(duration.num_seconds() as i32);
+    self
+  }
+
+  pub fn with_thumbnail_url(mut self, thumbnail_url: impl Into<String>) -> Self {
+    self.thumbnail_url = Some(thumbnail_url.into());
+    self
+  }
+
   pub fn soa(data: Vec<SongData>) -> SongDataSoa {
     let mut published_at = Vec::with_capacity(data.len());
     let mut song_id = Vec::with_capacity(data.len());
     let mut platform = Vec::with_capacity(data.len());
     let mut title = Vec::with_capacity(data.len());
+    let mut artist = Vec::with_capacity(data.len());
+    let mut duration = Vec::with_capacity(data.len());
+    let mut thumbnail_url = Vec::with_capacity(data.len());
     for item in data.into_iter() {
       published_at.push(item.published_at);
       song_id.push(item.song_id);
       platform.push(item.platform.as_str());
       title.push(item.title);
+      artist.push(item.artist);
+      duration.push(item.duration);
+      thumbnail_url.push(item.thumbnail_url);
     }
     SongDataSoa {
       published_at,
       song_id,
       platform,
       title,
+      artist,
+      duration,
+      thumbnail_url,
     }
   }
 }
@@ -78,8 +168,8 @@ where
         WHERE (platform, platform_song_id) = ($2, $3)
       ),
       inserted AS (
-        INSERT INTO songs (published_at, platform, platform_song_id, title)
-          VALUES ($1, $2, $3, $4)
+        INSERT INTO songs (published_at, platform, platform_song_id, title, artist, duration, thumbnail_url)
+          VALUES ($1, $2, $3, $4, $5, $6, $7)
         ON CONFLICT DO NOTHING
         RETURNING *
       )
@@ -88,10 +178,13 @@ where
       SELECT * FROM inserted;
     "#,
   )
-  .bind(&data.published_at)
-  .bind(&data.platform)
+  .bind(data.published_at)
+  .bind(data.platform)
   .bind(&data.song_id)
   .bind(&data.title)
+  .bind(&data.artist)
+  .bind(data.duration)
+  .bind(&data.thumbnail_url)
   .fetch_one(db)
   .await
 }
@@ -103,8 +196,10 @@ where
   let songs = SongData::soa(data);
   sqlx::query(
     r#"
-      INSERT INTO songs (published_at, platform, platform_song_id, title)
-        SELECT * FROM UNNEST($1::timestamptz[], $2::text[], $3::text[], $4::text[])
+      INSERT INTO songs (published_at, platform, platform_song_id, title, artist, duration, thumbnail_url)
+        SELECT * FROM UNNEST(
+          $1::timestamptz[], $2::text[], $3::text[], $4::text[], $5::text[], $6::int4[], $7::text[]
+        )
       ON CONFLICT DO NOTHING;
     "#,
   )
@@ -112,26 +207,141 @@ where
   .bind(&songs.platform)
   .bind(&songs.song_id)
   .bind(&songs.title)
+  .bind(&songs.artist)
+  .bind(&songs.duration)
+  .bind(&songs.thumbnail_url)
   .execute(db)
   .await?;
   Ok(())
 }
 
-pub async fn random<'db, E>(db: E) -> sqlx::Result<Song>
+pub async fn get<'db, E>(db: E, platform: Platform, id: &str) -> sqlx::Result<Option<Song>>
 where
   E: sqlx::PgExecutor<'db> + 'db,
 {
   sqlx::query_as(
     r#"
       SELECT * FROM songs
-      OFFSET floor(random() * (SELECT COUNT(*) FROM songs))
+      WHERE (platform, platform_song_id) = ($1, $2)
+    "#,
+  )
+  .bind(platform)
+  .bind(id)
+  .fetch_optional(db)
+  .await
+}
+
+/// The ids of songs on `platform` which exist, out of `ids`
+pub async fn exists_bulk<'db, E>(db: E, platform: Platform, ids: &[String]) -> sqlx::Result<Vec<String>>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query_scalar(
+    r#"
+      SELECT platform_song_id FROM songs
+      WHERE platform = $1 AND platform_song_id = ANY($2)
+    "#,
+  )
+  .bind(platform)
+  .bind(ids)
+  .fetch_all(db)
+  .await
+}
+
+/// Songs on `platform` with any of the `ids`, in no particular order
+pub async fn get_many<'db, E>(db: E, platform: Platform, ids: &[String]) -> sqlx::Result<Vec<Song>>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query_as(
+    r#"
+      SELECT * FROM songs
+      WHERE platform = $1 AND platform_song_id = ANY($2)
+    "#,
+  )
+  .bind(platform)
+  .bind(ids)
+  .fetch_all(db)
+  .await
+}
+
+/// A random available song, except the ones in `exclude`
+pub async fn random<'db, E>(db: E, exclude: &[i32]) -> sqlx::Result<Option<Song>>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query_as(
+    r#"
+      SELECT * FROM songs
+      WHERE availability = 'available' AND NOT song_id = ANY($1)
+      OFFSET floor(random() * (SELECT COUNT(*) FROM songs WHERE availability = 'available' AND NOT song_id = ANY($1)))
       LIMIT 1
     "#,
   )
-  .fetch_one(db)
+  .bind(exclude)
+  .fetch_optional(db)
   .await
 }
 
+/// Ids of up to `limit` songs on `platform` which were not checked since `checked_before`, least recently checked first
+pub async fn unchecked<'db, E>(
+  db: E,
+  platform: Platform,
+  checked_before: DateTime<Utc>,
+  limit: i64,
+) -> sqlx::Result<Vec<String>>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  sqlx::query_scalar(
+    r#"
+      SELECT platform_song_id FROM songs
+      WHERE platform = $1 AND (last_checked_at IS NULL OR last_checked_at < $2)
+      ORDER BY last_checked_at NULLS FIRST
+      LIMIT $3
+    "#,
+  )
+  .bind(platform)
+  .bind(checked_before)
+  .bind(limit)
+  .fetch_all(db)
+  .await
+}
+
+/// Record the availability of songs on `platform`, marking them as checked
+///
+/// Songs which exist are not `Unplayable` because of that, so those stay flagged.
+pub async fn set_availability<'db, E>(
+  db: E,
+  platform: Platform,
+  ids: &[String],
+  availability: Availability,
+) -> sqlx::Result<u64>
+where
+  E: sqlx::PgExecutor<'db> + 'db,
+{
+  Ok(
+    sqlx::query(
+      r#"
+        UPDATE songs
+        SET
+          availability = CASE
+            WHEN availability = 'unplayable' AND $3 = 'available' THEN availability
+            ELSE $3
+          END,
+          last_checked_at = now()
+        WHERE platform = $1 AND platform_song_id = ANY($2)
+      "#,
+    )
+    .bind(platform)
+    .bind(ids)
+    .bind(availability)
+    .execute(db)
+    .await?
+    .rows_affected(),
+  )
+}
+
 pub async fn exists<'db, E>(db: E, platform: Platform, id: String) -> sqlx::Result<bool>
 where
   E: sqlx::PgExecutor<'db> + 'db,
@@ -141,7 +351,7 @@ where
       SELECT EXISTS(SELECT 1 FROM songs WHERE (platform, platform_song_id) = ($1, $2))
     "#,
   )
-  .bind(&platform)
+  .bind(platform)
   .bind(&id)
   .fetch_one(db)
   .await
@@ -0,0 +1,214 @@
+use super::{songs::Song, Database};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, Row};
+
+/// Only rows of `channel` within the window given by `$2` and `$3`.
+///
+/// `channel_play_stats` aggregates plays per hour, so both ends include the whole hour they fall into.
+const WINDOW: &str = r#"
+  channel = $1
+  AND ($2::timestamptz IS NULL OR hour >= date_trunc('hour', $2::timestamptz))
+  AND ($3::timestamptz IS NULL OR hour < $3::timestamptz)
+"#;
+
+/// What the songs of [`songs`] are sorted by, descending
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SongSort {
+  #[default]
+  Plays,
+  SkipRate,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SongStats {
+  pub song: Song,
+  pub plays: i64,
+  pub skips: i64,
+  /// Fraction of the plays which were skipped
+  pub skip_rate: f64,
+}
+
+impl<'r> sqlx::FromRow<'r, PgRow> for SongStats {
+  fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+    Ok(Self {
+      song: Song::from_row(row)?,
+      plays: row.try_get("plays")?,
+      skips: row.try_get("skips")?,
+      skip_rate: row.try_get("skip_rate")?,
+    })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct RequesterStats {
+  pub requester: String,
+  /// Number of songs requested by the requester which were played
+  pub requests: i64,
+  pub skips: i64,
+  /// Total time their requests were played for, in seconds
+  pub play_seconds: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct HourStats {
+  /// Hour of the day in UTC, from 0 to 23
+  pub hour: i32,
+  pub plays: i64,
+  /// Plays which were requested by someone
+  pub requests: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct Summary {
+  pub plays: i64,
+  /// Plays which were requested by someone
+  pub requests: i64,
+  pub skips: i64,
+  /// Total play time in seconds, of the plays which have ended
+  pub play_seconds: f64,
+  /// Plays of songs which were never played on the channel before
+  pub new_plays: i64,
+  pub repeat_plays: i64,
+  /// Fraction of the plays which were new, `0` without plays
+  pub new_ratio: f64,
+}
+
+/// Recompute the statistics from the play history, without blocking readers
+pub async fn refresh(db: &Database) -> sqlx::Result<()> {
+  sqlx::query(r#"REFRESH MATERIALIZED VIEW CONCURRENTLY channel_play_stats"#)
+    .execute(db)
+    .await?;
+  Ok(())
+}
+
+/// Up to `limit` songs played on `channel` at least `min_plays` times within the window
+pub async fn songs(
+  db: &Database,
+  channel: &str,
+  since: Option<DateTime<Utc>>,
+  until: Option<DateTime<Utc>>,
+  sort: SongSort,
+  min_plays: i64,
+  limit: i64,
+) -> sqlx::Result<Vec<SongStats>> {
+  let order_by = match sort {
+    SongSort::Plays => "plays DESC, skip_rate DESC",
+    SongSort::SkipRate => "skip_rate DESC, plays DESC",
+  };
+  sqlx::query_as(&format!(
+    r#"
+      SELECT songs.*, stats.plays, stats.skips, stats.skips::float8 / stats.plays AS skip_rate
+      FROM (
+        SELECT song_id, SUM(plays)::int8 AS plays, SUM(skips)::int8 AS skips
+        FROM channel_play_stats
+        WHERE {WINDOW}
+        GROUP BY song_id
+      ) AS stats
+      JOIN songs USING (song_id)
+      WHERE stats.plays >= $4
+      ORDER BY {order_by}, song_id
+      LIMIT $5
+    "#
+  ))
+  .bind(channel)
+  .bind(since)
+  .bind(until)
+  .bind(min_plays)
+  .bind(limit)
+  .fetch_all(db)
+  .await
+}
+
+/// Up to `limit` people whose requests were played the most on `channel` within the window
+pub async fn requesters(
+  db: &Database,
+  channel: &str,
+  since: Option<DateTime<Utc>>,
+  until: Option<DateTime<Utc>>,
+  limit: i64,
+) -> sqlx::Result<Vec<RequesterStats>> {
+  sqlx::query_as(&format!(
+    r#"
+      SELECT
+        requester,
+        SUM(plays)::int8 AS requests,
+        SUM(skips)::int8 AS skips,
+        SUM(play_seconds) AS play_seconds
+      FROM channel_play_stats
+      WHERE {WINDOW} AND requester <> ''
+      GROUP BY requester
+      ORDER BY requests DESC, requester
+      LIMIT $4
+    "#
+  ))
+  .bind(channel)
+  .bind(since)
+  .bind(until)
+  .bind(limit)
+  .fetch_all(db)
+  .await
+}
+
+/// Plays on `channel` within the window per hour of the day, including the hours without any
+pub async fn hours(
+  db: &Database,
+  channel: &str,
+  since: Option<DateTime<Utc>>,
+  until: Option<DateTime<Utc>>,
+) -> sqlx::Result<Vec<HourStats>> {
+  sqlx::query_as(&format!(
+    r#"
+      SELECT
+        hour_of_day AS hour,
+        COALESCE(SUM(plays), 0)::int8 AS plays,
+        COALESCE(SUM(plays) FILTER (WHERE requester <> ''), 0)::int8 AS requests
+      FROM generate_series(0, 23) AS hour_of_day
+      LEFT JOIN channel_play_stats
+        ON EXTRACT(HOUR FROM hour AT TIME ZONE 'UTC') = hour_of_day AND {WINDOW}
+      GROUP BY hour_of_day
+      ORDER BY hour_of_day
+    "#
+  ))
+  .bind(channel)
+  .bind(since)
+  .bind(until)
+  .fetch_all(db)
+  .await
+}
+
+/// Totals of the plays on `channel` within the window
+pub async fn summary(
+  db: &Database,
+  channel: &str,
+  since: Option<DateTime<Utc>>,
+  until: Option<DateTime<Utc>>,
+) -> sqlx::Result<Summary> {
+  sqlx::query_as(&format!(
+    r#"
+      SELECT
+        plays,
+        requests,
+        skips,
+        play_seconds,
+        new_plays,
+        plays - new_plays AS repeat_plays,
+        COALESCE(new_plays::float8 / NULLIF(plays, 0), 0) AS new_ratio
+      FROM (
+        SELECT
+          COALESCE(SUM(plays), 0)::int8 AS plays,
+          COALESCE(SUM(plays) FILTER (WHERE requester <> ''), 0)::int8 AS requests,
+          COALESCE(SUM(skips), 0)::int8 AS skips,
+          COALESCE(SUM(play_seconds), 0) AS play_seconds,
+          COALESCE(SUM(first_plays), 0)::int8 AS new_plays
+        FROM channel_play_stats
+        WHERE {WINDOW}
+      ) AS totals
+    "#
+  ))
+  .bind(channel)
+  .bind(since)
+  .bind(until)
+  .fetch_one(db)
+  .await
+}
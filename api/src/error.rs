@@ -56,7 +56,7 @@ impl IntoMsgAndCode for String {
   }
 }
 
-impl<'a> IntoMsgAndCode for &'a str {
+impl IntoMsgAndCode for &str {
   fn into_msg_and_code(self) -> (StatusCode, String) {
     (StatusCode::BAD_REQUEST, self.into())
   }
@@ -0,0 +1,115 @@
+use crate::{
+  client::Youtube,
+  common::{config::Config, platform::Platform},
+  db::{
+    self,
+    locks::{LockClass, AVAILABILITY_CHECK_KEY},
+    songs::Availability,
+    Database,
+  },
+};
+use actix::prelude::*;
+use chrono::Utc;
+
+/// Maximum number of songs checked per run, looked up in chunks of 50
+const MAX_SONGS_PER_CHECK: i64 = 500;
+
+/// Periodically checks whether songs were deleted or made private on their platform,
+/// so that `/random` and playlists stop returning them.
+///
+/// Songs are re-checked every `Config::availability_recheck_interval`, least recently checked first.
+/// Only YouTube songs are checked for now.
+pub struct AvailabilityChecker {
+  job: Job,
+  check_interval: std::time::Duration,
+  running: bool,
+}
+
+#[derive(Clone)]
+struct Job {
+  db: Database,
+  youtube: Youtube,
+  recheck_interval: chrono::Duration,
+}
+
+impl AvailabilityChecker {
+  pub fn new(config: &Config, db: Database, youtube: Youtube) -> Self {
+    Self {
+      job: Job {
+        db,
+        youtube,
+        recheck_interval: config.availability_recheck_interval,
+      },
+      check_interval: config
+        .availability_check_interval
+        .to_std()
+        .unwrap_or_else(|_| std::time::Duration::from_secs(600)),
+      running: false,
+    }
+  }
+}
+
+impl Actor for AvailabilityChecker {
+  type Context = Context<Self>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    ctx.run_interval(self.check_interval, |this, ctx| {
+      // a check may take longer than the interval, don't overlap them
+      if this.running {
+        return;
+      }
+      this.running = true;
+      ctx.spawn(
+        this
+          .job
+          .clone()
+          .run()
+          .into_actor(this)
+          .map(|_, this, _| this.running = false),
+      );
+    });
+  }
+}
+
+impl Job {
+  async fn run(self) {
+    match self.try_run().await {
+      Ok(Some((available, unavailable))) if available + unavailable > 0 => {
+        log::info!("Checked availability of songs: {available} available, {unavailable} unavailable")
+      }
+      Ok(Some(_)) => {}
+      Ok(None) => log::debug!("Skipped availability check, it is running elsewhere"),
+      Err(e) => log::error!("Failed to check availability of songs: {e:?}"),
+    }
+  }
+
+  /// Returns `None` if another instance is already checking
+  async fn try_run(&self) -> anyhow::Result<Option<(u64, u64)>> {
+    let mut conn = self.db.acquire().await?;
+    if !db::locks::try_lock(&mut conn, LockClass::AvailabilityCheck, AVAILABILITY_CHECK_KEY).await? {
+      return Ok(None);
+    }
+    let result = self.check_youtube().await;
+    db::locks::unlock(&mut conn, LockClass::AvailabilityCheck, AVAILABILITY_CHECK_KEY).await?;
+    result.map(Some)
+  }
+
+  async fn check_youtube(&self) -> anyhow::Result<(u64, u64)> {
+    let ids = db::songs::unchecked(
+      &self.db,
+      Platform::Youtube,
+      Utc::now() - self.recheck_interval,
+      MAX_SONGS_PER_CHECK,
+    )
+    .await?;
+    if ids.is_empty() {
+      return Ok((0, 0));
+    }
+    let lookup = self.youtube.videos(ids.iter().map(|id| id.as_str())).await?;
+    let found = lookup.found.into_iter().map(|video| video.id).collect::<Vec<_>>();
+    let available = db::songs::set_availability(&self.db, Platform::Youtube, &found, Availability::Available).await?;
+    let unavailable =
+      db::songs::set_availability(&self.db, Platform::Youtube, &lookup.missing, Availability::Unavailable).await?;
+    Ok((available, unavailable))
+  }
+}
@@ -0,0 +1,7 @@
+pub mod availability_check;
+pub mod playlist_refresh;
+pub mod stats_refresh;
+
+pub use availability_check::AvailabilityChecker;
+pub use playlist_refresh::PlaylistRefresher;
+pub use stats_refresh::StatsRefresher;
@@ -0,0 +1,169 @@
+use crate::{
+  client::{Soundcloud, Youtube},
+  common::config::Config,
+  db::{self, locks::LockClass, playlists::Playlist, Database},
+  playlist_sync::{self, PlaylistFetches},
+};
+use actix::prelude::*;
+use chrono::Utc;
+use futures::StreamExt;
+use rand::Rng;
+
+/// Maximum number of stale playlists picked up per check
+const MAX_PLAYLISTS_PER_CHECK: i64 = 100;
+
+/// Periodically refreshes playlists which are older than `Config::playlist_refresh_interval`,
+/// so that viewers don't have to wait for the crawl when requesting them.
+///
+/// Every refresh holds a Postgres advisory lock on the playlist,
+/// so multiple API instances never refresh the same playlist at once.
+///
+/// Playlists which fail to refresh are retried after a delay, starting at the check interval
+/// and doubling with every consecutive failure, up to the refresh interval.
+pub struct PlaylistRefresher {
+  job: Job,
+  check_interval: std::time::Duration,
+  running: bool,
+}
+
+#[derive(Clone)]
+struct Job {
+  fetches: PlaylistFetches,
+  db: Database,
+  youtube: Youtube,
+  soundcloud: Option<Soundcloud>,
+  refresh_interval: chrono::Duration,
+  check_interval: chrono::Duration,
+  jitter: std::time::Duration,
+  concurrency: usize,
+}
+
+impl PlaylistRefresher {
+  pub fn new(
+    config: &Config,
+    fetches: PlaylistFetches,
+    db: Database,
+    youtube: Youtube,
+    soundcloud: Option<Soundcloud>,
+  ) -> Self {
+    Self {
+      job: Job {
+        fetches,
+        db,
+        youtube,
+        soundcloud,
+        refresh_interval: config.playlist_refresh_interval,
+        check_interval: config.playlist_refresh_check_interval,
+        jitter: config.playlist_refresh_jitter.to_std().unwrap_or_default(),
+        concurrency: config.playlist_refresh_concurrency.max(1),
+      },
+      check_interval: config
+        .playlist_refresh_check_interval
+        .to_std()
+        .unwrap_or_else(|_| std::time::Duration::from_secs(60)),
+      running: false,
+    }
+  }
+}
+
+impl Actor for PlaylistRefresher {
+  type Context = Context<Self>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    ctx.run_interval(self.check_interval, |this, ctx| {
+      // a check may take longer than the interval, don't overlap them
+      if this.running {
+        return;
+      }
+      this.running = true;
+      ctx.spawn(
+        this
+          .job
+          .clone()
+          .run()
+          .into_actor(this)
+          .map(|_, this, _| this.running = false),
+      );
+    });
+  }
+}
+
+impl Job {
+  async fn run(self) {
+    let stale = match db::playlists::stale(&self.db, Utc::now() - self.refresh_interval, MAX_PLAYLISTS_PER_CHECK).await
+    {
+      Ok(stale) => stale,
+      Err(e) => {
+        log::error!("Failed to query stale playlists: {e:?}");
+        return;
+      }
+    };
+    if stale.is_empty() {
+      return;
+    }
+    log::info!("Refreshing {} stale playlists", stale.len());
+    futures::stream::iter(stale)
+      .for_each_concurrent(self.concurrency, |playlist| self.refresh(playlist))
+      .await;
+  }
+
+  async fn refresh(&self, playlist: Playlist) {
+    // spread out the refreshes of playlists which went stale at the same time
+    let delay = rand::thread_rng().gen_range(std::time::Duration::ZERO..=self.jitter);
+    actix_rt::time::sleep(delay).await;
+    match self.try_refresh(&playlist).await {
+      Ok(true) => log::info!(
+        "Refreshed playlist {} {}",
+        playlist.platform().as_str(),
+        playlist.playlist_id()
+      ),
+      Ok(false) => log::debug!(
+        "Skipped playlist {} {}, it is being refreshed elsewhere",
+        playlist.platform().as_str(),
+        playlist.playlist_id()
+      ),
+      Err(e) => {
+        log::error!(
+          "Failed to refresh playlist {} {}: {e:?}",
+          playlist.platform().as_str(),
+          playlist.playlist_id()
+        );
+        if let Err(e) =
+          db::playlists::refresh_failed(&self.db, *playlist.id(), self.check_interval, self.refresh_interval).await
+        {
+          log::error!("Failed to record refresh failure of playlist {}: {e:?}", playlist.id());
+        }
+      }
+    }
+  }
+
+  /// Returns `false` if the playlist is already being refreshed, or was refreshed in the meantime
+  async fn try_refresh(&self, playlist: &Playlist) -> anyhow::Result<bool> {
+    let mut conn = self.db.acquire().await?;
+    if !db::locks::try_lock(&mut conn, LockClass::PlaylistRefresh, *playlist.id()).await? {
+      return Ok(false);
+    }
+    let result = self.refresh_locked(playlist).await;
+    db::locks::unlock(&mut conn, LockClass::PlaylistRefresh, *playlist.id()).await?;
+    result
+  }
+
+  async fn refresh_locked(&self, playlist: &Playlist) -> anyhow::Result<bool> {
+    // another instance may have finished refreshing it just before we acquired the lock
+    match db::playlists::get(&self.db, *playlist.platform(), playlist.playlist_id()).await? {
+      Some(current) if Utc::now() > *current.updated_at() + self.refresh_interval => {}
+      _ => return Ok(false),
+    }
+    playlist_sync::refresh(
+      &self.fetches,
+      &self.db,
+      &self.youtube,
+      self.soundcloud.as_ref(),
+      *playlist.platform(),
+      playlist.playlist_id(),
+      || {},
+    )
+    .await?;
+    Ok(true)
+  }
+}
@@ -0,0 +1,79 @@
+use crate::{
+  common::config::Config,
+  db::{
+    self,
+    locks::{LockClass, STATS_REFRESH_KEY},
+    Database,
+  },
+};
+use actix::prelude::*;
+
+/// Periodically recomputes the aggregated play history behind the `/stats` endpoints,
+/// so that they don't have to scan every play of a channel on each request.
+pub struct StatsRefresher {
+  job: Job,
+  refresh_interval: std::time::Duration,
+  running: bool,
+}
+
+#[derive(Clone)]
+struct Job {
+  db: Database,
+}
+
+impl StatsRefresher {
+  pub fn new(config: &Config, db: Database) -> Self {
+    Self {
+      job: Job { db },
+      refresh_interval: config
+        .stats_refresh_interval
+        .to_std()
+        .unwrap_or_else(|_| std::time::Duration::from_secs(300)),
+      running: false,
+    }
+  }
+}
+
+impl Actor for StatsRefresher {
+  type Context = Context<Self>;
+
+  fn started(&mut self, ctx: &mut Self::Context) {
+    ctx.run_interval(self.refresh_interval, |this, ctx| {
+      // a refresh may take longer than the interval, don't overlap them
+      if this.running {
+        return;
+      }
+      this.running = true;
+      ctx.spawn(
+        this
+          .job
+          .clone()
+          .run()
+          .into_actor(this)
+          .map(|_, this, _| this.running = false),
+      );
+    });
+  }
+}
+
+impl Job {
+  async fn run(self) {
+    match self.try_run().await {
+      Ok(true) => log::debug!("Refreshed channel statistics"),
+      Ok(false) => log::debug!("Skipped refreshing channel statistics, it is running elsewhere"),
+      Err(e) => log::error!("Failed to refresh channel statistics: {e:?}"),
+    }
+  }
+
+  /// Returns `false` if another instance is already refreshing
+  async fn try_run(&self) -> anyhow::Result<bool> {
+    let mut conn = self.db.acquire().await?;
+    if !db::locks::try_lock(&mut conn, LockClass::StatsRefresh, STATS_REFRESH_KEY).await? {
+      return Ok(false);
+    }
+    let result = db::stats::refresh(&self.db).await;
+    db::locks::unlock(&mut conn, LockClass::StatsRefresh, STATS_REFRESH_KEY).await?;
+    result?;
+    Ok(true)
+  }
+}
@@ -3,8 +3,11 @@ pub mod common;
 #[macro_use]
 pub mod db;
 pub mod error;
+pub mod jobs;
+pub mod playlist_sync;
 pub mod v1;
 
+use actix::Actor;
 use actix_cors::Cors;
 use actix_web::{self as web, dev::Server, http::header, middleware, web::Data, App, HttpResponse, HttpServer};
 use common::config::Config;
@@ -19,11 +22,79 @@ pub async fn start(socket: TcpListener, config: Config) -> anyhow::Result<Server
   // TODO: authentication
   let db = db::connect(&config.database_url).await?;
   let yt = client::Youtube::new("https://www.googleapis.com/youtube/v3", config.youtube_key.clone());
+  let sc = config
+    .soundcloud_client_id
+    .clone()
+    .map(|client_id| client::Soundcloud::new("https://api-v2.soundcloud.com", client_id));
+  let spotify = config
+    .spotify_client_id
+    .clone()
+    .zip(config.spotify_client_secret.clone())
+    .map(|(client_id, client_secret)| {
+      client::Spotify::new(
+        "https://api.spotify.com/v1",
+        "https://accounts.spotify.com",
+        client_id,
+        client_secret,
+      )
+    });
+  let oembed = Some(config.oembed_providers.clone())
+    .filter(|providers| !providers.is_empty())
+    .map(client::Oembed::new);
+  // shared between all workers, so that concurrent requests are coalesced regardless of which worker serves them
+  let playlist_fetches = Data::new(playlist_sync::PlaylistFetches::new());
+  let video_lookups = Data::new(v1::memo::VideoLookups::new());
+  let channel_events = common::events::ChannelEvents::new();
+  jobs::PlaylistRefresher::new(
+    &config,
+    playlist_fetches.get_ref().clone(),
+    db.clone(),
+    yt.clone(),
+    sc.clone(),
+  )
+  .start();
+  jobs::AvailabilityChecker::new(&config, db.clone(), yt.clone()).start();
+  jobs::StatsRefresher::new(&config, db.clone()).start();
+  {
+    let (db, channel_events) = (db.clone(), channel_events.clone());
+    web::rt::spawn(async move {
+      if let Err(e) = db::events::forward(db, channel_events).await {
+        log::error!("Failed to listen for channel events: {e:?}");
+      }
+    });
+  }
+  let local = config.local_library.clone().map(client::LocalLibrary::new);
+  if let Some(local) = local.clone() {
+    let db = db.clone();
+    web::rt::spawn(async move {
+      match v1::local::rescan(&db, local).await {
+        Ok(summary) => log::info!("Scanned local library: {summary:?}"),
+        Err(e) => log::error!("Failed to scan local library: {e:?}"),
+      }
+    });
+  }
   Ok(
     HttpServer::new(move || {
-      App::new()
+      let mut app = App::new()
         .app_data(Data::new(db.clone()))
         .app_data(Data::new(yt.clone()))
+        .app_data(playlist_fetches.clone())
+        .app_data(video_lookups.clone())
+        .app_data(Data::new(channel_events.clone()));
+      // handlers take `Option<Data<T>>` for optional platforms, so they're only registered when configured
+      if let Some(sc) = &sc {
+        app = app.app_data(Data::new(sc.clone()));
+      }
+      if let Some(spotify) = &spotify {
+        app = app.app_data(Data::new(spotify.clone()));
+      }
+      if let Some(oembed) = &oembed {
+        app = app.app_data(Data::new(oembed.clone()));
+      }
+      if let Some(local) = &local {
+        app = app.app_data(Data::new(local.clone()));
+      }
+      app
         .app_data(Data::new(config.clone()))
         .wrap(
           Cors::default()
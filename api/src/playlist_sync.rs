@@ -0,0 +1,144 @@
+//! Fetching playlists from their platforms and storing them, shared by the HTTP handlers and the background jobs.
+
+use crate::{
+  client::{Soundcloud, Youtube},
+  common::{platform::Platform, single_flight::SingleFlight},
+  db::{
+    self,
+    playlists::{PlaylistData, PlaylistMetadata},
+    songs::SongData,
+    Database,
+  },
+};
+use anyhow::Context;
+use std::{collections::HashMap, sync::Arc};
+
+/// The result of fetching a playlist
+pub enum Fetched {
+  /// The songs of the playlist didn't change since it was last fetched, but its metadata may have
+  Unchanged(Option<PlaylistMetadata>),
+  Changed(PlaylistData),
+}
+
+/// Fetch the current contents of a playlist from its platform, calling `on_page` after every fetched page
+///
+/// YouTube playlists are fetched conditionally, so unchanged pages cost neither quota nor `videos` calls.
+pub async fn fetch(
+  db: &Database,
+  client: &Youtube,
+  soundcloud: Option<&Soundcloud>,
+  platform: Platform,
+  id: &str,
+  mut on_page: impl FnMut(),
+) -> anyhow::Result<Fetched> {
+  let data = match platform {
+    Platform::Youtube => {
+      let known = db::playlists::pages(db, platform, id).await?;
+      let (details, pages) = futures::join!(client.playlist(id), client.playlist_pages(id, &known, on_page));
+      let metadata = details
+        .context("Failed to fetch playlist from YouTube")?
+        .map(PlaylistMetadata::from);
+      let pages = pages.context("Failed to fetch playlist from YouTube")?;
+      if pages.len() == known.len() && pages.iter().all(|(_, videos)| videos.is_none()) {
+        return Ok(Fetched::Unchanged(metadata));
+      }
+      // the songs of unchanged pages were stored when they were last fetched
+      let unchanged_ids = pages
+        .iter()
+        .filter(|(_, videos)| videos.is_none())
+        .flat_map(|(page, _)| page.video_ids.iter().cloned())
+        .collect::<Vec<_>>();
+      let mut stored = db::songs::get_many(db, platform, &unchanged_ids)
+        .await?
+        .into_iter()
+        .map(|song| (song.song_id().clone(), SongData::from(song)))
+        .collect::<HashMap<_, _>>();
+      let mut songs = vec![];
+      let mut page_etags = vec![];
+      for (page, videos) in pages {
+        match videos {
+          Some(videos) => songs.extend(videos.into_iter().map(SongData::from)),
+          None => songs.extend(page.video_ids.iter().filter_map(|id| stored.remove(id))),
+        }
+        page_etags.push(page);
+      }
+      PlaylistData::new(platform, id.into(), songs)
+        .with_pages(page_etags)
+        .with_metadata(metadata)
+    }
+    Platform::Soundcloud => {
+      let tracks = soundcloud
+        .context("SoundCloud is not configured")?
+        .playlist_tracks(id)
+        .await
+        .context("Failed to fetch playlist from SoundCloud")?;
+      on_page();
+      PlaylistData::new(
+        platform,
+        id.into(),
+        tracks.into_iter().map(SongData::from).collect::<Vec<_>>(),
+      )
+    }
+    Platform::Spotify => anyhow::bail!("Spotify playlists are not supported"),
+    Platform::Oembed => anyhow::bail!("oEmbed does not support playlists"),
+    Platform::Local => anyhow::bail!("Local library does not support playlists"),
+  };
+  Ok(Fetched::Changed(data))
+}
+
+/// An error shared between every caller awaiting the same fetch, keeping its chain of causes
+#[derive(Debug, Clone)]
+pub struct SharedError(Arc<anyhow::Error>);
+
+impl std::fmt::Display for SharedError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    std::fmt::Display::fmt(&self.0, f)
+  }
+}
+
+impl std::error::Error for SharedError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    self.0.source()
+  }
+}
+
+/// Coalesces concurrent fetches of the same playlist, resolving to the number of stored songs
+pub type PlaylistFetches = SingleFlight<(Platform, String), Result<usize, SharedError>>;
+
+/// Fetch and store a playlist, returning the number of stored songs.
+///
+/// If the same playlist is already being fetched, this awaits that fetch instead,
+/// in which case `on_page` is never called.
+pub async fn refresh(
+  fetches: &PlaylistFetches,
+  db: &Database,
+  client: &Youtube,
+  soundcloud: Option<&Soundcloud>,
+  platform: Platform,
+  id: &str,
+  on_page: impl FnMut() + Send + 'static,
+) -> anyhow::Result<usize> {
+  let (db, client, soundcloud, owned_id) = (db.clone(), client.clone(), soundcloud.cloned(), id.to_string());
+  let stored = fetches
+    .run((platform, id.to_string()), move || async move {
+      let stored = async {
+        Ok(
+          match fetch(&db, &client, soundcloud.as_ref(), platform, &owned_id, on_page).await? {
+            Fetched::Unchanged(metadata) => db::playlists::touch(&db, platform, &owned_id, metadata.as_ref())
+              .await
+              .context("Failed to store playlist")? as usize,
+            Fetched::Changed(data) => {
+              let songs = data.songs().len();
+              db::playlists::upsert(&db, data)
+                .await
+                .context("Failed to store playlist")?;
+              songs
+            }
+          },
+        )
+      };
+      stored.await.map_err(|e| SharedError(Arc::new(e)))
+    })
+    .await?;
+  Ok(stored)
+}
@@ -0,0 +1,98 @@
+use crate::{
+  common::events::ChannelEvents,
+  db::{self, channels::ChannelSettings, Database},
+  error::{Error, FailWith},
+};
+use actix_web::{get, http::header, post, put, web, web::Json, web::Query, HttpResponse, Result};
+use bytes::Bytes;
+use futures::StreamExt;
+use tokio::sync::broadcast::error::RecvError;
+
+/// How often a comment is sent to idle event streams, so that proxies and players don't time them out
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SettingsRequest {
+  pub channel: String,
+}
+
+/// Obtain the settings of a channel, or the defaults if it was never configured.
+#[get("/channel/settings")]
+pub async fn get_settings(db: web::Data<Database>, Query(query): Query<SettingsRequest>) -> Result<HttpResponse> {
+  let settings = db::channels::settings(db.get_ref(), &query.channel).await.internal()?;
+  Ok(HttpResponse::Ok().json(settings))
+}
+
+/// Replace the settings of a channel.
+#[put("/channel/settings")]
+pub async fn put_settings(db: web::Data<Database>, Json(body): Json<ChannelSettings>) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  if body.cooldown_minutes.unwrap_or(0) < 0 || body.cooldown_songs.unwrap_or(0) < 0 {
+    return Err(Error::from("Cooldowns can't be negative").into());
+  }
+  if body.skip_votes.unwrap_or(1) < 1 {
+    return Err(Error::from("At least one vote is required to skip").into());
+  }
+  if !(1..=100).contains(&body.skip_percent.unwrap_or(1)) {
+    return Err(Error::from("The skip percentage must be between 1 and 100").into());
+  }
+  if body.max_upvotes.unwrap_or(0) < 0 {
+    return Err(Error::from("The upvote limit can't be negative").into());
+  }
+  if body.subscriber_lane.unwrap_or(1) < 1 {
+    return Err(Error::from("At least one subscriber request must be played per regular request").into());
+  }
+  if body.vip_tokens.unwrap_or(0) < 0 {
+    return Err(Error::from("The number of VIP tokens can't be negative").into());
+  }
+  let settings = db::channels::set_settings(db.get_ref(), &body).await.internal()?;
+  Ok(HttpResponse::Ok().json(settings))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct StreamStart {
+  pub channel: String,
+}
+
+/// Record that a stream started on a channel, handing out VIP tokens again.
+#[post("/channel/stream")]
+pub async fn start_stream(db: web::Data<Database>, Json(body): Json<StreamStart>) -> Result<HttpResponse> {
+  let stream = db::channels::start_stream(db.get_ref(), &body.channel)
+    .await
+    .internal()?;
+  Ok(HttpResponse::Created().json(stream))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct EventsRequest {
+  pub channel: String,
+}
+
+/// Subscribe to the events of a channel as `text/event-stream`, e.g. passed votes to skip the current song.
+#[get("/channel/events")]
+pub async fn events(events: web::Data<ChannelEvents>, Query(query): Query<EventsRequest>) -> HttpResponse {
+  let received = futures::stream::unfold(events.subscribe(&query.channel), |mut receiver| async move {
+    let message = match receiver.recv().await {
+      Ok(event) => format!("data: {}\n\n", serde_json::to_string(&event).ok()?),
+      // the player fell behind, it only receives the latest events
+      Err(RecvError::Lagged(missed)) => format!(": missed {missed} events\n\n"),
+      Err(RecvError::Closed) => return None,
+    };
+    Some((message, receiver))
+  });
+  let heartbeat = futures::stream::unfold(
+    actix_rt::time::interval(HEARTBEAT_INTERVAL),
+    |mut interval| async move {
+      interval.tick().await;
+      Some((":\n\n".to_owned(), interval))
+    },
+  );
+  let body =
+    futures::stream::select(received, heartbeat).map(|message| Ok::<_, std::convert::Infallible>(Bytes::from(message)));
+  HttpResponse::Ok()
+    .content_type("text/event-stream")
+    .insert_header((header::CACHE_CONTROL, "no-cache"))
+    // `Compress` would buffer the events
+    .insert_header((header::CONTENT_ENCODING, "identity"))
+    .streaming(body)
+}
@@ -0,0 +1,126 @@
+use crate::{
+  client::LocalLibrary,
+  common::platform::Platform,
+  db::{self, local_files::SyncSummary, Database},
+  error::{Error, FailWith},
+};
+use actix_web::{
+  get,
+  http::{
+    header::{self, ContentRange, ContentRangeSpec, Range},
+    StatusCode,
+  },
+  post, web,
+  web::Query,
+  HttpRequest, HttpResponse, Result,
+};
+use bytes::Bytes;
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct StreamRequest {
+  pub id: String,
+}
+
+/// Scan the library and synchronize it with the database
+pub async fn rescan(db: &Database, library: LocalLibrary) -> anyhow::Result<SyncSummary> {
+  let scan = web::block(move || library.scan()).await??;
+  Ok(db::local_files::sync(db, scan.files.into_iter().map(Into::into).collect(), &scan.failed).await?)
+}
+
+/// Rescan the local library, picking up added, removed and changed files.
+#[post("/local/rescan")]
+pub async fn post_rescan(db: web::Data<Database>, library: Option<web::Data<LocalLibrary>>) -> Result<HttpResponse> {
+  let library = LocalLibrary::clone(
+    library
+      .as_deref()
+      .with((StatusCode::NOT_IMPLEMENTED, "Local library is not configured"))?,
+  );
+  let summary = rescan(db.get_ref(), library).await.internal()?;
+  log::info!("rescanned local library: {summary:?}");
+  Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Stream a song from the local library, supporting single `Range` requests.
+#[get("/local/stream")]
+pub async fn stream(
+  req: HttpRequest,
+  db: web::Data<Database>,
+  library: Option<web::Data<LocalLibrary>>,
+  Query(query): Query<StreamRequest>,
+) -> Result<HttpResponse> {
+  let library = library
+    .as_deref()
+    .with((StatusCode::NOT_IMPLEMENTED, "Local library is not configured"))?;
+  // only serve files which were picked up by a scan
+  if !db::songs::exists(db.get_ref(), Platform::Local, query.id.clone())
+    .await
+    .internal()?
+  {
+    return Err(Error::from(StatusCode::NOT_FOUND).into());
+  }
+  let path = library.path(&query.id).with(StatusCode::NOT_FOUND)?;
+  let content_type = library.content_type(&query.id).with(StatusCode::NOT_FOUND)?;
+
+  let mut file = tokio::fs::File::open(&path).await.with(StatusCode::NOT_FOUND)?;
+  let length = file.metadata().await.internal()?.len();
+
+  let range = req
+    .headers()
+    .get(header::RANGE)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.parse::<Range>().ok());
+  let (mut response, start, end) = match range {
+    Some(Range::Bytes(specs)) if specs.len() == 1 => match specs[0].to_satisfiable_range(length) {
+      Some((start, end)) => {
+        let mut response = HttpResponse::build(StatusCode::PARTIAL_CONTENT);
+        response.insert_header(ContentRange(ContentRangeSpec::Bytes {
+          range: Some((start, end)),
+          instance_length: Some(length),
+        }));
+        (response, start, end)
+      }
+      None => {
+        return Ok(
+          HttpResponse::build(StatusCode::RANGE_NOT_SATISFIABLE)
+            .insert_header(ContentRange(ContentRangeSpec::Bytes {
+              range: None,
+              instance_length: Some(length),
+            }))
+            .finish(),
+        )
+      }
+    },
+    // multiple ranges are not supported, serve the whole file instead
+    _ if length == 0 => return Ok(HttpResponse::Ok().content_type(content_type).finish()),
+    _ => (HttpResponse::Ok(), 0, length - 1),
+  };
+
+  file.seek(SeekFrom::Start(start)).await.internal()?;
+  let reader = file.take(end - start + 1);
+  let body = futures::stream::unfold(Some(reader), |reader| async move {
+    let mut reader = reader?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    match reader.read(&mut buf).await {
+      Ok(0) => None,
+      Ok(n) => {
+        buf.truncate(n);
+        Some((Ok(Bytes::from(buf)), Some(reader)))
+      }
+      Err(e) => Some((Err(e), None)),
+    }
+  });
+
+  Ok(
+    response
+      .content_type(content_type)
+      .insert_header((header::ACCEPT_RANGES, "bytes"))
+      // the audio is already compressed, and `Compress` would break `Content-Length`
+      .insert_header((header::CONTENT_ENCODING, "identity"))
+      .no_chunking(end - start + 1)
+      .streaming(body),
+  )
+}
@@ -0,0 +1,133 @@
+use crate::{
+  client::{Soundcloud, Spotify, Youtube},
+  common::{
+    config::Config,
+    matching::{self, Candidate},
+    platform::Platform,
+  },
+  db::{
+    self,
+    songs::{Song, SongData},
+    Database,
+  },
+  error::{Error, FailWith},
+};
+use actix_web::{get, http::StatusCode, web, web::Query, HttpResponse, Result};
+
+/// How many known songs and search results are considered as candidates
+const MAX_CANDIDATES: u32 = 10;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct MatchRequest {
+  pub platform: Platform,
+  pub id: String,
+  pub target: Platform,
+}
+
+fn song_candidate(song: &Song) -> Candidate<'_> {
+  Candidate {
+    title: song.title(),
+    artist: song.artist().as_deref(),
+    duration: *song.duration(),
+  }
+}
+
+fn data_candidate(song: &SongData) -> Candidate<'_> {
+  Candidate {
+    title: song.title(),
+    artist: song.artist().as_deref(),
+    duration: *song.duration(),
+  }
+}
+
+/// Find the song on the `target` platform which best matches a memorized song.
+///
+/// Known songs are considered first, then the target platform is searched.
+/// The result is cached, and when nothing matched, matching is attempted again after `match_miss_ttl`.
+#[get("/match")]
+pub async fn get(
+  config: web::Data<Config>,
+  db: web::Data<Database>,
+  client: web::Data<Youtube>,
+  soundcloud: Option<web::Data<Soundcloud>>,
+  spotify: Option<web::Data<Spotify>>,
+  Query(query): Query<MatchRequest>,
+) -> Result<HttpResponse> {
+  log::info!("{query:#?}");
+  let source = db::songs::get(db.get_ref(), query.platform, &query.id)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown song, memorize it first"))?;
+  if *source.platform() == query.target {
+    return Ok(HttpResponse::Ok().json(source));
+  }
+  if let Some(cached) = db::matches::get(db.get_ref(), *source.id(), query.target, config.match_miss_ttl)
+    .await
+    .internal()?
+  {
+    let song = cached.with((StatusCode::NOT_FOUND, "No match found"))?;
+    return Ok(HttpResponse::Ok().json(song));
+  }
+
+  let source_candidate = song_candidate(&source);
+  let search = matching::normalize(&format!(
+    "{} {}",
+    source.artist().as_deref().unwrap_or_default(),
+    source.title()
+  ))
+  .join(" ");
+
+  let known = db::matches::candidates(db.get_ref(), query.target, &search, MAX_CANDIDATES as i64)
+    .await
+    .internal()?;
+  let matched = match matching::best(&source_candidate, known.iter().map(song_candidate)) {
+    Some(i) => Some(known[i].clone()),
+    None => {
+      let results = match query.target {
+        Platform::Youtube => client
+          .search(&search, MAX_CANDIDATES)
+          .await
+          .with("Failed to search YouTube")?
+          .into_iter()
+          .map(SongData::from)
+          .collect::<Vec<_>>(),
+        Platform::Soundcloud => soundcloud
+          .as_deref()
+          .with((StatusCode::NOT_IMPLEMENTED, "SoundCloud is not configured"))?
+          .search(&search, MAX_CANDIDATES)
+          .await
+          .with("Failed to search SoundCloud")?
+          .into_iter()
+          .map(SongData::from)
+          .collect::<Vec<_>>(),
+        Platform::Spotify => spotify
+          .as_deref()
+          .with((StatusCode::NOT_IMPLEMENTED, "Spotify is not configured"))?
+          .search(&search, MAX_CANDIDATES)
+          .await
+          .with("Failed to search Spotify")?
+          .into_iter()
+          .map(SongData::from)
+          .collect::<Vec<_>>(),
+        Platform::Oembed | Platform::Local => {
+          return Err(Error::from("Songs can not be matched to this platform").into())
+        }
+      };
+      match matching::best(&source_candidate, results.iter().map(data_candidate)) {
+        Some(i) => Some(db::songs::create(db.get_ref(), results[i].clone()).await.internal()?),
+        None => None,
+      }
+    }
+  };
+
+  db::matches::set(
+    db.get_ref(),
+    *source.id(),
+    query.target,
+    matched.as_ref().map(|s| *s.id()),
+  )
+  .await
+  .internal()?;
+  let song = matched.with((StatusCode::NOT_FOUND, "No match found"))?;
+  Ok(HttpResponse::Ok().json(song))
+}
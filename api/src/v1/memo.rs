@@ -1,46 +1,481 @@
-use crate::client::Youtube;
-use crate::common::platform::Platform;
-use crate::db::{songs, Database};
-use crate::error::FailWith;
-use actix_web::{post, web, web::Json, HttpResponse, Result};
+use crate::client::{soundcloud, spotify, ytv3, ytv3::Video, Oembed, Soundcloud, Spotify, Youtube};
+use crate::common::{platform::Platform, single_flight::SingleFlight};
+use crate::db::{self, songs, Database};
+use crate::error::{Error, FailWith};
+use actix_web::{http::StatusCode, post, web, web::Json, HttpResponse, Result};
+use chrono::Utc;
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+
+/// Maximum number of songs memorized by a single batch request
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// How many SoundCloud tracks or oEmbed URLs of a batch are resolved at once
+const MAX_CONCURRENT_RESOLVES: usize = 4;
 
 #[derive(serde::Deserialize, Debug)]
 pub struct MemoRequest {
   pub platform: Platform,
+  /// Song ID, or a video URL for YouTube, or a track URL for Spotify, SoundCloud and oEmbed
   pub id: String,
+  /// The channel the song is requested on, rejecting it if it is within the channel's cooldown
+  pub channel: Option<String>,
+}
+
+/// Fail with `409 Conflict` if `song` was played on `channel` too recently to be requested again
+pub async fn check_cooldown(
+  db: &Database,
+  channel: Option<&str>,
+  song: &songs::Song,
+) -> std::result::Result<(), Error> {
+  let channel = match channel {
+    Some(channel) => channel,
+    None => return Ok(()),
+  };
+  let settings = db::channels::settings(db, channel).await.internal()?;
+  if !settings.has_cooldown() {
+    return Ok(());
+  }
+  let last = db::plays::last_play(db, channel, *song.id()).await.internal()?;
+  match last.and_then(|last| settings.cooldown_reason(&last, Utc::now())) {
+    Some(reason) => Err(Error::from((StatusCode::CONFLICT, reason))),
+    None => Ok(()),
+  }
+}
+
+/// Coalesces concurrent lookups of the same YouTube video
+pub type VideoLookups = SingleFlight<String, std::result::Result<Option<Video>, Error>>;
+
+/// Normalize a song id or URL into the id the song is stored under
+fn normalize_id(platform: Platform, id: &str, oembed: Option<&Oembed>) -> std::result::Result<String, Error> {
+  match platform {
+    Platform::Youtube => ytv3::video_id(id).with("Invalid song id"),
+    Platform::Spotify => spotify::track_id(id).with("Invalid song id"),
+    // oEmbed songs are stored under their normalized URL
+    Platform::Oembed => {
+      let (url, _) = oembed
+        .with((StatusCode::NOT_IMPLEMENTED, "oEmbed is not configured"))?
+        .provider(id)
+        .with("Unsupported oEmbed provider")?;
+      Ok(url.into())
+    }
+    Platform::Soundcloud | Platform::Local => Ok(id.into()),
+  }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct MemoResponse {
+  #[serde(flatten)]
+  pub song: songs::Song,
+  /// Whether the song's metadata was fetched from its platform by this request
+  pub fetched: bool,
 }
 
 /// Memorize the song, allowing it to be returned from `/random`.
+///
+/// Responds with the song, `201 Created` if it was new, or `200 OK` if it was known.
+/// If `channel` is given, known songs which are within its cooldown are rejected with `409 Conflict`.
 #[post("/memo")]
 pub async fn post(
   db: web::Data<Database>,
   client: web::Data<Youtube>,
-  Json(body): Json<MemoRequest>,
+  lookups: web::Data<VideoLookups>,
+  soundcloud: Option<web::Data<Soundcloud>>,
+  spotify: Option<web::Data<Spotify>>,
+  oembed: Option<web::Data<Oembed>>,
+  Json(mut body): Json<MemoRequest>,
 ) -> Result<HttpResponse> {
   log::info!("{body:#?}");
+  body.id = normalize_id(body.platform, &body.id, oembed.as_ref().map(|v| v.get_ref()))?;
+  // SoundCloud tracks may be referred to by URL, but are stored under their stable id
+  let mut resolved = None;
+  if body.platform == Platform::Soundcloud && !soundcloud::is_id(&body.id) {
+    let soundcloud = soundcloud
+      .as_deref()
+      .with((StatusCode::NOT_IMPLEMENTED, "SoundCloud is not configured"))?;
+    log::info!("resolving track {}", body.id);
+    let track = soundcloud.track(&body.id).await.with("Invalid song id")?;
+    let track = track.with("Invalid song id")?;
+    body.id = track.id.clone();
+    resolved = Some(track);
+  }
   // check if we know this (platform, song_id) combination
-  if !songs::exists(db.get_ref(), body.platform, body.id.clone())
-    .await
-    .internal()?
-  {
-    // if false: fetch info from youtube/videos
-    let (song_id, title, published_at) = match body.platform {
-      Platform::Youtube => {
-        log::info!("getting video {}", body.id);
-        let result = client.videos([body.id.as_str()]).await.with("Invalid song id")?;
-        log::info!("{result:#?}");
-        let video = result.into_iter().next().with("Invalid song id")?;
-        (video.id, video.title, video.published_at)
+  if let Some(song) = songs::get(db.get_ref(), body.platform, &body.id).await.internal()? {
+    check_cooldown(db.get_ref(), body.channel.as_deref(), &song).await?;
+    return Ok(HttpResponse::Ok().json(MemoResponse { song, fetched: false }));
+  }
+  // if not: fetch info from the platform
+  let data = match body.platform {
+    Platform::Youtube => {
+      log::info!("getting video {}", body.id);
+      let client = Youtube::clone(&client);
+      let id = body.id.clone();
+      let video = lookups
+        .run(body.id.clone(), move || async move {
+          let result = client.videos([id.as_str()]).await.with("Invalid song id")?;
+          log::info!("{result:#?}");
+          Ok(result.found.into_iter().next())
+        })
+        .await?;
+      songs::SongData::from(video.with("Invalid song id")?)
+    }
+    Platform::Soundcloud => {
+      let track = match resolved {
+        Some(track) => track,
+        None => {
+          let soundcloud = soundcloud
+            .as_deref()
+            .with((StatusCode::NOT_IMPLEMENTED, "SoundCloud is not configured"))?;
+          log::info!("getting track {}", body.id);
+          let track = soundcloud.track(&body.id).await.with("Invalid song id")?;
+          track.with("Invalid song id")?
+        }
+      };
+      log::info!("{track:#?}");
+      songs::SongData::from(track)
+    }
+    Platform::Spotify => {
+      let spotify = spotify
+        .as_deref()
+        .with((StatusCode::NOT_IMPLEMENTED, "Spotify is not configured"))?;
+      log::info!("getting track {}", body.id);
+      let track = spotify.track(&body.id).await.with("Invalid song id")?;
+      log::info!("{track:#?}");
+      songs::SongData::from(track.with("Invalid song id")?)
+    }
+    Platform::Oembed => {
+      let oembed = oembed
+        .as_deref()
+        .with((StatusCode::NOT_IMPLEMENTED, "oEmbed is not configured"))?;
+      log::info!("getting embed {}", body.id);
+      let embed = oembed.resolve(&body.id).await.with("Invalid song id")?;
+      log::info!("{embed:#?}");
+      songs::SongData::from(embed.with("Invalid song id")?)
+    }
+    // local songs are only ever added by scanning the library
+    Platform::Local => return Err(Error::from((StatusCode::NOT_FOUND, "Unknown local song")).into()),
+  };
+  // and store it
+  log::info!("storing {data:?}");
+  let song = songs::create(db.get_ref(), data).await.internal()?;
+  Ok(HttpResponse::Created().json(MemoResponse { song, fetched: true }))
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoStatus {
+  /// The song was memorized before
+  Known,
+  /// The song was fetched from its platform and memorized
+  Added,
+  /// The song doesn't exist on its platform, e.g. because it was deleted
+  NotFound,
+  /// The id or URL can't refer to a song on its platform
+  Invalid,
+  /// The song couldn't be fetched from its platform
+  Failed,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct MemoResult {
+  pub platform: Platform,
+  /// The id the song is stored under, or the requested id if it is invalid
+  pub id: String,
+  pub status: MemoStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+type Outcome = (MemoStatus, Option<String>);
+
+/// Fetch songs which are not memorized yet from their platform
+async fn fetch_batch(
+  client: &Youtube,
+  soundcloud: Option<&Soundcloud>,
+  spotify: Option<&Spotify>,
+  oembed: Option<&Oembed>,
+  platform: Platform,
+  ids: Vec<String>,
+) -> Vec<(String, std::result::Result<songs::SongData, Outcome>)> {
+  let failed = |error: &str| (MemoStatus::Failed, Some(error.to_string()));
+  let not_found = || (MemoStatus::NotFound, None);
+  match platform {
+    Platform::Youtube => match client.videos(ids.iter().map(|id| id.as_str())).await {
+      Ok(lookup) => lookup
+        .found
+        .into_iter()
+        .map(|video| (video.id.clone(), Ok(songs::SongData::from(video))))
+        .chain(lookup.missing.into_iter().map(|id| (id, Err(not_found()))))
+        .collect(),
+      Err(e) => {
+        log::error!("Failed to look up videos: {e:?}");
+        let error = failed("Failed to fetch videos from YouTube");
+        ids.into_iter().map(|id| (id, Err(error.clone()))).collect()
+      }
+    },
+    Platform::Soundcloud => match soundcloud {
+      Some(soundcloud) => {
+        futures::stream::iter(ids)
+          .map(|id| async move {
+            let result = match soundcloud.track(&id).await {
+              Ok(Some(track)) => Ok(songs::SongData::from(track)),
+              Ok(None) => Err(not_found()),
+              Err(_) => Err(failed("Failed to fetch track from SoundCloud")),
+            };
+            (id, result)
+          })
+          .buffered(MAX_CONCURRENT_RESOLVES)
+          .collect()
+          .await
+      }
+      None => {
+        let error = failed("SoundCloud is not configured");
+        ids.into_iter().map(|id| (id, Err(error.clone()))).collect()
       }
-    };
-    // and store it
-    log::info!("storing {song_id}, {title}, {published_at}");
-    songs::create(
-      db.get_ref(),
-      songs::SongData::new(published_at, song_id, body.platform, title),
+    },
+    Platform::Spotify => match spotify {
+      Some(spotify) => match spotify.tracks(ids.iter().map(|id| id.as_str())).await {
+        Ok(tracks) => {
+          let mut found = tracks
+            .into_iter()
+            .map(|track| (track.id.clone(), track))
+            .collect::<HashMap<_, _>>();
+          ids
+            .into_iter()
+            .map(|id| match found.remove(&id) {
+              Some(track) => (id, Ok(songs::SongData::from(track))),
+              None => (id, Err(not_found())),
+            })
+            .collect()
+        }
+        Err(e) => {
+          log::error!("Failed to look up tracks: {e:?}");
+          let error = failed("Failed to fetch tracks from Spotify");
+          ids.into_iter().map(|id| (id, Err(error.clone()))).collect()
+        }
+      },
+      None => {
+        let error = failed("Spotify is not configured");
+        ids.into_iter().map(|id| (id, Err(error.clone()))).collect()
+      }
+    },
+    Platform::Oembed => match oembed {
+      Some(oembed) => {
+        futures::stream::iter(ids)
+          .map(|id| async move {
+            let result = match oembed.resolve(&id).await {
+              Ok(Some(embed)) => Ok(songs::SongData::from(embed)),
+              Ok(None) => Err(not_found()),
+              Err(_) => Err(failed("Failed to fetch embed")),
+            };
+            (id, result)
+          })
+          .buffered(MAX_CONCURRENT_RESOLVES)
+          .collect()
+          .await
+      }
+      // ids are normalized using the providers, so this can't happen
+      None => vec![],
+    },
+    // local songs are only ever added by scanning the library
+    Platform::Local => ids.into_iter().map(|id| (id, Err(not_found()))).collect(),
+  }
+}
+
+/// Memorize up to `MAX_BATCH_SIZE` songs at once, reporting the status of every song in order.
+///
+/// Known songs are skipped, and the others are fetched in bulk where their platform allows it.
+#[post("/memo/batch")]
+pub async fn post_batch(
+  db: web::Data<Database>,
+  client: web::Data<Youtube>,
+  soundcloud: Option<web::Data<Soundcloud>>,
+  spotify: Option<web::Data<Spotify>>,
+  oembed: Option<web::Data<Oembed>>,
+  Json(items): Json<Vec<MemoRequest>>,
+) -> Result<HttpResponse> {
+  log::info!("memorizing {} songs", items.len());
+  if items.len() > MAX_BATCH_SIZE {
+    return Err(Error::from(format!("At most {MAX_BATCH_SIZE} songs can be memorized at once")).into());
+  }
+
+  let mut results = items
+    .into_iter()
+    .map(
+      |item| match normalize_id(item.platform, &item.id, oembed.as_ref().map(|v| v.get_ref())) {
+        Ok(id) => MemoResult {
+          platform: item.platform,
+          id,
+          status: MemoStatus::Known,
+          error: None,
+        },
+        Err(e) => MemoResult {
+          platform: item.platform,
+          id: item.id,
+          status: MemoStatus::Invalid,
+          error: Some(e.to_string()),
+        },
+      },
     )
-    .await
-    .internal()?;
+    .collect::<Vec<_>>();
+
+  // SoundCloud tracks may be referred to by URL, but are stored under their stable id
+  let mut resolved = HashMap::<String, songs::SongData>::new();
+  let urls = results
+    .iter()
+    .enumerate()
+    .filter(|(_, r)| r.platform == Platform::Soundcloud && r.status == MemoStatus::Known && !soundcloud::is_id(&r.id))
+    .map(|(i, r)| (i, r.id.clone()))
+    .collect::<Vec<_>>();
+  let tracks = match soundcloud.as_deref() {
+    Some(soundcloud) => {
+      futures::stream::iter(urls)
+        .map(|(i, url)| async move {
+          let track = soundcloud.track(&url).await;
+          if let Err(e) = &track {
+            log::error!("Failed to resolve track {url}: {e:?}");
+          }
+          (i, track.map_err(|_| "Failed to fetch track from SoundCloud"))
+        })
+        .buffered(MAX_CONCURRENT_RESOLVES)
+        .collect::<Vec<_>>()
+        .await
+    }
+    None => urls
+      .into_iter()
+      .map(|(i, _)| (i, Err("SoundCloud is not configured")))
+      .collect(),
+  };
+  for (i, track) in tracks {
+    let result = &mut results[i];
+    match track {
+      Ok(Some(track)) => {
+        result.id = track.id.clone();
+        resolved.insert(track.id.clone(), songs::SongData::from(track));
+      }
+      Ok(None) => result.status = MemoStatus::NotFound,
+      Err(error) => {
+        result.status = MemoStatus::Failed;
+        result.error = Some(error.into());
+      }
+    }
   }
-  Ok(HttpResponse::Ok().finish())
+
+  let mut pending = HashMap::<Platform, Vec<String>>::new();
+  for result in results.iter().filter(|r| r.status == MemoStatus::Known) {
+    pending.entry(result.platform).or_default().push(result.id.clone());
+  }
+  let mut outcomes = HashMap::<(Platform, String), Outcome>::new();
+  let mut data = vec![];
+  for (platform, mut ids) in pending {
+    ids.sort();
+    ids.dedup();
+    let known = songs::exists_bulk(db.get_ref(), platform, &ids)
+      .await
+      .internal()?
+      .into_iter()
+      .collect::<HashSet<_>>();
+    // tracks which were resolved from their URL don't have to be fetched again
+    let (prefetched, unknown) = ids
+      .into_iter()
+      .filter(|id| !known.contains(id))
+      .partition::<Vec<_>, _>(|id| resolved.contains_key(id));
+    for id in prefetched {
+      data.extend(resolved.remove(&id));
+      outcomes.insert((platform, id), (MemoStatus::Added, None));
+    }
+    if unknown.is_empty() {
+      continue;
+    }
+    log::info!("fetching {} {} songs", unknown.len(), platform.as_str());
+    let fetched = fetch_batch(
+      &client,
+      soundcloud.as_ref().map(|v| v.get_ref()),
+      spotify.as_ref().map(|v| v.get_ref()),
+      oembed.as_ref().map(|v| v.get_ref()),
+      platform,
+      unknown,
+    )
+    .await;
+    for (id, result) in fetched {
+      let outcome = match result {
+        Ok(song) => {
+          data.push(song);
+          (MemoStatus::Added, None)
+        }
+        Err(outcome) => outcome,
+      };
+      outcomes.insert((platform, id), outcome);
+    }
+  }
+  log::info!("storing {} songs", data.len());
+  songs::create_bulk(db.get_ref(), data).await.internal()?;
+
+  for result in results.iter_mut().filter(|r| r.status == MemoStatus::Known) {
+    if let Some((status, error)) = outcomes.get(&(result.platform, result.id.clone())) {
+      result.status = *status;
+      result.error = error.clone();
+    }
+  }
+  Ok(HttpResponse::Ok().json(results))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use actix_web::{test, App};
+  use secrecy::Secret;
+  use wiremock::{
+    matchers::{method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+  };
+
+  crate::db_test!(batch_reports_stored_soundcloud_ids, db, id = rand::random::<u32>().to_string(), cleanup [
+    r#"DELETE FROM songs WHERE platform = 'soundcloud' AND platform_song_id = $1"#,
+  ] {
+    let url = format!("https://soundcloud.com/test/{id}");
+    let soundcloud = MockServer::start().await;
+    Mock::given(path("/resolve"))
+      .and(method("GET"))
+      .and(query_param("url", url.as_str()))
+      .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "kind": "track",
+        "id": id.parse::<u64>()?,
+        "title": "test",
+        "duration": 180000,
+        "created_at": "2022-01-01T00:00:00Z",
+        "user": { "username": "test" },
+      })))
+      .expect(2)
+      .named("resolve")
+      .mount(&soundcloud)
+      .await;
+    let app = test::init_service(
+      App::new()
+        .app_data(web::Data::new(db.clone()))
+        .app_data(web::Data::new(Youtube::new(soundcloud.uri(), Secret::new("test".into()))))
+        .app_data(web::Data::new(Soundcloud::new(soundcloud.uri(), Secret::new("test".into()))))
+        .service(post_batch),
+    )
+    .await;
+
+    let mut statuses = vec![];
+    for _ in 0..2 {
+      let request = test::TestRequest::post()
+        .uri("/memo/batch")
+        .set_json(serde_json::json!([{ "platform": "soundcloud", "id": url }]))
+        .to_request();
+      let results: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+      assert_eq!(results[0]["id"], id.as_str());
+      statuses.push(results[0]["status"].clone());
+    }
+    assert_eq!(statuses, ["added", "known"]);
+
+    let stored: i64 =
+      sqlx::query_scalar(r#"SELECT COUNT(*) FROM songs WHERE platform = 'soundcloud' AND platform_song_id = $1"#)
+        .bind(&id)
+        .fetch_one(db)
+        .await?;
+    assert_eq!(stored, 1);
+  });
 }
@@ -1,14 +1,46 @@
+pub mod channel;
+pub mod local;
+pub mod matches;
 pub mod memo;
+pub mod playback;
 pub mod playlist;
+pub mod queue;
 pub mod random;
 pub mod search;
+pub mod stats;
 
 use actix_web::{web, Scope};
 
 pub fn routes() -> Scope {
   web::scope("/v1")
+    .service(channel::get_settings)
+    .service(channel::put_settings)
+    .service(channel::events)
+    .service(channel::start_stream)
+    .service(local::post_rescan)
+    .service(local::stream)
+    .service(matches::get)
     .service(memo::post)
+    .service(memo::post_batch)
+    .service(playback::report)
+    .service(playback::flagged)
+    .service(playback::start)
+    .service(playback::end)
+    .service(playback::history)
+    .service(playback::vote_skip)
     .service(playlist::get)
+    .service(playlist::get_job)
+    .service(playlist::get_metadata)
+    .service(playlist::changes)
+    .service(queue::get)
+    .service(queue::post)
+    .service(queue::post_upvote)
+    .service(queue::post_pin)
+    .service(queue::next)
     .service(random::get)
+    .service(stats::songs)
+    .service(stats::requesters)
+    .service(stats::hours)
+    .service(stats::summary)
   //.service(search::get)
 }
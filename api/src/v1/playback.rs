@@ -0,0 +1,174 @@
+use crate::{
+  common::{config::Config, events::ChannelEvent, platform::Platform},
+  db::{self, plays::PlayOutcome, Database},
+  error::{Error, FailWith},
+};
+use actix_web::{get, http::StatusCode, post, web, web::Json, web::Query, HttpResponse, Result};
+
+/// Maximum number of plays returned from `/playback/history` at once
+const MAX_HISTORY_LIMIT: u64 = 100;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct FailureReport {
+  /// The channel whose player failed to play the song
+  pub channel: String,
+  pub platform: Platform,
+  pub id: String,
+  /// The player's error code, e.g. `101` or `150` if the YouTube video can't be embedded
+  pub code: i32,
+}
+
+/// Report that a song failed to play.
+///
+/// Songs with too many reports of permanent errors are marked as unplayable, and no longer returned from `/random`.
+#[post("/playback/failure")]
+pub async fn report(
+  config: web::Data<Config>,
+  db: web::Data<Database>,
+  Json(body): Json<FailureReport>,
+) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  let song = db::songs::get(db.get_ref(), body.platform, &body.id)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown song"))?;
+  let summary = db::playback_failures::report(
+    db.get_ref(),
+    *song.id(),
+    &body.channel,
+    body.code,
+    config.playback_failure_threshold,
+  )
+  .await
+  .internal()?;
+  Ok(HttpResponse::Ok().json(summary))
+}
+
+/// Obtain the songs which were flagged as unplayable, for review.
+#[get("/playback/failures")]
+pub async fn flagged(db: web::Data<Database>) -> Result<HttpResponse> {
+  Ok(HttpResponse::Ok().json(db::playback_failures::flagged(db.get_ref()).await.internal()?))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PlayStart {
+  /// The channel the song is played on
+  pub channel: String,
+  pub platform: Platform,
+  pub id: String,
+  /// Who requested the song, if anyone
+  pub requester: Option<String>,
+}
+
+/// Report that a song started playing, responding with the play to report the end of.
+#[post("/playback/start")]
+pub async fn start(db: web::Data<Database>, Json(body): Json<PlayStart>) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  let song = db::songs::get(db.get_ref(), body.platform, &body.id)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown song"))?;
+  let play = db::plays::start(db.get_ref(), &body.channel, *song.id(), body.requester.as_deref())
+    .await
+    .internal()?;
+  Ok(HttpResponse::Created().json(play))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PlayEnd {
+  /// The id of the play, as returned from `/playback/start`
+  pub play: i64,
+  pub outcome: PlayOutcome,
+}
+
+/// Report how a song stopped playing.
+#[post("/playback/end")]
+pub async fn end(db: web::Data<Database>, Json(body): Json<PlayEnd>) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  let play = db::plays::end(db.get_ref(), body.play, body.outcome)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown play, or it has ended already"))?;
+  Ok(HttpResponse::Ok().json(play))
+}
+
+fn default_history_limit() -> u64 {
+  20
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct HistoryRequest {
+  pub channel: String,
+  /// Only plays before the play with this id, for pagination
+  pub before: Option<i64>,
+  #[serde(default = "default_history_limit")]
+  pub limit: u64,
+}
+
+/// Obtain the songs played on a channel, most recent first.
+#[get("/playback/history")]
+pub async fn history(db: web::Data<Database>, Query(query): Query<HistoryRequest>) -> Result<HttpResponse> {
+  let limit = query.limit.min(MAX_HISTORY_LIMIT) as i64;
+  let plays = db::plays::history(db.get_ref(), &query.channel, query.before, limit)
+    .await
+    .internal()?;
+  Ok(HttpResponse::Ok().json(plays))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SkipVote {
+  pub channel: String,
+  pub voter: String,
+  /// Number of people who chatted recently, required for percentage thresholds
+  pub chatters: Option<i64>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SkipVoteStatus {
+  /// The id of the play which is voted on
+  pub play: i64,
+  pub votes: i64,
+  /// Number of votes needed to skip
+  pub required: i64,
+  pub passed: bool,
+}
+
+/// Vote to skip the song playing on a channel.
+///
+/// Once enough people voted, the play ends as skipped, and a `skip` event is sent to the channel's players.
+#[post("/playback/skip-vote")]
+pub async fn vote_skip(db: web::Data<Database>, Json(body): Json<SkipVote>) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  let settings = db::channels::settings(db.get_ref(), &body.channel).await.internal()?;
+  if !settings.has_vote_skip() {
+    return Err(Error::from((StatusCode::FORBIDDEN, "Vote-skip is disabled on this channel")).into());
+  }
+  let required = settings
+    .skip_threshold(body.chatters)
+    .with("`chatters` is required to skip by percentage")?;
+  let play = db::plays::current(db.get_ref(), &body.channel)
+    .await
+    .internal()?
+    .with((StatusCode::CONFLICT, "Nothing is playing"))?;
+  let votes = db::skip_votes::vote(db.get_ref(), play.id, &body.voter)
+    .await
+    .internal()?;
+  let passed = votes >= required;
+  // only the vote which ends the play sends the event
+  if passed
+    && db::plays::end(db.get_ref(), play.id, PlayOutcome::Skipped)
+      .await
+      .internal()?
+      .is_some()
+  {
+    db::events::publish(db.get_ref(), &body.channel, ChannelEvent::Skip { play: play.id })
+      .await
+      .internal()?;
+  }
+  Ok(HttpResponse::Ok().json(SkipVoteStatus {
+    play: play.id,
+    votes,
+    required,
+    passed,
+  }))
+}
@@ -1,85 +1,547 @@
 use crate::{
-  client::Youtube,
-  common::{config::Config, platform::Platform, util},
-  db::{self, playlists::PlaylistData, songs::SongData, Database},
-  error::FailWith,
+  client::{Soundcloud, Youtube},
+  common::{
+    config::Config,
+    platform::Platform,
+    util::{self, cursor::Cursor},
+  },
+  db::{
+    self,
+    import_jobs::ImportJob,
+    playlists::{PageQuery, Playlist, SortBy, SortOrder},
+    songs::Song,
+    Database,
+  },
+  error::{Error, FailWith},
+  playlist_sync::{self, PlaylistFetches},
 };
-use actix_web::{get, web, web::Query, HttpResponse, Result};
-use chrono::{Duration, Utc};
+use actix_web::{get, http::StatusCode, web, web::Query, HttpResponse, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::StreamExt;
+use serde::{de::IntoDeserializer, Deserialize};
+use uuid::Uuid;
 
 fn default_limit() -> u64 {
   10
 }
 
+/// Maximum number of playlists in a pool
+const MAX_POOL_SIZE: usize = 10;
+
 #[derive(serde::Deserialize, Debug)]
 pub struct PlaylistRequest {
-  pub platform: Platform,
-  pub id: String,
+  pub platform: Option<Platform>,
+  pub id: Option<String>,
+  /// Comma-separated pool of playlists as `PLATFORM:ID`, replaces `platform` and `id`
+  pub playlists: Option<String>,
   // TODO: shuffle
   /* #[serde(default)]
   #[serde(deserialize_with = "util::loose_bool::deserialize")]
   pub shuffle: bool, */
   #[serde(default)]
   pub offset: u64,
+  /// Switches to keyset pagination, the empty cursor starts at the beginning of the playlist
+  pub cursor: Option<Cursor>,
   #[serde(default = "default_limit")]
   pub limit: u64,
   #[serde(default)]
   #[serde(deserialize_with = "util::loose_bool::deserialize")]
   pub force: bool,
+  #[serde(default)]
+  pub sort: SortBy,
+  #[serde(default)]
+  pub order: SortOrder,
+  /// Only songs whose title contains this
+  pub title: Option<String>,
+  pub published_after: Option<DateTime<Utc>>,
+  pub published_before: Option<DateTime<Utc>>,
+  /// In seconds
+  pub max_duration: Option<u32>,
+  /// Comma-separated songs to leave out as `PLATFORM:ID`, e.g. the ones played in the current session
+  pub exclude: Option<String>,
+}
+
+/// Parse a `PLATFORM:ID` pair, `kind` names what it identifies in errors
+fn parse_platform_id(v: &str, kind: &str) -> std::result::Result<(Platform, String), Error> {
+  let (platform, id) = v
+    .split_once(':')
+    .filter(|(_, id)| !id.is_empty())
+    .with(format!("Invalid {kind} `{v}`, expected `PLATFORM:ID`"))?;
+  let platform = Platform::deserialize(platform.into_deserializer())
+    .map_err(|e: serde::de::value::Error| Error::from(format!("Invalid {kind} `{v}`: {e}")))?;
+  Ok((platform, id.to_string()))
+}
+
+/// Parse a comma-separated pool of playlists, `PLATFORM:ID,PLATFORM:ID,...`
+pub fn parse_pool(v: &str) -> std::result::Result<Vec<(Platform, String)>, Error> {
+  let pool = v
+    .split(',')
+    .map(|playlist| parse_platform_id(playlist, "playlist"))
+    .collect::<std::result::Result<Vec<_>, Error>>()?;
+  if pool.len() > MAX_POOL_SIZE {
+    return Err(Error::from(format!(
+      "At most {MAX_POOL_SIZE} playlists can be combined"
+    )));
+  }
+  Ok(pool)
+}
+
+/// The playlists a request refers to, either a single one or a pool
+fn requested_pool(
+  platform: Option<Platform>,
+  id: Option<&str>,
+  playlists: Option<&str>,
+) -> std::result::Result<Vec<(Platform, String)>, Error> {
+  match (platform, id, playlists) {
+    (Some(platform), Some(id), None) => Ok(vec![(platform, id.to_string())]),
+    (None, None, Some(playlists)) => parse_pool(playlists),
+    _ => Err(Error::from("Either `platform` and `id`, or `playlists` are required")),
+  }
 }
 
-async fn should_fetch(db: &Database, platform: Platform, id: &str, refresh_interval: Duration) -> Result<bool> {
-  Ok(match db::playlists::get(db, platform, id).await.internal()? {
+impl PlaylistRequest {
+  fn pool(&self) -> std::result::Result<Vec<(Platform, String)>, Error> {
+    requested_pool(self.platform, self.id.as_deref(), self.playlists.as_deref())
+  }
+
+  fn page_query(&self) -> std::result::Result<PageQuery, Error> {
+    Ok(PageQuery {
+      sort: self.sort,
+      order: self.order,
+      title: self.title.clone(),
+      published_after: self.published_after,
+      published_before: self.published_before,
+      max_duration: self.max_duration.map(|v| v.min(i32::MAX as u32) as i32),
+      exclude: self
+        .exclude
+        .iter()
+        .flat_map(|songs| songs.split(','))
+        .filter(|song| !song.is_empty())
+        .map(|song| parse_platform_id(song, "song"))
+        .collect::<std::result::Result<_, _>>()?,
+    })
+  }
+}
+
+fn is_stale(playlist: Option<&Playlist>, refresh_interval: Duration) -> bool {
+  match playlist {
     Some(playlist) => Utc::now() > *playlist.updated_at() + refresh_interval,
     None => true,
-  })
+  }
 }
 
+/// Fetch and store a playlist, recording the progress in its import job
+async fn import(
+  fetches: web::Data<PlaylistFetches>,
+  db: Database,
+  client: web::Data<Youtube>,
+  soundcloud: Option<web::Data<Soundcloud>>,
+  job: ImportJob,
+) {
+  let (page_tx, mut page_rx) = futures::channel::mpsc::unbounded::<()>();
+  let refresh = playlist_sync::refresh(
+    &fetches,
+    &db,
+    &client,
+    soundcloud.as_ref().map(|v| v.get_ref()),
+    *job.platform(),
+    job.playlist_id(),
+    move || {
+      let _ = page_tx.unbounded_send(());
+    },
+  );
+  // the channel closes once the fetch completes and drops the sender
+  let progress = async {
+    let mut pages = 0;
+    while page_rx.next().await.is_some() {
+      pages += 1;
+      if let Err(e) = db::import_jobs::progress(&db, *job.id(), pages).await {
+        log::error!("Failed to update progress of import job {}: {e:?}", job.id());
+      }
+    }
+  };
+  let (stored, _) = futures::join!(refresh, progress);
+
+  let result = match stored {
+    Ok(songs) => db::import_jobs::finish(&db, *job.id(), songs as i32).await,
+    Err(e) => {
+      log::error!(
+        "Failed to import playlist {} {}: {e:?}",
+        job.platform().as_str(),
+        job.playlist_id()
+      );
+      db::import_jobs::fail(&db, *job.id(), &format!("{e:#}")).await
+    }
+  };
+  if let Err(e) = result {
+    log::error!("Failed to finish import job {}: {e:?}", job.id());
+  }
+}
+
+/// The state of a requested playlist
+pub enum Loaded {
+  /// The playlist is stored, and may be refreshed in the background
+  Stored(Playlist),
+  /// The playlist is being imported for the first time by the job
+  Importing(Uuid),
+}
+
+/// Look up a stored playlist, starting an import job in the background if it has to be (re-)fetched.
+#[allow(clippy::too_many_arguments)]
+pub async fn load(
+  config: &Config,
+  fetches: &web::Data<PlaylistFetches>,
+  db: &Database,
+  client: &web::Data<Youtube>,
+  soundcloud: &Option<web::Data<Soundcloud>>,
+  platform: Platform,
+  id: &str,
+  force: bool,
+) -> std::result::Result<Loaded, Error> {
+  // SoundCloud sets may be referred to by URL, but are stored under their stable id
+  let id = match platform {
+    Platform::Soundcloud => soundcloud
+      .as_deref()
+      .with((StatusCode::NOT_IMPLEMENTED, "SoundCloud is not configured"))?
+      .playlist_id(id)
+      .await
+      .with("Failed to resolve playlist from SoundCloud")?
+      .with("Invalid playlist id")?,
+    _ => id.to_string(),
+  };
+  // check if playlist exists + get last updated time
+  let playlist = db::playlists::get(db, platform, &id).await.internal()?;
+  if force || is_stale(playlist.as_ref(), config.playlist_refresh_interval) {
+    let (job, created) = db::import_jobs::start(db, platform, &id).await.internal()?;
+    if created {
+      log::info!("starting import job {}", job.id());
+      actix_web::rt::spawn(import(
+        fetches.clone(),
+        db.clone(),
+        client.clone(),
+        soundcloud.clone(),
+        job.clone(),
+      ));
+    }
+    if playlist.is_none() {
+      return Ok(Loaded::Importing(*job.id()));
+    }
+  }
+  Ok(Loaded::Stored(playlist.internal()?))
+}
+
+/// Load every playlist of a pool, returning the internal ids of the stored ones and the ids of the import jobs
+pub async fn load_pool(
+  config: &Config,
+  fetches: &web::Data<PlaylistFetches>,
+  db: &Database,
+  client: &web::Data<Youtube>,
+  soundcloud: &Option<web::Data<Soundcloud>>,
+  pool: Vec<(Platform, String)>,
+  force: bool,
+) -> std::result::Result<(Vec<i32>, Vec<Uuid>), Error> {
+  let (mut stored, mut importing) = (vec![], vec![]);
+  for (platform, id) in pool {
+    match load(config, fetches, db, client, soundcloud, platform, &id, force).await? {
+      Loaded::Stored(playlist) => stored.push(*playlist.id()),
+      Loaded::Importing(job) => importing.push(job),
+    }
+  }
+  Ok((stored, importing))
+}
+
+/// Obtain a page of songs from a playlist, or from the union of a pool of playlists, sorted and filtered as requested.
+///
+/// Pages are selected by `offset`, or by `cursor` in which case the response includes the cursor of the next page.
+/// Cursors only support sorting by position.
+///
+/// If a playlist has to be (re-)fetched, this starts an import job in the background.
+/// While the job is running, the previously stored playlist is served if there is one.
+/// If none of the playlists are stored yet, this responds with `202 Accepted` and the ids of the jobs.
 #[get("/playlist")]
 pub async fn get(
   config: web::Data<Config>,
+  fetches: web::Data<PlaylistFetches>,
   db: web::Data<Database>,
   client: web::Data<Youtube>,
+  soundcloud: Option<web::Data<Soundcloud>>,
   Query(query): Query<PlaylistRequest>,
 ) -> Result<HttpResponse> {
   log::info!("{query:#?}");
-  // check if playlist exists + get last updated time
-  // update and persist can probably be the same (just INSERT INTO ... ON CONFLICT DO NOTHING), but `update` also has to modify `updated_at`
-  // if should update: fetch + update playlist
-  // if does not exist: fetch + persist playlist
-  if query.force
-    || should_fetch(
+  let pool = query.pool()?;
+  let page_query = query.page_query()?;
+  let (playlists, jobs) = load_pool(&config, &fetches, &db, &client, &soundcloud, pool, query.force).await?;
+  if playlists.is_empty() {
+    return Ok(match (&query.playlists, jobs.first()) {
+      (None, Some(job)) => HttpResponse::Accepted().json(ImportStarted { job: *job }),
+      _ => HttpResponse::Accepted().json(ImportsStarted { jobs }),
+    });
+  }
+
+  // return playlist page(cursor, limit)
+  if let Some(cursor) = query.cursor {
+    if query.sort != SortBy::Position {
+      return Err(Error::from("Cursors can only be used when sorting by position").into());
+    }
+    // fetch one more song to find out whether there is a next page
+    let mut songs = db::playlists::get_page_after(
       db.get_ref(),
-      query.platform,
-      &query.id,
-      config.playlist_refresh_interval,
+      &playlists,
+      &page_query,
+      cursor.key(),
+      query.limit as i32 + 1,
     )
-    .await?
-  {
-    // TODO: if this fails because of rate limiting, don't exit
-    let data = match query.platform {
-      Platform::Youtube => PlaylistData::new(
-        Platform::Youtube,
-        query.id.clone(),
-        client
-          .get_ref()
-          .playlist_videos(&query.id)
-          .await
-          .with("Failed to fetch playlist from YouTube")?
-          .into_iter()
-          .map(SongData::from)
-          .collect::<Vec<_>>(),
-      ),
+    .await
+    .internal()?;
+    let next_cursor = if songs.len() > query.limit as usize {
+      songs.truncate(query.limit as usize);
+      songs.last().map(|song| Cursor::after(song.rank, song.position))
+    } else {
+      None
     };
-    db::playlists::upsert(db.get_ref(), data).await.internal()?;
+    return Ok(HttpResponse::Ok().json(PlaylistPage {
+      songs: songs.into_iter().map(|song| song.song.into()).collect(),
+      next_cursor,
+    }));
   }
-
   // return playlist page(offset, limit)
-  Ok(
-    HttpResponse::Ok().json(
-      db::playlists::get_page(db.get_ref(), &query.id, query.offset as i32, query.limit as i32)
-        .await
-        .internal()?,
-    ),
+  let songs = db::playlists::get_page(
+    db.get_ref(),
+    &playlists,
+    &page_query,
+    query.offset as i32,
+    query.limit as i32,
   )
+  .await
+  .internal()?;
+  Ok(HttpResponse::Ok().json(songs.into_iter().map(PlatformSong::from).collect::<Vec<_>>()))
+}
+
+/// A song along with its platform, since a pool can combine playlists of different platforms
+#[derive(serde::Serialize, Debug)]
+pub struct PlatformSong {
+  pub platform: Platform,
+  #[serde(flatten)]
+  pub song: Song,
+}
+
+impl From<Song> for PlatformSong {
+  fn from(song: Song) -> Self {
+    Self {
+      platform: *song.platform(),
+      song,
+    }
+  }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct PlaylistPage {
+  pub songs: Vec<PlatformSong>,
+  /// Cursor of the next page, `None` if this is the last page
+  pub next_cursor: Option<Cursor>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ImportStarted {
+  pub job: Uuid,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct ImportsStarted {
+  pub jobs: Vec<Uuid>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ImportJobRequest {
+  pub id: Uuid,
+}
+
+/// Obtain the status and progress of a playlist import job.
+#[get("/playlist/job")]
+pub async fn get_job(db: web::Data<Database>, Query(query): Query<ImportJobRequest>) -> Result<HttpResponse> {
+  let job = db::import_jobs::get(db.get_ref(), query.id)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown import job"))?;
+  Ok(HttpResponse::Ok().json(job))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PlaylistMetadataRequest {
+  pub platform: Platform,
+  pub id: String,
+}
+
+/// Obtain the metadata of a stored playlist, without its songs.
+#[get("/playlist/metadata")]
+pub async fn get_metadata(
+  db: web::Data<Database>,
+  Query(query): Query<PlaylistMetadataRequest>,
+) -> Result<HttpResponse> {
+  let playlist = db::playlists::get(db.get_ref(), query.platform, &query.id)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown playlist"))?;
+  Ok(HttpResponse::Ok().json(playlist))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PlaylistChangesRequest {
+  pub platform: Platform,
+  pub id: String,
+  pub since: DateTime<Utc>,
+}
+
+/// Obtain the songs which were added to or removed from a playlist by refreshes after `since`.
+#[get("/playlist/changes")]
+pub async fn changes(db: web::Data<Database>, Query(query): Query<PlaylistChangesRequest>) -> Result<HttpResponse> {
+  let changes = db::playlists::changes(db.get_ref(), query.platform, &query.id, query.since)
+    .await
+    .internal()?;
+  Ok(HttpResponse::Ok().json(changes))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use actix_web::{
+    test::{call_and_read_body_json, call_service, init_service, read_body_json, TestRequest},
+    App,
+  };
+  use secrecy::Secret;
+  use structopt::StructOpt;
+  use wiremock::MockServer;
+
+  #[test]
+  fn parse_excluded_songs() {
+    let exclude = |v: &str| {
+      Query::<PlaylistRequest>::from_query(&format!("platform=youtube&id=PL&exclude={v}"))
+        .unwrap()
+        .page_query()
+        .map(|query| query.exclude)
+        .map_err(|e| e.to_string())
+    };
+    assert_eq!(
+      exclude("youtube:a,,soundcloud:1"),
+      Ok(vec![
+        (Platform::Youtube, "a".into()),
+        (Platform::Soundcloud, "1".into())
+      ])
+    );
+    assert_eq!(exclude(""), Ok(vec![]));
+    assert_eq!(
+      exclude("a"),
+      Err("Invalid song `a`, expected `PLATFORM:ID`".to_string())
+    );
+  }
+
+  #[test]
+  fn songs_include_their_platform() {
+    let data = db::songs::SongData::new(Utc::now(), "123".into(), Platform::Soundcloud, "test".into());
+    let song = serde_json::to_value(PlatformSong::from(Song::from_data(1, data))).unwrap();
+    assert_eq!(
+      song,
+      serde_json::json!({ "platform": "soundcloud", "id": "123", "title": "test" })
+    );
+  }
+
+  #[test]
+  fn parse_valid_pools() {
+    assert_eq!(
+      parse_pool("youtube:PL1,soundcloud:123").ok(),
+      Some(vec![
+        (Platform::Youtube, "PL1".to_string()),
+        (Platform::Soundcloud, "123".to_string())
+      ])
+    );
+    // only the first colon separates the platform, ids may contain more
+    assert_eq!(
+      parse_pool("youtube:a:b").ok(),
+      Some(vec![(Platform::Youtube, "a:b".to_string())])
+    );
+    let pool = ["youtube:PL"; MAX_POOL_SIZE].join(",");
+    assert_eq!(parse_pool(&pool).map(|pool| pool.len()).ok(), Some(MAX_POOL_SIZE));
+  }
+
+  #[test]
+  fn parse_invalid_pools() {
+    let error = |v: &str| parse_pool(v).err().map(|e| e.to_string());
+    assert_eq!(
+      error(""),
+      Some("Invalid playlist ``, expected `PLATFORM:ID`".to_string())
+    );
+    assert_eq!(
+      error("youtube:PL1,"),
+      Some("Invalid playlist ``, expected `PLATFORM:ID`".to_string())
+    );
+    assert_eq!(
+      error("youtube"),
+      Some("Invalid playlist `youtube`, expected `PLATFORM:ID`".to_string())
+    );
+    assert_eq!(
+      error("youtube:"),
+      Some("Invalid playlist `youtube:`, expected `PLATFORM:ID`".to_string())
+    );
+    assert!(error("vimeo:123")
+      .unwrap()
+      .starts_with("Invalid playlist `vimeo:123`: unknown variant"));
+    let pool = ["youtube:PL"; MAX_POOL_SIZE + 1].join(",");
+    assert_eq!(
+      error(&pool),
+      Some(format!("At most {MAX_POOL_SIZE} playlists can be combined"))
+    );
+  }
+
+  crate::db_test!(import_responds_with_job, db, playlist, cleanup [r#"DELETE FROM import_jobs WHERE platform_playlist_id = $1"#] {
+    // the mock responds to every request with `404 Not Found`, so the import fails
+    let youtube = MockServer::start().await;
+    let config = Config::from_iter_safe([
+      "api",
+      "--youtube-key=test",
+      "--database-url=test",
+      "--port=0",
+      "--playlist-refresh-interval=1h",
+    ])?;
+    let app = init_service(
+      App::new()
+        .app_data(web::Data::new(config))
+        .app_data(web::Data::new(PlaylistFetches::new()))
+        .app_data(web::Data::new(db.clone()))
+        .app_data(web::Data::new(Youtube::new(youtube.uri(), Secret::new("test".into()))))
+        .service(get)
+        .service(get_job),
+    )
+    .await;
+
+    let request = TestRequest::get()
+      .uri(&format!("/playlist?platform=youtube&id={playlist}"))
+      .to_request();
+    let response = call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let started: serde_json::Value = read_body_json(response).await;
+    let job = started["job"].as_str().unwrap().to_string();
+
+    let mut status = serde_json::Value::Null;
+    for _ in 0..100 {
+      let request = TestRequest::get()
+        .uri(&format!("/playlist/job?id={job}"))
+        .to_request();
+      status = call_and_read_body_json(&app, request).await;
+      if status["status"] != "running" {
+        break;
+      }
+      actix_rt::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(status["status"], "failed");
+    // the error is recorded with its causes
+    let error = status["errors"][0].as_str().unwrap();
+    assert!(error.starts_with("Failed to fetch playlist from YouTube: "), "{error}");
+    assert!(status.get("songs_stored").is_none());
+
+    let stored: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM playlists WHERE platform_playlist_id = $1"#)
+      .bind(&playlist)
+      .fetch_one(db)
+      .await?;
+    assert_eq!(stored, 0);
+  });
 }
@@ -0,0 +1,307 @@
+use crate::{
+  common::{platform::Platform, role::Role},
+  db::{
+    self,
+    channels::{ChannelSettings, QueueMode},
+    queue::{Added, QueueEntry, Upvote},
+    Database,
+  },
+  error::{Error, FailWith},
+  v1::memo,
+};
+use actix_web::{get, http::StatusCode, post, web, web::Json, web::Query, HttpResponse, Result};
+
+#[derive(serde::Deserialize, Debug)]
+pub struct QueueRequest {
+  pub channel: String,
+}
+
+/// Obtain the pending requests of a channel, in the order they will be played.
+#[get("/queue")]
+pub async fn get(db: web::Data<Database>, Query(query): Query<QueueRequest>) -> Result<HttpResponse> {
+  let settings = db::channels::settings(db.get_ref(), &query.channel).await.internal()?;
+  let streak = match settings.subscriber_lane {
+    Some(per_regular) => db::queue::subscriber_streak(db.get_ref(), &query.channel, per_regular.max(1) as usize)
+      .await
+      .internal()?,
+    None => 0,
+  };
+  let entries = db::queue::pending(db.get_ref(), &query.channel).await.internal()?;
+  Ok(HttpResponse::Ok().json(db::queue::order(entries, &settings, streak)))
+}
+
+/// Upvote a pending entry on behalf of `voter`, responding with the upvoted entry
+async fn upvote(
+  db: &Database,
+  settings: &ChannelSettings,
+  entry: QueueEntry,
+  voter: &str,
+) -> std::result::Result<QueueEntry, Error> {
+  if settings.queue_mode != QueueMode::Votes {
+    return Err(Error::from((
+      StatusCode::FORBIDDEN,
+      "Upvotes are disabled on this channel",
+    )));
+  }
+  if entry.requester == voter {
+    return Err(Error::from((StatusCode::CONFLICT, "You can't upvote your own request")));
+  }
+  match db::queue::upvote(db, &entry.channel, entry.id, voter, settings.max_upvotes)
+    .await
+    .internal()?
+  {
+    Upvote::Added => {}
+    Upvote::Repeated => return Err(Error::from((StatusCode::CONFLICT, "You upvoted this request already"))),
+    Upvote::LimitReached => {
+      let max = settings.max_upvotes.unwrap_or_default();
+      return Err(Error::from((
+        StatusCode::CONFLICT,
+        format!(
+          "You can only upvote {max} pending request{} at once",
+          if max == 1 { "" } else { "s" }
+        ),
+      )));
+    }
+  }
+  // the entry may have been played in the meantime, it still received the upvote
+  let id = entry.id;
+  Ok(db::queue::get_pending(db, id).await.internal()?.unwrap_or(QueueEntry {
+    score: entry.score + 1,
+    ..entry
+  }))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct AddRequest {
+  pub channel: String,
+  pub platform: Platform,
+  /// Song ID, as returned from `/memo`
+  pub id: String,
+  pub requester: String,
+  /// The requester's role in the channel, taken as claimed like the role of [`PinRequest`]
+  #[serde(default)]
+  pub role: Role,
+  /// Move the request to the front of the queue using one of the requester's VIP tokens
+  #[serde(default)]
+  pub front: bool,
+}
+
+/// The number of tokens `role` has on the channel, fails with `403 Forbidden` if it has none
+fn token_allowance(settings: &ChannelSettings, role: Role) -> std::result::Result<i32, Error> {
+  match settings.vip_tokens {
+    Some(allowance) if role >= Role::Vip => Ok(allowance),
+    _ => Err(Error::from((
+      StatusCode::FORBIDDEN,
+      "Only VIPs can move requests to the front",
+    ))),
+  }
+}
+
+/// Request a song on a channel.
+///
+/// Responds with the entry, `201 Created` if the song was queued, or `200 OK` if it was queued already
+/// and the request counted as an upvote. Tokens aren't used for requests which count as upvotes.
+#[post("/queue")]
+pub async fn post(db: web::Data<Database>, Json(body): Json<AddRequest>) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  let song = db::songs::get(db.get_ref(), body.platform, &body.id)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown song"))?;
+  memo::check_cooldown(db.get_ref(), Some(&body.channel), &song).await?;
+  let settings = db::channels::settings(db.get_ref(), &body.channel).await.internal()?;
+  let tokens = match body.front {
+    true => Some(token_allowance(&settings, body.role)?),
+    false => None,
+  };
+  match db::queue::add(
+    db.get_ref(),
+    &body.channel,
+    *song.id(),
+    &body.requester,
+    body.role,
+    tokens,
+  )
+  .await
+  .internal()?
+  {
+    Added::Entry(entry) => return Ok(HttpResponse::Created().json(entry)),
+    Added::Queued => {}
+    Added::NoTokens => {
+      let allowance = tokens.unwrap_or_default();
+      return Err(
+        Error::from((
+          StatusCode::CONFLICT,
+          format!(
+            "You have used your {allowance} token{} for this stream",
+            if allowance == 1 { "" } else { "s" }
+          ),
+        ))
+        .into(),
+      );
+    }
+  }
+
+  if settings.queue_mode != QueueMode::Votes {
+    return Err(Error::from((StatusCode::CONFLICT, "This song is already queued")).into());
+  }
+  let entry = db::queue::find_pending(db.get_ref(), &body.channel, *song.id())
+    .await
+    .internal()?
+    // it was played in the meantime
+    .with((StatusCode::CONFLICT, "This song was just played"))?;
+  Ok(HttpResponse::Ok().json(upvote(db.get_ref(), &settings, entry, &body.requester).await?))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct UpvoteRequest {
+  pub entry: i64,
+  pub voter: String,
+}
+
+/// Upvote a pending request.
+#[post("/queue/upvote")]
+pub async fn post_upvote(db: web::Data<Database>, Json(body): Json<UpvoteRequest>) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  let entry = db::queue::get_pending(db.get_ref(), body.entry)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "Unknown request, or it was played already"))?;
+  let settings = db::channels::settings(db.get_ref(), &entry.channel).await.internal()?;
+  Ok(HttpResponse::Ok().json(upvote(db.get_ref(), &settings, entry, &body.voter).await?))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct PinRequest {
+  pub entry: i64,
+  /// `false` to unpin the entry
+  pub pinned: bool,
+  /// The role of whoever pins the entry, only moderators and above can
+  pub role: Role,
+}
+
+/// Pin a pending request to the top of the queue, or unpin it.
+///
+/// The role is taken as claimed, the player calling the API is the trust boundary and has to take it from the chat.
+#[post("/queue/pin")]
+pub async fn post_pin(db: web::Data<Database>, Json(body): Json<PinRequest>) -> Result<HttpResponse> {
+  log::info!("{body:#?}");
+  if body.role < Role::Moderator {
+    return Err(Error::from((StatusCode::FORBIDDEN, "Only moderators can pin requests")).into());
+  }
+  let found = db::queue::pin(db.get_ref(), body.entry, body.pinned).await.internal()?;
+  let entry = db::queue::get_pending(db.get_ref(), body.entry)
+    .await
+    .internal()?
+    .filter(|_| found)
+    .with((StatusCode::NOT_FOUND, "Unknown request, or it was played already"))?;
+  Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Take the next request from the queue of a channel to play it, `204 No Content` if it is empty.
+#[post("/queue/next")]
+pub async fn next(db: web::Data<Database>, Json(body): Json<QueueRequest>) -> Result<HttpResponse> {
+  let settings = db::channels::settings(db.get_ref(), &body.channel).await.internal()?;
+  match db::queue::next(db.get_ref(), &settings).await.internal()? {
+    Some(entry) => Ok(HttpResponse::Ok().json(entry)),
+    None => Ok(HttpResponse::NoContent().finish()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::songs::SongData;
+  use actix_web::{test, App};
+  use chrono::Utc;
+  use serde_json::json;
+
+  crate::db_test!(requests_upvotes_and_pins, db, channel, cleanup [
+    r#"DELETE FROM queue_entries WHERE channel = $1"#,
+    r#"DELETE FROM channel_settings WHERE channel = $1"#,
+    r#"DELETE FROM songs WHERE starts_with(platform_song_id, $1)"#,
+  ] {
+    db::channels::set_settings(
+      db,
+      &ChannelSettings {
+        queue_mode: QueueMode::Votes,
+        max_upvotes: Some(1),
+        ..ChannelSettings::new(&channel)
+      },
+    )
+    .await?;
+    let mut songs = vec![];
+    for i in 0..2 {
+      let data = SongData::new(Utc::now(), format!("{channel}-{i}"), Platform::Youtube, "test".into());
+      songs.push(db::songs::create(db, data).await?);
+    }
+    let app = test::init_service(
+      App::new()
+        .app_data(web::Data::new(db.clone()))
+        .service(post)
+        .service(post_upvote)
+        .service(post_pin),
+    )
+    .await;
+    let request = |song: &db::songs::Song, requester: &str| {
+      test::TestRequest::post()
+        .uri("/queue")
+        .set_json(json!({ "channel": channel, "platform": "youtube", "id": song.song_id(), "requester": requester }))
+        .to_request()
+    };
+
+    let response = test::call_service(&app, request(&songs[0], "a")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let first: serde_json::Value = test::read_body_json(response).await;
+    // requesting a queued song upvotes it
+    let response = test::call_service(&app, request(&songs[0], "b")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let upvoted: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(upvoted["id"], first["id"]);
+    assert_eq!(upvoted["score"], 1);
+    let response = test::call_service(&app, request(&songs[0], "a")).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = test::call_service(&app, request(&songs[0], "b")).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // `b` used their only upvote
+    let response = test::call_service(&app, request(&songs[1], "c")).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let second: serde_json::Value = test::read_body_json(response).await;
+    let upvote = test::TestRequest::post()
+      .uri("/queue/upvote")
+      .set_json(json!({ "entry": second["id"], "voter": "b" }))
+      .to_request();
+    assert_eq!(test::call_service(&app, upvote).await.status(), StatusCode::CONFLICT);
+    // concurrent upvotes can't exceed the limit either
+    let (first_id, second_id) = (first["id"].as_i64().unwrap(), second["id"].as_i64().unwrap());
+    let (a, b) = futures::join!(
+      db::queue::upvote(db, &channel, first_id, "d", Some(1)),
+      db::queue::upvote(db, &channel, second_id, "d", Some(1))
+    );
+    let mut upvotes = [a?, b?];
+    upvotes.sort_by_key(|upvote| *upvote != Upvote::Added);
+    assert_eq!(upvotes, [Upvote::Added, Upvote::LimitReached]);
+
+    // only moderators can pin
+    let pin = |role: &str| {
+      test::TestRequest::post()
+        .uri("/queue/pin")
+        .set_json(json!({ "entry": second_id, "pinned": true, "role": role }))
+        .to_request()
+    };
+    assert_eq!(test::call_service(&app, pin("vip")).await.status(), StatusCode::FORBIDDEN);
+    let response = test::call_service(&app, pin("moderator")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let pinned: serde_json::Value = test::read_body_json(response).await;
+    assert!(pinned["pinned_at"].is_string());
+
+    let votes: i64 = sqlx::query_scalar(
+      r#"SELECT COUNT(*) FROM queue_votes JOIN queue_entries USING (entry_id) WHERE channel = $1"#,
+    )
+    .bind(&channel)
+    .fetch_one(db)
+    .await?;
+    assert_eq!(votes, 2);
+  });
+}
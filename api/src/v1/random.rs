@@ -1,14 +1,20 @@
 use crate::{
-  common::platform::Platform,
+  client::{Soundcloud, Youtube},
+  common::{config::Config, platform::Platform},
   db::{self, Database},
   error::FailWith,
+  playlist_sync::PlaylistFetches,
+  v1::playlist::{self, ImportsStarted, PlatformSong},
 };
-use actix_web::{get, web, web::Query, HttpResponse, Result};
+use actix_web::{get, http::StatusCode, web, web::Query, HttpResponse, Result};
 
 #[derive(serde::Deserialize, Debug)]
 pub struct RandomRequest {
   pub platform: Option<Platform>,
+  /// Leave out songs which are within the cooldown of this channel, or queued on it
   pub channel: Option<String>,
+  /// Comma-separated pool of playlists as `PLATFORM:ID` to draw from, instead of every known song
+  pub playlists: Option<String>,
   #[serde(default = "default_count")]
   pub count: u64,
 }
@@ -17,9 +23,57 @@ fn default_count() -> u64 {
   1
 }
 
+/// Obtain a random song, from the union of a pool of playlists if `playlists` is given.
+///
+/// Playlists of the pool are loaded like in `GET /playlist`.
+/// If `channel` is given, songs which can't be requested on it because of its cooldown,
+/// or because they are queued on it already, are never drawn.
 #[get("/random")]
-pub async fn get(db: web::Data<Database>, Query(query): Query<RandomRequest>) -> Result<HttpResponse> {
+pub async fn get(
+  config: web::Data<Config>,
+  fetches: web::Data<PlaylistFetches>,
+  db: web::Data<Database>,
+  client: web::Data<Youtube>,
+  soundcloud: Option<web::Data<Soundcloud>>,
+  Query(query): Query<RandomRequest>,
+) -> Result<HttpResponse> {
   log::info!("{query:#?}");
+  let exclude = match &query.channel {
+    Some(channel) => {
+      let settings = db::channels::settings(db.get_ref(), channel).await.internal()?;
+      let mut exclude = db::queue::pending_songs(db.get_ref(), channel).await.internal()?;
+      if settings.has_cooldown() {
+        exclude.extend(
+          db::plays::recent(
+            db.get_ref(),
+            channel,
+            settings.cooldown_minutes,
+            settings.cooldown_songs,
+          )
+          .await
+          .internal()?,
+        );
+      }
+      exclude
+    }
+    None => vec![],
+  };
+  if let Some(pool) = &query.playlists {
+    let pool = playlist::parse_pool(pool)?;
+    let (playlists, jobs) = playlist::load_pool(&config, &fetches, &db, &client, &soundcloud, pool, false).await?;
+    if playlists.is_empty() {
+      return Ok(HttpResponse::Accepted().json(ImportsStarted { jobs }));
+    }
+    let song = db::playlists::random(db.get_ref(), &playlists, &exclude)
+      .await
+      .internal()?
+      .with((StatusCode::NOT_FOUND, "The playlists contain no available songs"))?;
+    return Ok(HttpResponse::Ok().json(PlatformSong::from(song)));
+  }
   // fetch and return a random song id
-  Ok(HttpResponse::Ok().json(db::songs::random(db.get_ref()).await.internal()?))
+  let song = db::songs::random(db.get_ref(), &exclude)
+    .await
+    .internal()?
+    .with((StatusCode::NOT_FOUND, "There are no available songs"))?;
+  Ok(HttpResponse::Ok().json(PlatformSong::from(song)))
 }
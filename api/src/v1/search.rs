@@ -1,7 +1,4 @@
-use crate::{
-  common::platform::Platform,
-  db::{self, Database},
-};
+use crate::{common::platform::Platform, db::Database};
 use actix_web::{get, web, web::Query, HttpResponse};
 
 #[derive(serde::Deserialize, Debug)]
@@ -11,7 +8,7 @@ pub struct SearchRequest {
 }
 
 #[get("/search")]
-pub async fn get(db: web::Data<Database>, Query(query): Query<SearchRequest>) -> HttpResponse {
+pub async fn get(_db: web::Data<Database>, Query(query): Query<SearchRequest>) -> HttpResponse {
   log::info!("{query:#?}");
   // TODO: call youtube search api in a reasonable way
 
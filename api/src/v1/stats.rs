@@ -0,0 +1,104 @@
+use crate::{
+  db::{self, stats::SongSort, Database},
+  error::{Error, FailWith},
+};
+use actix_web::{get, web, web::Query, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+
+/// Maximum number of songs or requesters returned at once
+const MAX_STATS_LIMIT: u64 = 100;
+
+fn default_limit() -> u64 {
+  10
+}
+
+fn default_min_plays() -> i64 {
+  1
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct StatsRequest {
+  pub channel: String,
+  /// Only plays which started at or after this time, all of them if `None`
+  pub since: Option<DateTime<Utc>>,
+  /// Only plays which started before this time, all of them if `None`
+  pub until: Option<DateTime<Utc>>,
+  #[serde(default = "default_limit")]
+  pub limit: u64,
+}
+
+/// Fails if the window given by `since` and `until` is empty
+fn validate_window(since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> Result<(), Error> {
+  match (since, until) {
+    (Some(since), Some(until)) if since >= until => Err(Error::from("`since` must be before `until`")),
+    _ => Ok(()),
+  }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct SongStatsRequest {
+  pub channel: String,
+  pub since: Option<DateTime<Utc>>,
+  pub until: Option<DateTime<Utc>>,
+  #[serde(default = "default_limit")]
+  pub limit: u64,
+  #[serde(default)]
+  pub sort: SongSort,
+  /// Only songs played at least this many times, to leave out skip rates of single plays
+  #[serde(default = "default_min_plays")]
+  pub min_plays: i64,
+}
+
+/// Obtain the most played or most skipped songs of a channel.
+#[get("/stats/songs")]
+pub async fn songs(db: web::Data<Database>, Query(query): Query<SongStatsRequest>) -> Result<HttpResponse> {
+  validate_window(query.since, query.until)?;
+  let songs = db::stats::songs(
+    db.get_ref(),
+    &query.channel,
+    query.since,
+    query.until,
+    query.sort,
+    query.min_plays,
+    query.limit.min(MAX_STATS_LIMIT) as i64,
+  )
+  .await
+  .internal()?;
+  Ok(HttpResponse::Ok().json(songs))
+}
+
+/// Obtain the people whose requests were played the most on a channel.
+#[get("/stats/requesters")]
+pub async fn requesters(db: web::Data<Database>, Query(query): Query<StatsRequest>) -> Result<HttpResponse> {
+  validate_window(query.since, query.until)?;
+  let requesters = db::stats::requesters(
+    db.get_ref(),
+    &query.channel,
+    query.since,
+    query.until,
+    query.limit.min(MAX_STATS_LIMIT) as i64,
+  )
+  .await
+  .internal()?;
+  Ok(HttpResponse::Ok().json(requesters))
+}
+
+/// Obtain the number of plays and requests on a channel per hour of the day.
+#[get("/stats/hours")]
+pub async fn hours(db: web::Data<Database>, Query(query): Query<StatsRequest>) -> Result<HttpResponse> {
+  validate_window(query.since, query.until)?;
+  let hours = db::stats::hours(db.get_ref(), &query.channel, query.since, query.until)
+    .await
+    .internal()?;
+  Ok(HttpResponse::Ok().json(hours))
+}
+
+/// Obtain the total plays, play time, skips and new songs of a channel.
+#[get("/stats/summary")]
+pub async fn summary(db: web::Data<Database>, Query(query): Query<StatsRequest>) -> Result<HttpResponse> {
+  validate_window(query.since, query.until)?;
+  let summary = db::stats::summary(db.get_ref(), &query.channel, query.since, query.until)
+    .await
+    .internal()?;
+  Ok(HttpResponse::Ok().json(summary))
+}
@@ -93,19 +93,193 @@ export namespace v1 {
     return platforms.includes(v as any);
   }
 
-  export async function memo(platform: Platform, id: string) {
-    return await post(base + "/memo", null, null, { platform, id });
+  export type Song = { id: string; title: string };
+  export type MemoResult = Song & { fetched: boolean };
+
+  /** Memorize a song, failing with `409` if it is requested on `channel` within its cooldown */
+  export async function memo(platform: Platform, id: string, channel?: string): Promise<Response<MemoResult>> {
+    return await post(base + "/memo", null, null, { platform, id, channel });
   }
 
-  export type Song = { id: string; title: string };
+  export type ImportJob = {
+    id: string;
+    status: "running" | "done" | "failed";
+    pages_fetched: number;
+    /** Only set once the job is `done` */
+    songs_stored?: number;
+    errors: string[];
+  };
+
+  export async function job(id: string): Promise<Response<ImportJob>> {
+    return await get(base + "/playlist/job", { id }, null);
+  }
+
+  /**
+   * Obtain a page of songs from a playlist
+   *
+   * If the playlist is being imported for the first time, this polls the import job
+   * every `pollInterval` until it is finished, then requests the page again.
+   */
   export async function playlist(
     platform: Platform,
     id: string,
     offset: number,
-    limit: number
+    limit: number,
+    pollInterval: number = 1000 /* ms */
   ): Promise<Response<Song[]>> {
+    const response = await get<Song[] | { job: string }>(base + "/playlist", { platform, id, offset, limit }, null);
+    if (!("job" in response.data)) return response as Response<Song[]>;
+
+    let status: ImportJob;
+    do {
+      await new Promise((resolve) => setTimeout(resolve, pollInterval));
+      status = (await job(response.data.job)).data;
+    } while (status.status === "running");
+    if (status.status === "failed") {
+      throw { type: "error", status: 502, message: status.errors[status.errors.length - 1] ?? "Import failed" };
+    }
     return await get(base + "/playlist", { platform, id, offset, limit }, null);
   }
 
+  export type PlaylistMetadata = {
+    platform: Platform;
+    id: string;
+    updated_at: string;
+    title?: string;
+    owner?: string;
+    owner_id?: string;
+    thumbnail_url?: string;
+    item_count?: number;
+  };
+
+  /** Obtain the metadata of a playlist which was imported before */
+  export async function playlistMetadata(platform: Platform, id: string): Promise<Response<PlaylistMetadata>> {
+    return await get(base + "/playlist/metadata", { platform, id }, null);
+  }
+
+  export type FailureReport = { reports: number; availability: "available" | "unavailable" | "unplayable" };
+  /** Report that a song failed to play, `code` is the player's error code */
+  export async function reportFailure(
+    channel: string,
+    platform: Platform,
+    id: string,
+    code: number
+  ): Promise<Response<FailureReport>> {
+    return await post(base + "/playback/failure", null, null, { channel, platform, id, code });
+  }
+
+  export type PlayOutcome = "finished" | "skipped" | "error";
+  export type Play = {
+    id: number;
+    channel: string;
+    song: Song;
+    requester: string | null;
+    started_at: string;
+    ended_at: string | null;
+    outcome: PlayOutcome | null;
+  };
+
+  /** Report that a song started playing on `channel` */
+  export async function startPlay(
+    channel: string,
+    platform: Platform,
+    id: string,
+    requester: string | null = null
+  ): Promise<Response<Play>> {
+    return await post(base + "/playback/start", null, null, { channel, platform, id, requester });
+  }
+
+  /** Report how a play ended */
+  export async function endPlay(play: number, outcome: PlayOutcome): Promise<Response<Play>> {
+    return await post(base + "/playback/end", null, null, { play, outcome });
+  }
+
+  /** Obtain the songs played on `channel`, most recent first, before the play with id `before` */
+  export async function history(channel: string, before?: number, limit: number = 20): Promise<Response<Play[]>> {
+    const params = before === undefined ? { channel, limit } : { channel, before, limit };
+    return await get(base + "/playback/history", params, null);
+  }
+
+  export type Role = "user" | "subscriber" | "vip" | "moderator" | "broadcaster";
+
+  export type QueueEntry = {
+    id: number;
+    channel: string;
+    platform: Platform;
+    song: Song;
+    requester: string;
+    role: Role;
+    requested_at: string;
+    score: number;
+    pinned_at: string | null;
+    front_at: string | null;
+  };
+
+  /** Obtain the pending requests of `channel`, in the order they will be played */
+  export async function queue(channel: string): Promise<Response<QueueEntry[]>> {
+    return await get(base + "/queue", { channel }, null);
+  }
+
+  /**
+   * Request a song on `channel`, `id` is the song id returned from `memo`
+   *
+   * Fails with `409` if the song is queued already, unless the channel orders its queue by votes,
+   * in which case the request upvotes the queued song.
+   * With `front`, the request is moved to the front of the queue using one of the requester's VIP tokens.
+   */
+  export async function enqueue(
+    channel: string,
+    platform: Platform,
+    id: string,
+    requester: string,
+    role: Role = "user",
+    front: boolean = false
+  ): Promise<Response<QueueEntry>> {
+    return await post(base + "/queue", null, null, { channel, platform, id, requester, role, front });
+  }
+
+  /** Record that a stream started on `channel`, handing out VIP tokens again */
+  export async function startStream(channel: string): Promise<Response<{ channel: string; started_at: string }>> {
+    return await post(base + "/channel/stream", null, null, { channel });
+  }
+
+  /** Upvote a pending request */
+  export async function upvote(entry: number, voter: string): Promise<Response<QueueEntry>> {
+    return await post(base + "/queue/upvote", null, null, { entry, voter });
+  }
+
+  /** Pin a pending request to the top of the queue, or unpin it. Fails with `403` unless `role` is moderator or above */
+  export async function pin(entry: number, role: Role, pinned: boolean = true): Promise<Response<QueueEntry>> {
+    return await post(base + "/queue/pin", null, null, { entry, pinned, role });
+  }
+
+  /** Take the next request from the queue of `channel`, without `data` if it is empty */
+  export async function dequeue(channel: string): Promise<Response<QueueEntry | void>> {
+    return await post(base + "/queue/next", null, null, { channel });
+  }
+
+  export type SkipVoteStatus = { play: number; votes: number; required: number; passed: boolean };
+
+  /**
+   * Vote to skip the song playing on `channel`
+   *
+   * `chatters` is the number of people who chatted recently, for channels which skip by percentage.
+   */
+  export async function voteSkip(channel: string, voter: string, chatters?: number): Promise<Response<SkipVoteStatus>> {
+    return await post(base + "/playback/skip-vote", null, null, { channel, voter, chatters });
+  }
+
+  /** An event sent to the players of a channel, `skip` once a vote to skip the `play` passed */
+  export type ChannelEvent = { type: "skip"; play: number };
+
+  /** Subscribe to the events of `channel`, the returned source reconnects by itself until it is closed */
+  export function events(channel: string, onEvent: (event: ChannelEvent) => void): EventSource {
+    const url = new URL(base + "/channel/events");
+    url.search = "?" + new URLSearchParams({ channel }).toString();
+    const source = new EventSource(url.toString());
+    source.onmessage = (message) => onEvent(JSON.parse(message.data));
+    return source;
+  }
+
   //export async function random() {}
 }
@@ -2,31 +2,77 @@ import "./style.css";
 import "./util";
 import * as api from "./api";
 import { Player } from "./player";
-import { Playlist } from "./playlist";
+import { Playlist, SongItem } from "./playlist";
 import { Channel, Role } from "./twitch";
 import { CommandRegistry } from "./command";
 
+const CHANNEL = "moscowwbish";
+
 // TODO: support multiple players dynamically based on playlist specified platform
 const youtube = new Player();
-const playlist = new Playlist();
+const playlist = new Playlist(CHANNEL);
 
-youtube.on("videoEnd", () => {
-  console.log("go next (end)");
-  const next = playlist.next();
+/** The id of the current play as recorded by the API, `null` if nothing is playing or it couldn't be recorded */
+let currentPlay: Promise<number | null> = Promise.resolve(null);
+
+/** People who chatted within the last 10 minutes count towards percentage vote-skip thresholds */
+const CHATTER_ACTIVITY_WINDOW = 10 * 60 * 1000; /* ms */
+/** When each person last chatted, and their role in the channel at the time */
+const chatters = new Map<string, { lastSeen: number; role: Role }>();
+function activeChatters() {
+  const since = Date.now() - CHATTER_ACTIVITY_WINDOW;
+  for (const [user, { lastSeen }] of chatters) {
+    if (lastSeen < since) chatters.delete(user);
+  }
+  return chatters.size;
+}
+
+const ROLES: Record<Role, api.v1.Role> = {
+  [Role.User]: "user",
+  [Role.Subscriber]: "subscriber",
+  [Role.VIP]: "vip",
+  [Role.Moderator]: "moderator",
+  [Role.Broadcaster]: "broadcaster",
+};
+/** The role of someone who chatted recently, for the priority of their requests */
+function roleOf(user: string): api.v1.Role {
+  return ROLES[chatters.get(user)?.role ?? Role.User];
+}
+
+/**
+ * Play `next`, or stop if there is nothing to play.
+ *
+ * If a song was playing, `outcome` is reported as the way it ended.
+ */
+function play(next: SongItem | null, outcome: api.v1.PlayOutcome = "skipped") {
+  const ended = currentPlay;
+  ended.then((id) => (id !== null ? api.v1.endPlay(id, outcome) : null)).catch((e) => console.error(e));
   if (!next) {
+    currentPlay = Promise.resolve(null);
     youtube.stop();
   } else {
+    currentPlay = api.v1
+      .startPlay(CHANNEL, next.platform, next.id, next.requester ?? null)
+      .then(({ data }) => data.id)
+      .catch((e) => {
+        console.error(e);
+        return null;
+      });
     youtube.play(next.id);
   }
+}
+
+youtube.on("videoEnd", async () => {
+  console.log("go next (end)");
+  play(await playlist.next(), "finished");
 });
-youtube.on("error", () => {
-  console.log("go next (error)");
-  const next = playlist.next();
-  if (!next) {
-    youtube.stop();
-  } else {
-    youtube.play(next.id);
+youtube.on("error", async (code) => {
+  console.log("go next (error)", code);
+  const failed = playlist.playing;
+  if (failed) {
+    api.v1.reportFailure(CHANNEL, failed.platform, failed.id, code).catch((e) => console.error(e));
   }
+  play(await playlist.next(), "error");
 });
 
 function getListId(id: string) {
@@ -51,18 +97,30 @@ function getSongId(id: string) {
 
 const registry = new CommandRegistry("$sr");
 
+/** Request a song, moving it to the front of the queue with one of the requester's VIP tokens if `front` */
+async function request(user: string, rawId: string, front: boolean = false) {
+  const id = getSongId(rawId);
+  console.log("parsed id", id);
+  if (!id) return;
+
+  try {
+    await playlist.add("song", "youtube", id, undefined, user, roleOf(user), front);
+  } catch (e) {
+    // e.g. the song is already queued, or was played too recently
+    console.warn("rejected request", id, e);
+    return;
+  }
+  if (!youtube.playing()) {
+    play(await playlist.next());
+  }
+}
 registry.add("default", {
   allow: Role.Broadcaster,
-  async run(_user: string, rawId: string) {
-    const id = getSongId(rawId);
-    console.log("parsed id", id);
-    if (!id) return;
-
-    await playlist.add("song", "youtube", id);
-    if (!youtube.playing()) {
-      youtube.play(playlist.next()!.id);
-    }
-  },
+  run: async (user, rawId) => request(user, rawId),
+});
+registry.add("front", {
+  allow: Role.VIP,
+  run: async (user, rawId) => request(user, rawId, true),
 });
 
 async function list(platform: api.v1.Platform, rawId: string, rawCount: string) {
@@ -76,7 +134,7 @@ async function list(platform: api.v1.Platform, rawId: string, rawCount: string)
 
   await playlist.add("list", platform, id, count);
   if (!youtube.playing()) {
-    youtube.play(playlist.next()!.id);
+    play(await playlist.next());
   }
 }
 registry.add("list", {
@@ -91,26 +149,65 @@ registry.add("list:youtube", {
 registry.add("skip", {
   allow: Role.Moderator,
   run: async (_user) => {
-    const next = playlist.skip();
+    const next = await playlist.skip();
     if (!next) return;
-    youtube.play(next.id);
+    play(next, "skipped");
+  },
+});
+registry.add("voteskip", {
+  allow: Role.User,
+  run: async (user) => {
+    // the API skips once enough people voted, and tells every player through a `skip` event
+    api.v1.voteSkip(CHANNEL, user, activeChatters()).catch((e) => console.warn("rejected vote", e));
+  },
+});
+/** The pending request of the song with the given id or URL, `null` if it isn't queued */
+async function findRequest(rawId: string): Promise<api.v1.QueueEntry | null> {
+  const id = getSongId(rawId);
+  if (!id) return null;
+  const { data: entries } = await api.v1.queue(CHANNEL);
+  return entries.find((entry) => entry.platform === "youtube" && entry.song.id === id) ?? null;
+}
+registry.add("upvote", {
+  allow: Role.User,
+  run: async (user, rawId) => {
+    const entry = await findRequest(rawId);
+    if (!entry) return;
+    api.v1.upvote(entry.id, user).catch((e) => console.warn("rejected upvote", e));
+  },
+});
+registry.add("pin", {
+  allow: Role.Moderator,
+  run: async (user, rawId) => {
+    const entry = await findRequest(rawId);
+    if (!entry) return;
+    api.v1.pin(entry.id, roleOf(user)).catch((e) => console.error(e));
   },
 });
 registry.add("skip:list", {
   allow: Role.Moderator,
   run: async (_user) => {
-    const next = playlist.skip(true);
-    if (!next) {
-      youtube.stop();
-    } else {
-      youtube.play(next.id);
-    }
+    play(await playlist.skip(true), "skipped");
   },
 });
 
-const channel = new Channel("moscowwbish");
+// hands out VIP tokens again
+api.v1.startStream(CHANNEL).catch((e) => console.error(e));
+
+const channel = new Channel(CHANNEL);
 channel.onopen = () => console.log("connected");
-channel.onmessage = (m) => registry.handle(m);
+channel.onmessage = (m) => {
+  chatters.set(m.user, { lastSeen: Date.now(), role: m.role() });
+  registry.handle(m);
+};
+
+api.v1.events(CHANNEL, async (event) => {
+  if (event.type !== "skip" || (await currentPlay) !== event.play) return;
+  console.log("go next (vote)");
+  // the API has ended the play already
+  currentPlay = Promise.resolve(null);
+  play(await playlist.next());
+});
 
 // @ts-ignore
 window._app = { youtube, playlist, registry, channel };
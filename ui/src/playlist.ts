@@ -8,60 +8,90 @@ type ListItem = {
   cursor: number;
   count: number;
 };
-type SongItem = {
+export type SongItem = {
   type: "song";
   platform: api.v1.Platform;
   id: string;
+  /** Who requested the song, if it wasn't taken from a playlist */
+  requester?: string;
 };
-type Item = ListItem | SongItem;
 
+/**
+ * Requested songs are queued by the API, and played before the songs of lists.
+ * Lists are only kept here, and played in the order they were added.
+ */
 export class Playlist {
-  items: Item[] = [];
-  current: Item | null = null;
+  /** @param channel The channel songs are requested on */
+  constructor(readonly channel: string) {}
 
-  next(): SongItem | null {
+  items: ListItem[] = [];
+  current: ListItem | null = null;
+  /** The song which was last returned from `next` */
+  playing: SongItem | null = null;
+
+  async next(): Promise<SongItem | null> {
+    this.playing = (await this.dequeue()) ?? this.advance();
+    return this.playing;
+  }
+
+  /** Take the next requested song from the API's queue */
+  private async dequeue(): Promise<SongItem | null> {
+    try {
+      const response = await api.v1.dequeue(this.channel);
+      if (!("data" in response)) return null;
+      const { platform, song, requester } = response.data;
+      return { type: "song", platform, id: song.id, requester };
+    } catch (e) {
+      // keep playing the lists while the API is unreachable
+      console.error(e);
+      return null;
+    }
+  }
+
+  private advance(): SongItem | null {
     this.current ??= this.items.shift() ?? null;
     const current = this.current;
     if (!current) return null;
-    if (current.type === "song") {
-      this.current = null;
-      return current;
-    } else {
-      const id = current.songs[current.cursor]?.id ?? null;
-      const item = id ? { type: "song" as const, platform: current.platform, id } : null;
+    const id = current.songs[current.cursor]?.id ?? null;
+    const item = id ? { type: "song" as const, platform: current.platform, id } : null;
 
-      current.cursor += 1;
-      const remainingLocal = current.songs.length - current.cursor;
-      const remainingRemote = current.count - current.cursor;
+    current.cursor += 1;
+    const remainingLocal = current.songs.length - current.cursor;
+    const remainingRemote = current.count - current.cursor;
 
-      if (remainingRemote > 0) {
-        if (remainingLocal <= 1) {
-          const count = remainingRemote >= 10 ? 10 : remainingRemote;
-          api.v1.playlist(current.platform, current.id, current.songs.length, count).then(({ data }) => {
-            current.songs.push(...data);
-          });
-        }
-      } else {
-        this.current = null;
+    if (remainingRemote > 0) {
+      if (remainingLocal <= 1) {
+        const count = remainingRemote >= 10 ? 10 : remainingRemote;
+        api.v1.playlist(current.platform, current.id, current.songs.length, count).then(({ data }) => {
+          current.songs.push(...data);
+        });
       }
-      return item;
+    } else {
+      this.current = null;
     }
+    return item;
   }
 
-  skip(full: boolean = false): SongItem | null {
-    if (!full) return this.next();
-    else {
-      if (this.current && this.current.type === "list") {
-        this.current = null;
-      }
-      return this.next();
-    }
+  /** Skip the playing song, or with `full`, the rest of the current list too */
+  async skip(full: boolean = false): Promise<SongItem | null> {
+    if (full) this.current = null;
+    return await this.next();
   }
 
-  async add(type: "list" | "song", platform: api.v1.Platform, id: string, count: number = 10): Promise<void> {
+  async add(
+    type: "list" | "song",
+    platform: api.v1.Platform,
+    id: string,
+    count: number = 10,
+    requester?: string,
+    role: api.v1.Role = "user",
+    front: boolean = false
+  ): Promise<void> {
     if (type === "song") {
-      await api.v1.memo(platform, id);
-      this.items.push({ type, platform, id });
+      // rejects songs within the channel's cooldown, and songs which are queued already, or upvotes them
+      const { data: song } = await api.v1.memo(platform, id, this.channel);
+      // songs added without a requester count as the broadcaster's
+      await api.v1.enqueue(this.channel, platform, song.id, requester ?? this.channel, role, front);
     } else {
       // get the backend to pre-fetch the playlist + fetch first few songs
       const response = await api.v1.playlist(platform, id, 0, count! < 10 ? count! : 10);